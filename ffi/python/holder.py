@@ -1,7 +1,7 @@
 #!/usr/bin/env python3
 import sys
 sys.path.append("..")
-from libs.libkeri_ecosystem import Controller, SignatureState, SignedAttestationDatum 
+from keri_ecosystem import Controller, SignatureState, SignedAttestationDatum 
 import tempfile
 import base64
 import json
@@ -35,8 +35,10 @@ issuer = signed_data.get_issuer()
 print("Asking did:keri:" + issuer + " for KEL:" )
 verification = verifier.verify_vc(signed_data)
 
-if verification:
+if verification == SignatureState.Ok:
     print("VC is signed by " + issuer + "\n")
+elif verification == SignatureState.Revoked:
+    print("VC was revoked by " + issuer + "\n")
 else:
     print("Signature is wrong. VC is not signed by " + issuer + "\n")
 
@@ -0,0 +1,13 @@
+"""Bindings of keri_ecosystem which raise exception class of the error kind,
+see `keri_errors`. Import them from here instead of `libkeri_ecosystem`.
+"""
+from keri_errors import *
+from libs import libkeri_ecosystem as _bindings
+
+_classes = (_bindings.Controller, _bindings.SignedAttestationDatum,
+            _bindings.VcVerification)
+
+Controller = typed(_bindings.Controller, _classes)
+SignedAttestationDatum = typed(_bindings.SignedAttestationDatum, _classes)
+VcVerification = typed(_bindings.VcVerification, _classes)
+SignatureState = _bindings.SignatureState
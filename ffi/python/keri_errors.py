@@ -0,0 +1,121 @@
+"""Exception classes of keri_ecosystem errors.
+
+Bindings raise plain Exception with message of the rust error, which
+starts with the error kind, like "UnknownIdentifier: ...". `typed` wraps
+classes of the bindings, so their methods raise exception class of the
+error kind instead. `keri_ecosystem` module exports the wrapped classes and
+it's the only supported entry point, classes imported from
+`libkeri_ecosystem` directly raise plain Exception.
+"""
+import functools
+
+
+class KeriEcosystemError(Exception):
+    """Base class of errors raised by keri_ecosystem."""
+
+
+class UnknownIdentifier(KeriEcosystemError):
+    """Identifier isn't managed by the controller."""
+
+
+class KelNotFound(KeriEcosystemError):
+    """There is no KEL of identifier and it can't be fetched."""
+
+
+class AddressNotFound(KeriEcosystemError):
+    """Address of identifier isn't known."""
+
+
+class InvalidSignature(KeriEcosystemError):
+    """Signature doesn't match the keys or can't be verified at all."""
+
+
+class TelStateConflict(KeriEcosystemError):
+    """TEL event doesn't fit the current state of the TEL."""
+
+
+class ProtocolError(KeriEcosystemError):
+    """Received message or data has unexpected format."""
+
+
+class StorageError(KeriEcosystemError):
+    """Database or file store can't be read or written."""
+
+
+class Decommissioned(KeriEcosystemError):
+    """Identifier is decommissioned."""
+
+
+class Duplicitous(KeriEcosystemError):
+    """Identifier is duplicitous."""
+
+
+class KeriError(KeriEcosystemError):
+    """Error of the underlying KERI library."""
+
+
+class EncodingError(KeriEcosystemError):
+    """Data isn't valid utf8 or base64."""
+
+
+class CommunicationError(KeriEcosystemError):
+    """Other identifier can't be reached or files can't be accessed."""
+
+
+ERROR_KINDS = {
+    cls.__name__: cls
+    for cls in [
+        UnknownIdentifier,
+        KelNotFound,
+        AddressNotFound,
+        InvalidSignature,
+        TelStateConflict,
+        ProtocolError,
+        StorageError,
+        Decommissioned,
+        Duplicitous,
+        KeriError,
+        EncodingError,
+        CommunicationError,
+    ]
+}
+
+
+def from_exception(exc):
+    """Returns exception of the error kind found in message of `exc`."""
+    message = str(exc.args[0]) if exc.args else str(exc)
+    kind, _, description = message.partition(": ")
+    cls = ERROR_KINDS.get(kind, KeriEcosystemError)
+    if cls is KeriEcosystemError:
+        description = message
+    return cls(description)
+
+
+class typed:
+    """Wraps class or object of the bindings, like Controller. Errors raised
+    by its methods are translated with `from_exception`. Returned objects of
+    `classes`, like controller made by `Controller.new`, are wrapped too."""
+
+    def __init__(self, inner, classes=()):
+        self._inner = inner
+        self._classes = classes
+
+    def __getattr__(self, name):
+        attr = getattr(self._inner, name)
+        if not callable(attr):
+            return attr
+
+        @functools.wraps(attr)
+        def call(*args, **kwargs):
+            args = [arg._inner if isinstance(arg, typed) else arg for arg in args]
+            try:
+                result = attr(*args, **kwargs)
+            except KeriEcosystemError:
+                raise
+            except Exception as exc:
+                raise from_exception(exc) from exc
+            if isinstance(result, self._classes):
+                return typed(result, self._classes)
+            return result
+
+        return call
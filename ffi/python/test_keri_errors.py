@@ -0,0 +1,60 @@
+#!/usr/bin/env python3
+"""Tests of error translation done by wrapped classes of `keri_ecosystem`.
+Bindings are replaced by fake classes, so the library doesn't need to be
+built. Run with `python3 -m unittest test_keri_errors` from this folder.
+"""
+import unittest
+
+from keri_errors import *
+
+
+class FakeController:
+    """Raises errors the way bindings do."""
+
+    name = "fake"
+
+    @staticmethod
+    def new():
+        return FakeController()
+
+    def get_kerl(self, prefix):
+        raise Exception("UnknownIdentifier: " + prefix)
+
+    def verify(self, other):
+        if not isinstance(other, FakeController):
+            raise Exception("bad argument")
+        raise Exception("InvalidSignature: wrong keys")
+
+    def fail(self):
+        raise Exception("Some unexpected error")
+
+
+Controller = typed(FakeController, (FakeController,))
+
+
+class TypedTest(unittest.TestCase):
+    def test_error_kind(self):
+        controller = Controller.new()
+        with self.assertRaises(UnknownIdentifier) as raised:
+            controller.get_kerl("Dprefix")
+        self.assertEqual(str(raised.exception), "Dprefix")
+
+    def test_base_class(self):
+        controller = Controller.new()
+        with self.assertRaises(KeriEcosystemError) as raised:
+            controller.fail()
+        self.assertIs(type(raised.exception), KeriEcosystemError)
+        self.assertEqual(str(raised.exception), "Some unexpected error")
+
+    def test_wrapped_arguments(self):
+        controller = Controller.new()
+        self.assertIsInstance(controller, typed)
+        with self.assertRaises(InvalidSignature):
+            controller.verify(Controller.new())
+
+    def test_attributes(self):
+        self.assertEqual(Controller.new().name, "fake")
+
+
+if __name__ == "__main__":
+    unittest.main()
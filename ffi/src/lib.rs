@@ -1,3 +1,5 @@
 #![allow(unused)]
+// Deprecated methods are still exposed for existing callers.
+#![allow(deprecated)]
 
 include!(concat!(env!("OUT_DIR"), "/glue.rs"));
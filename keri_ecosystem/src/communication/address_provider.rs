@@ -29,13 +29,14 @@ impl AddressProvider {
 
     pub fn get_address(&self, id: &str) -> Result<Option<String>, Error> {
         let file = OpenOptions::new().read(true).open(&self.db_path)?;
-        let mut lines = io::BufReader::new(file).lines();
+        let lines = io::BufReader::new(file).lines();
 
-        Ok(lines
-            .find(|line| line.as_ref().unwrap().starts_with(id))
-            .map(|element| match element {
-                Ok(el) => el.split(" ").collect::<Vec<_>>()[1].to_owned(),
-                Err(_) => "".into(),
-            }))
+        for line in lines {
+            let line = line?;
+            if line.starts_with(id) {
+                return Ok(line.split(" ").nth(1).map(|address| address.to_owned()));
+            }
+        }
+        Ok(None)
     }
 }
@@ -5,10 +5,11 @@ use crate::{
 use base64::URL_SAFE;
 use keri::{
     event::event_data::EventData,
-    event::sections::seal::Seal,
+    event::sections::seal::{EventSeal, Seal},
     event_message::parse::{signed_event_stream, Deserialized},
     prefix::Prefix,
 };
+use log::warn;
 use std::{
     io::{Read, Write},
     net::{TcpListener, TcpStream},
@@ -16,6 +17,10 @@ use std::{
     time::Duration,
 };
 
+/// Longest framed message which is read, so broken length doesn't make
+/// reader allocate any amount of memory.
+const MAX_FRAME_LEN: u64 = 16 * 1024 * 1024;
+
 pub struct TCPCommunication {
     address: String,
     address_provider: AddressProvider,
@@ -41,9 +46,8 @@ impl TCPCommunication {
     pub fn send(message: &[u8], address: &str, to_who: &str, entity: &Entity) -> Result<(), Error> {
         let mut msg = [to_who, " "].join("").as_bytes().to_vec();
         msg.extend(message);
-        let mut stream = TcpStream::connect(address.clone())?;
-        stream.set_read_timeout(Some(Duration::from_millis(500)))?;
-        stream.write_all(&msg)?;
+        let mut stream = TCPCommunication::connect(address)?;
+        stream.write_all(&msg).map_err(Error::CommunicationError)?;
 
         let msg = TCPCommunication::read_all(&stream)?;
 
@@ -54,47 +58,105 @@ impl TCPCommunication {
             let mut msg = [to_who, " "].join("").as_bytes().to_vec();
             msg.extend(res);
 
-            stream.write_all(&msg)?;
+            stream.write_all(&msg).map_err(Error::CommunicationError)?;
         }
         Ok(())
     }
 
+    /// Sends events to witness. Returns its response, which consists of
+    /// receipts of events it accepted.
+    pub fn publish(message: &[u8], address: &str, witness: &str) -> Result<Vec<u8>, Error> {
+        let mut msg = [witness, " "].join("").as_bytes().to_vec();
+        msg.extend(message);
+        let mut stream = TCPCommunication::connect(address)?;
+        stream.write_all(&msg).map_err(Error::CommunicationError)?;
+
+        TCPCommunication::read_all(&stream)
+    }
+
+    /// Asks for TEL of vc. Returns json of `TelReply`, which is sent
+    /// framed, see `frame`.
     pub fn ask_for_tel(vc: &[u8], address: &str) -> Result<Vec<u8>, Error> {
         let mut msg = "tel ".as_bytes().to_vec();
         msg.extend(vc);
-        let mut stream = TcpStream::connect(address.clone())?;
-        stream.write_all(&msg)?;
-        // println!("Sent:\n{}\n", String::from_utf8(msg).unwrap());
-        let mut buf = [0; 2048];
-        let n = stream.read(&mut buf)?;
-        let m = buf[..n].to_vec().clone();
-
-        Ok(m)
+        let mut stream = TCPCommunication::connect(address)?;
+        stream.write_all(&msg).map_err(Error::CommunicationError)?;
+
+        TCPCommunication::read_framed(&stream)
+    }
+
+    /// Prefixes message with its length, as 8 bytes big endian, so reader
+    /// knows where it ends regardless of how it's split into packets.
+    pub fn frame(msg: &[u8]) -> Vec<u8> {
+        let mut framed = (msg.len() as u64).to_be_bytes().to_vec();
+        framed.extend(msg);
+        framed
+    }
+
+    /// Reads message sent with `frame`.
+    fn read_framed(mut stream: &TcpStream) -> Result<Vec<u8>, Error> {
+        let mut len = [0u8; 8];
+        stream
+            .read_exact(&mut len)
+            .map_err(Error::CommunicationError)?;
+        let len = u64::from_be_bytes(len);
+        if len > MAX_FRAME_LEN {
+            return Err(Error::ProtocolError(format!(
+                "message of {} bytes is too long",
+                len
+            )));
+        }
+        let mut msg = vec![0u8; len as usize];
+        stream
+            .read_exact(&mut msg)
+            .map_err(Error::CommunicationError)?;
+        Ok(msg)
     }
 
     pub fn run(address: String, controller: Arc<Mutex<Controller>>) -> Result<(), Error> {
-        let listener = TcpListener::bind(&address)?;
+        let listener = TcpListener::bind(&address).map_err(Error::CommunicationError)?;
         // println!("Listening on: {}", address);
 
         loop {
-            let (mut socket, _adr) = listener.accept()?;
-            &socket.set_read_timeout(Some(Duration::from_millis(200)))?;
-            &socket.set_write_timeout(Some(Duration::from_millis(200)))?;
+            let (mut socket, _adr) = listener.accept().map_err(Error::CommunicationError)?;
+            socket
+                .set_read_timeout(Some(Duration::from_millis(200)))
+                .map_err(Error::CommunicationError)?;
+            socket
+                .set_write_timeout(Some(Duration::from_millis(200)))
+                .map_err(Error::CommunicationError)?;
 
             let c = Arc::clone(&controller);
 
             loop {
-                let msg: Vec<u8> = TCPCommunication::read_all(&socket)?;
+                // Broken connection ends only this conversation, not the
+                // server.
+                let msg: Vec<u8> = match TCPCommunication::read_all(&socket) {
+                    Ok(msg) => msg,
+                    Err(_) => break,
+                };
 
                 if msg.len() == 0 {
                     break;
                 }
                 let msg = &msg;
                 if msg.len() > 0 {
-                    let k = c.lock().unwrap();
-                    let receipt = k.parse_message(msg).expect("failed while event processing");
+                    let k = c
+                        .lock()
+                        .map_err(|_| Error::Generic("controller is poisoned".into()))?;
+                    // Message which can't be processed is reported and
+                    // left without response.
+                    let receipt = match k.parse_message(msg) {
+                        Ok(receipt) => receipt,
+                        Err(e) => {
+                            warn!("Can't process message: {}", e);
+                            continue;
+                        }
+                    };
 
-                    socket.write_all(&receipt)?;
+                    if socket.write_all(&receipt).is_err() {
+                        break;
+                    }
                 }
             }
         }
@@ -102,7 +164,10 @@ impl TCPCommunication {
 
     pub fn format_event_stream(msg: &[u8], incoming: bool) -> String {
         let mut out = String::new();
-        let s = signed_event_stream(msg).unwrap().1;
+        let s = match signed_event_stream(msg) {
+            Ok((_rest, events)) => events,
+            Err(e) => return format!("Can't parse event stream: {}", e),
+        };
         for ev in s {
             match ev {
                 Deserialized::Event(e) => {
@@ -139,31 +204,101 @@ impl TCPCommunication {
                                 .join(", "),
                         ]
                         .join(" "),
+                        EventData::Dip(dip) => [
+                            "delegated inception, delegator:",
+                            &dip.seal.prefix.to_str(),
+                            "current key:",
+                            &dip.inception_data
+                                .key_config
+                                .public_keys
+                                .iter()
+                                .map(|k| k.to_str())
+                                .collect::<Vec<_>>()
+                                .join(", "),
+                        ]
+                        .join(" "),
+                        EventData::Drt(drt) => [
+                            "delegated rotation, current key:",
+                            &drt.rotation_data
+                                .key_config
+                                .public_keys
+                                .iter()
+                                .map(|k| k.to_str())
+                                .collect::<Vec<_>>()
+                                .join(", "),
+                        ]
+                        .join(" "),
                         EventData::Ixn(ixn) => {
-                            let digest = match ixn.data[0] {
-                                Seal::Event(ref es) => {
-                                    base64::encode_config(&es.event_digest.digest, URL_SAFE)
-                                }
-                                Seal::Location(_) => "".into(),
-                                Seal::Digest(ref d) => {
-                                    base64::encode_config(&d.dig.digest, URL_SAFE)
-                                }
-                                Seal::Root(_) => "".into(),
-                            };
-                            ["interaction,".to_string(), "digest:".to_string(), digest].join(" ")
+                            let seals = ixn
+                                .data
+                                .iter()
+                                .map(|seal| match seal {
+                                    Seal::Event(es) => format!(
+                                        "event of {} sn {}: {}",
+                                        es.prefix.to_str(),
+                                        es.sn,
+                                        base64::encode_config(&es.event_digest.digest, URL_SAFE)
+                                    ),
+                                    Seal::Location(ls) => {
+                                        format!("location of {} sn {}", ls.prefix.to_str(), ls.sn)
+                                    }
+                                    Seal::Digest(d) => format!(
+                                        "digest: {}",
+                                        base64::encode_config(&d.dig.digest, URL_SAFE)
+                                    ),
+                                    Seal::Root(_) => "root".into(),
+                                })
+                                .collect::<Vec<_>>()
+                                .join(", ");
+                            ["interaction,".to_string(), seals].join(" ")
                         }
                         _ => "".to_string(),
                     };
                     out.push_str(&format!("\tsn: {}, type: {}\n", e.event.event.event.sn, t));
                 }
-                Deserialized::Vrc(_) => {
-                    // println!("\ttype: {}", "receipt");
+                Deserialized::Vrc(vrc) => {
+                    if let EventData::Vrc(rct) = &vrc.event_message.event.event_data {
+                        out.push_str(&Self::format_validator_receipt(
+                            vrc.event_message.event.sn,
+                            &rct.validator_seal,
+                        ));
+                    }
+                }
+                Deserialized::Rct(rct) => {
+                    out.push_str(&format!(
+                        "\tsn: {}, type: witness receipt, witnesses: {}\n",
+                        rct.body.event.sn,
+                        rct.couplets
+                            .iter()
+                            .map(|(witness, _)| witness.to_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ));
                 }
-                Deserialized::Rct(_) => {}
             }
         }
         out
     }
+    /// Formats receipt of event of given sn, made by validator with keys
+    /// from the sealed establishment event.
+    pub fn format_validator_receipt(sn: u64, seal: &EventSeal) -> String {
+        format!(
+            "\tsn: {}, type: receipt, validator: {} at sn {}\n",
+            sn,
+            seal.prefix.to_str(),
+            seal.sn
+        )
+    }
+
+    /// Connects to address, with timeout of waiting for reply.
+    fn connect(address: &str) -> Result<TcpStream, Error> {
+        let stream = TcpStream::connect(address).map_err(Error::CommunicationError)?;
+        stream
+            .set_read_timeout(Some(Duration::from_millis(500)))
+            .map_err(Error::CommunicationError)?;
+        Ok(stream)
+    }
+
     fn read_all(mut stream: &TcpStream) -> Result<Vec<u8>, Error> {
         let mut buf = vec![0; 2048];
         let mut msg: Vec<u8> = vec![];
@@ -172,7 +307,7 @@ impl TCPCommunication {
                 msg.extend(&buf[..m].to_vec());
                 let mut m = m;
                 while m == buf.len() {
-                    m = stream.read(&mut buf)?;
+                    m = stream.read(&mut buf).map_err(Error::CommunicationError)?;
 
                     msg.extend(&buf[..m].to_vec());
                 }
@@ -187,3 +322,40 @@ impl TCPCommunication {
         self.address.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_framing() -> Result<(), Error> {
+        let address = "localhost:1777";
+        let listener = TcpListener::bind(address)?;
+        // Message longer than read buffer, sent in parts.
+        let msg: Vec<u8> = (0..5000).map(|i| (i % 256) as u8).collect();
+        let sent = msg.clone();
+        let sender = thread::spawn(move || -> std::io::Result<()> {
+            let (mut socket, _) = listener.accept()?;
+            let framed = TCPCommunication::frame(&sent);
+            for part in framed.chunks(2048) {
+                socket.write_all(part)?;
+                thread::sleep(Duration::from_millis(20));
+            }
+            let (mut socket, _) = listener.accept()?;
+            socket.write_all(&u64::MAX.to_be_bytes())?;
+            Ok(())
+        });
+
+        let stream = TCPCommunication::connect(address)?;
+        assert_eq!(TCPCommunication::read_framed(&stream)?, msg);
+        let stream = TCPCommunication::connect(address)?;
+        assert!(matches!(
+            TCPCommunication::read_framed(&stream),
+            Err(Error::ProtocolError(_))
+        ));
+        sender.join().unwrap()?;
+
+        Ok(())
+    }
+}
@@ -1,58 +1,671 @@
-use crate::{controller::SignatureState, kerl::KERL};
-use crate::{error::Error, tel::tel_event::TelState, tel::TEL};
-use base64::URL_SAFE;
-use keri::signer::CryptoBox;
+use crate::{
+    communication::tcp_communication::TCPCommunication,
+    controller::{KeyStateVerification, Receipt, ReceiptCoverage, SignatureState},
+    kerl::{
+        event_generator::{
+            is_decommissioned, is_non_transferable, make_digest_seal, make_witness_rct, Key,
+            KeyType, PrefixType, WitnessConfig,
+        },
+        duplicity::{DuplicityEvidence, DuplicityReport, FailureKind},
+        pending::PendingStore,
+        validator_receipts::ValidatorReceipts,
+        verification::{verify_signature, verify_with_keys},
+        witness_receipts::WitnessReceipts,
+        KERL,
+    },
+};
+use crate::{
+    error::Error,
+    tel::tel_event::{EventType, SignedTelEvent, TelEvent, TelState},
+    tel::TEL,
+};
+use crate::wallet::{keystore::Keystore, MultiKeyManager, Wallet};
 use keri::{
     database::lmdb::LmdbEventDatabase,
+    derivation::self_addressing::SelfAddressing,
+    event::{
+        event_data::EventData,
+        sections::{
+            seal::{EventSeal, Seal},
+            threshold::SignatureThreshold,
+        },
+        EventMessage,
+    },
+    event_message::{
+        parse::{signed_event_stream, Deserialized},
+        SignedEventMessage, SignedNontransferableReceipt,
+    },
     keri::Keri,
-    prefix::{IdentifierPrefix, Prefix},
+    prefix::{AttachedSignaturePrefix, BasicPrefix, IdentifierPrefix, Prefix, SelfSigningPrefix},
     signer::KeyManager,
     state::IdentifierState,
 };
-use std::{convert::TryInto, path::Path};
+use log::warn;
+use std::{path::Path, time::Duration};
 
 pub struct Entity {
     keri: KERL<LmdbEventDatabase>,
-    pub wallet: CryptoBox,
+    pub wallet: Wallet,
+    keystore: Option<Keystore>,
+    receipts: WitnessReceipts,
+    duplicity: DuplicityEvidence,
+    validator_receipts: ValidatorReceipts,
+    pending: PendingStore,
 }
 
 impl Entity {
-    pub fn new(db_path: &str) -> Result<Entity, Error> {
+    pub fn new(db_path: &str, prefix_type: PrefixType) -> Result<Entity, Error> {
+        let wallet = match prefix_type {
+            PrefixType::NonTransferable => Wallet::non_transferable(KeyType::Ed25519Sha512)?,
+            _ => Wallet::new()?,
+        };
+        Self::incept_with_wallet(db_path, wallet, &WitnessConfig::default(), prefix_type)
+    }
+
+    /// Incepts identifier with designated witnesses. Its events are fully
+    /// committed when `witness_config.threshold` witnesses receipt them.
+    pub fn new_with_witnesses(
+        db_path: &str,
+        witness_config: &WitnessConfig,
+    ) -> Result<Entity, Error> {
+        Self::incept_with_wallet(db_path, Wallet::new()?, witness_config, PrefixType::Basic)
+    }
+
+    /// Opens identifier stored in `db_path` or incepts a new one if there
+    /// is none. Private keys are kept in keystore encrypted with
+    /// `passphrase`, so the identifier can be used again after restart.
+    pub fn open(db_path: &str, passphrase: &str) -> Result<Entity, Error> {
+        if Keystore::new(db_path, passphrase).exists() {
+            Self::load(db_path, passphrase)
+        } else {
+            let mut ent = Self::incept_with_wallet(
+                db_path,
+                Wallet::new()?,
+                &WitnessConfig::default(),
+                PrefixType::Basic,
+            )?;
+            ent.save_keys(db_path, passphrase)?;
+            Ok(ent)
+        }
+    }
+
+    /// Opens identifier stored in `db_path` with keys from its keystore.
+    /// Fails if there is no keystore.
+    pub fn load(db_path: &str, passphrase: &str) -> Result<Entity, Error> {
+        let keystore = Keystore::new(db_path, passphrase);
+        if !keystore.exists() {
+            return Err(Error::StorageError(format!(
+                "there is no keystore in {}",
+                db_path
+            )));
+        }
         let db = LmdbEventDatabase::new(Path::new(db_path))
-            .map_err(|e| Error::Generic(e.to_string()))?;
-        let wallet = CryptoBox::new()?;
+            .map_err(|e| Error::StorageError(e.to_string()))?;
+        let (prefix, mut wallet) = keystore.load()?;
+        let keri = KERL::new(db, prefix.parse()?)?;
+        // Make sure that stored keys are the current keys of identifier.
+        let state = keri
+            .get_state()?
+            .ok_or_else(|| Error::KelNotFound(prefix.clone()))?;
+        let key_prefixes =
+            |keys: Vec<Key>| keys.iter().map(Key::derive_key_prefix).collect::<Vec<_>>();
+        // Keys of decommissioned identifier are wiped.
+        if (wallet.has_keys() || !is_decommissioned(&state))
+            && key_prefixes(wallet.public_keys()) != state.current.public_keys
+        {
+            // Keystore is one rotation behind the KEL if keys weren't saved
+            // after rotation. Keys it rotated to are stored already.
+            if !wallet.has_keys()
+                || key_prefixes(wallet.next_public_keys()) != state.current.public_keys
+            {
+                return Err(Error::StorageError(
+                    "keystore doesn't match identifier current keys".into(),
+                ));
+            }
+            if is_decommissioned(&state) {
+                wallet.wipe();
+            } else if wallet.is_rotation_prepared() {
+                wallet.rotate()?;
+            } else {
+                return Err(Error::StorageError(
+                    "keystore misses keys identifier rotated to".into(),
+                ));
+            }
+            keystore.save(&prefix, &wallet)?;
+        }
+
+        Ok(Self {
+            keri,
+            wallet,
+            keystore: Some(keystore),
+            receipts: WitnessReceipts::new(db_path),
+            duplicity: DuplicityEvidence::new(db_path),
+            validator_receipts: ValidatorReceipts::new(db_path),
+            pending: PendingStore::new(db_path),
+        })
+    }
+
+    /// Writes keys to keystore encrypted with `passphrase`. Keys are kept
+    /// there from now on, so identifier can be loaded after restart.
+    pub fn save_keys(&mut self, db_path: &str, passphrase: &str) -> Result<(), Error> {
+        let keystore = Keystore::new(db_path, passphrase);
+        keystore.save(&self.get_prefix()?, &self.wallet)?;
+        self.keystore = Some(keystore);
+        Ok(())
+    }
+
+    /// Incepts identifier with `key_count` keys of given type and signing
+    /// threshold. Its prefix is self-addressing.
+    pub fn new_with_keys(
+        db_path: &str,
+        key_type: KeyType,
+        key_count: usize,
+        threshold: SignatureThreshold,
+    ) -> Result<Entity, Error> {
+        let wallet = Wallet::with_key_types(&vec![key_type; key_count], threshold)?;
+        Self::incept_with_wallet(
+            db_path,
+            wallet,
+            &WitnessConfig::default(),
+            PrefixType::SelfAddressing,
+        )
+    }
+
+    /// Incepts identifier with keys derived from seeds. `seeds` is json
+    /// list of base64 encoded seeds, at least two are required.
+    pub fn new_from_seeds(db_path: &str, seeds: &str) -> Result<Entity, Error> {
+        let seeds: Vec<&str> =
+            serde_json::from_str(seeds).map_err(|e| Error::Generic(e.to_string()))?;
+        let wallet = Wallet::from_seeds(&seeds)?;
+        Self::incept_with_wallet(db_path, wallet, &WitnessConfig::default(), PrefixType::Basic)
+    }
+
+    /// Incepts identifier with keys derived from BIP39 mnemonic phrase.
+    pub fn new_from_mnemonic(db_path: &str, phrase: &str) -> Result<Entity, Error> {
+        let wallet = Wallet::from_mnemonic(phrase)?;
+        Self::incept_with_wallet(db_path, wallet, &WitnessConfig::default(), PrefixType::Basic)
+    }
+
+    /// Incepts identifier delegated by `delegator`. Delegator approves the
+    /// inception by anchoring seal of delegated inception event in its KEL.
+    pub fn new_delegated(db_path: &str, delegator: &mut Entity) -> Result<Entity, Error> {
+        let db = LmdbEventDatabase::new(Path::new(db_path))
+            .map_err(|e| Error::StorageError(e.to_string()))?;
+        let wallet = Wallet::new()?;
         let mut keri = KERL::new(db, IdentifierPrefix::default())?;
-        keri.incept(&wallet)?;
 
-        Ok(Self { keri, wallet })
+        let dip = keri.make_dip(&wallet, &delegator.get_own_state()?)?;
+        delegator.approve_delegated(&dip.event_message)?;
+        // Delegated event is accepted only if delegator's KEL is known.
+        keri.process_kel(&delegator.get_kerl()?)?;
+        keri.process_signed(&dip)?;
+
+        Ok(Self {
+            keri,
+            wallet,
+            keystore: None,
+            receipts: WitnessReceipts::new(db_path),
+            duplicity: DuplicityEvidence::new(db_path),
+            validator_receipts: ValidatorReceipts::new(db_path),
+            pending: PendingStore::new(db_path),
+        })
     }
 
-    // pub fn new_from_seeds(db_path: &str, seeds: &str) -> Result<Entity, Error> {
-    //     let seeds: Vec<&str> =
-    //         serde_json::from_str(seeds).map_err(|e| Error::Generic(e.to_string()))?;
-    //     let db = LmdbEventDatabase::new(Path::new(db_path))
-    //         .map_err(|e| Error::Generic(e.to_string()))?;
-    //     let wallet = WalletWrapper::incept_wallet_from_seed(seeds.clone())?;
-    //     let mut keri = KERL::new(db, IdentifierPrefix::default())?;
-    //     keri.incept(&wallet)?;
+    fn incept_with_wallet(
+        db_path: &str,
+        wallet: Wallet,
+        witness_config: &WitnessConfig,
+        prefix_type: PrefixType,
+    ) -> Result<Entity, Error> {
+        let db = LmdbEventDatabase::new(Path::new(db_path))
+            .map_err(|e| Error::StorageError(e.to_string()))?;
+        let mut keri = KERL::new(db, IdentifierPrefix::default())?;
+        keri.incept(&wallet, witness_config, prefix_type)?;
 
-    //     Ok(Self {
-    //         keri,
-    //         wallet: WalletWrapper::incept_wallet_from_seed(seeds)?,
-    //     })
-    // }
+        Ok(Self {
+            keri,
+            wallet,
+            keystore: None,
+            receipts: WitnessReceipts::new(db_path),
+            duplicity: DuplicityEvidence::new(db_path),
+            validator_receipts: ValidatorReceipts::new(db_path),
+            pending: PendingStore::new(db_path),
+        })
+    }
 
     pub fn update_keys(&mut self) -> Result<(), Error> {
-        self.keri.rotate(&mut self.wallet)?;
+        self.rotate(None)
+    }
+
+    /// Rotates keys and designates new set of witnesses.
+    pub fn update_witnesses(&mut self, witness_config: &WitnessConfig) -> Result<(), Error> {
+        self.rotate(Some(witness_config))
+    }
+
+    /// Rotates keys of delegated identifier. Rotation is approved by
+    /// `delegator` by anchoring seal of delegated rotation in its KEL.
+    /// Keys are rotated only once the rotation is accepted.
+    pub fn update_delegated_keys(&mut self, delegator: &mut Entity) -> Result<(), Error> {
+        let delegator_prefix: IdentifierPrefix = delegator.get_prefix()?.parse()?;
+        if self.get_delegator()? != Some(delegator_prefix) {
+            return Err(Error::Generic(
+                "Identifier is not delegated by given delegator".into(),
+            ));
+        }
+        let drt = self
+            .keri
+            .make_drt(&mut self.wallet, &delegator.get_own_state()?)?;
+        // Keys to rotate to are stored before the rotation is anchored.
+        self.save_wallet()?;
+        self.keri.check_drt(&drt)?;
+        delegator.approve_delegated(&drt.event_message)?;
+        self.keri.process_kel(&delegator.get_kerl()?)?;
+        self.keri.process_signed(&drt)?;
+        self.wallet.rotate()?;
+        self.save_wallet()
+    }
+
+    /// Anchors seal of delegated event in KEL.
+    fn approve_delegated(&mut self, delegated: &EventMessage) -> Result<(), Error> {
+        self.keri.approve_delegated(delegated, &self.wallet)?;
         Ok(())
     }
 
-    pub fn append(&mut self, msg: &str) -> Result<(), Error> {
-        let payload = if msg.is_empty() { None } else { Some(msg) };
-        self.keri.make_ixn(payload, &self.wallet)?;
+    /// Returns delegator of this identifier, if it's delegated.
+    pub fn get_delegator(&self) -> Result<Option<IdentifierPrefix>, Error> {
+        self.keri.get_delegator(&self.get_prefix()?.parse()?)
+    }
+
+    /// Checks if events of delegated identifier are approved by its
+    /// delegator. It's always true for not delegated identifiers.
+    pub fn check_delegation(&self, id: &IdentifierPrefix) -> Result<(), Error> {
+        self.keri.check_delegation(id)
+    }
+
+    fn get_own_state(&self) -> Result<IdentifierState, Error> {
+        self.keri
+            .get_state()?
+            .ok_or(Error::Generic("There is no state".into()))
+    }
+
+    /// Decommissions identifier. It makes the final rotation, which doesn't
+    /// commit to any next keys, and wipes all private keys.
+    pub fn decommission(&mut self) -> Result<(), Error> {
+        self.check_active()?;
+        if !self.is_transferable()? {
+            return Err(Error::Generic(
+                "Non-transferable identifier can't be decommissioned".into(),
+            ));
+        }
+        if self.get_delegator()?.is_some() {
+            return Err(Error::Generic(
+                "Delegated identifier can't be decommissioned".into(),
+            ));
+        }
+        self.keri.decommission(&mut self.wallet)?;
+        self.wallet.wipe();
+        self.save_wallet()
+    }
+
+    pub fn is_decommissioned(&self) -> Result<bool, Error> {
+        Ok(is_decommissioned(&self.get_own_state()?))
+    }
+
+    fn check_active(&self) -> Result<(), Error> {
+        if self.is_decommissioned()? {
+            Err(Error::Decommissioned(self.get_prefix()?))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn rotate(&mut self, witness_config: Option<&WitnessConfig>) -> Result<(), Error> {
+        self.check_active()?;
+        if !self.is_transferable()? {
+            return Err(Error::Generic(
+                "Keys of non-transferable identifier can't be rotated".into(),
+            ));
+        }
+        if self.get_delegator()?.is_some() {
+            return Err(Error::Generic(
+                "Rotation of delegated identifier has to be approved by delegator".into(),
+            ));
+        }
+        // Keys to rotate to are stored before rotation event is made, so
+        // keystore has them even if saving rotated keys fails.
+        self.wallet.prepare_rotation()?;
+        self.save_wallet()?;
+        self.keri.rotate(&mut self.wallet, witness_config)?;
+        self.save_wallet()
+    }
+
+    /// Writes keys to keystore, if identifier has one.
+    fn save_wallet(&self) -> Result<(), Error> {
+        match &self.keystore {
+            Some(keystore) => keystore.save(&self.get_prefix()?, &self.wallet),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns current witnesses and their threshold.
+    pub fn get_witness_config(&self) -> Result<WitnessConfig, Error> {
+        self.keri
+            .get_state()?
+            .map(|state| WitnessConfig::from_state(&state))
+            .ok_or(Error::Generic("There is no state".into()))
+    }
+
+    /// Verifies and stores witness receipts found in `msg`. Other messages
+    /// are ignored.
+    pub fn process_witness_receipts(&self, msg: &[u8]) -> Result<(), Error> {
+        let events = signed_event_stream(msg)
+            .map_err(|e| Error::ProtocolError(e.to_string()))?
+            .1;
+        for des_event in events {
+            if let Deserialized::Rct(rct) = des_event {
+                self.process_witness_receipt(&rct)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn process_witness_receipt(&self, rct: &SignedNontransferableReceipt) -> Result<(), Error> {
+        let prefix = &rct.body.event.prefix;
+        let sn = rct.body.event.sn;
+        let receipted_digest = match &rct.body.event.event_data {
+            EventData::Rct(receipt) => receipt.receipted_event_digest.clone(),
+            _ => return Err(Error::ProtocolError("not a witness receipt".into())),
+        };
+        let event = self
+            .keri
+            .get_event_at_sn(prefix, sn)?
+            .ok_or(Error::Generic(format!("There is no event of sn {}", sn)))?
+            .serialize()?;
+        if !receipted_digest.verify_binding(&event) {
+            return Err(Error::ProtocolError(
+                "receipt doesn't match the event".into(),
+            ));
+        }
+        let witnesses = self
+            .keri
+            .get_state_at_sn(prefix, sn)?
+            .ok_or(Error::Generic(format!("There is no state at sn {}", sn)))?
+            .witnesses;
+        for (witness, signature) in &rct.couplets {
+            if !witnesses.contains(witness) {
+                return Err(Error::InvalidSignature(format!(
+                    "{} is not a witness of event",
+                    witness.to_str()
+                )));
+            }
+            if !verify_signature(witness, &event, &signature.derivative())? {
+                return Err(Error::InvalidSignature("wrong witness signature".into()));
+            }
+            self.receipts.add(prefix, sn, witness, signature)?;
+        }
         Ok(())
     }
 
+    /// Verifies receipt of transferable identifier and indexes its seal.
+    /// Its KEL has to be known. Receipt itself is stored by event
+    /// processor.
+    fn process_validator_receipt(&self, vrc: &SignedEventMessage) -> Result<(), Error> {
+        let prefix = &vrc.event_message.event.prefix;
+        let sn = vrc.event_message.event.sn;
+        let (receipted_digest, seal) = match &vrc.event_message.event.event_data {
+            EventData::Vrc(receipt) => (
+                receipt.receipted_event_digest.clone(),
+                receipt.validator_seal.clone(),
+            ),
+            _ => return Err(Error::ProtocolError("not a validator receipt".into())),
+        };
+        let event = self
+            .keri
+            .get_event_at_sn(prefix, sn)?
+            .ok_or(Error::Generic(format!("There is no event of sn {}", sn)))?
+            .serialize()?;
+        if !receipted_digest.verify_binding(&event) {
+            return Err(Error::ProtocolError(
+                "receipt doesn't match the event".into(),
+            ));
+        }
+        let validator_event = self
+            .keri
+            .get_event_at_sn(&seal.prefix, seal.sn)?
+            .ok_or_else(|| Error::KelNotFound(seal.prefix.to_str()))?;
+        if !seal
+            .event_digest
+            .verify_binding(&validator_event.serialize()?)
+        {
+            return Err(Error::InvalidSignature("wrong validator seal".into()));
+        }
+        let keys = self
+            .keri
+            .get_state_at_sn(&seal.prefix, seal.sn)?
+            .ok_or_else(|| Error::KelNotFound(seal.prefix.to_str()))?
+            .current;
+        if !verify_with_keys(&keys, &event, &vrc.signatures)? {
+            return Err(Error::InvalidSignature("wrong validator signature".into()));
+        }
+        self.validator_receipts.add(prefix, sn, &seal)
+    }
+
+    /// Returns identifiers which receipted event of given sn: witnesses
+    /// and other transferable identifiers, along with seals of their
+    /// establishment events.
+    pub fn get_receipts(&self, prefix: &IdentifierPrefix, sn: u64) -> Result<Vec<Receipt>, Error> {
+        let witnesses = self
+            .receipts
+            .get(prefix, sn)?
+            .into_iter()
+            .map(|(witness, _)| Receipt {
+                prefix: IdentifierPrefix::Basic(witness).to_str(),
+                seal: None,
+            });
+        let validators = self
+            .validator_receipts
+            .get(prefix, sn)?
+            .into_iter()
+            .filter_map(
+                |seal| match self.keri.has_receipt(prefix, sn, &seal.prefix) {
+                    Ok(true) => Some(Ok(Receipt {
+                        prefix: seal.prefix.to_str(),
+                        seal: Some(seal),
+                    })),
+                    Ok(false) => None,
+                    Err(e) => Some(Err(e)),
+                },
+            )
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(witnesses.chain(validators).collect())
+    }
+
+    /// Returns summary of receipts of each event of given identifier.
+    pub fn get_receipt_coverage(
+        &self,
+        prefix: &IdentifierPrefix,
+    ) -> Result<Vec<ReceiptCoverage>, Error> {
+        let state = self
+            .get_state_for_prefix(prefix)?
+            .ok_or_else(|| Error::KelNotFound(prefix.to_str()))?;
+        (0..=state.sn)
+            .map(|sn| {
+                let witness_threshold = self
+                    .keri
+                    .get_state_at_sn(prefix, sn)?
+                    .map(|state| state.tally)
+                    .unwrap_or_default();
+                let (witnesses, validators): (Vec<_>, Vec<_>) = self
+                    .get_receipts(prefix, sn)?
+                    .into_iter()
+                    .partition(|receipt| receipt.seal.is_none());
+                Ok(ReceiptCoverage {
+                    sn,
+                    witnesses: witnesses.into_iter().map(|r| r.prefix).collect(),
+                    witness_threshold,
+                    validators: validators.into_iter().map(|r| r.prefix).collect(),
+                })
+            })
+            .collect()
+    }
+
+    /// Returns formatted own KEL followed by receipts of its events, both
+    /// of witnesses and other identifiers.
+    pub fn get_formatted_kerl(&self) -> Result<String, Error> {
+        let prefix: IdentifierPrefix = self.get_prefix()?.parse()?;
+        let mut out = TCPCommunication::format_event_stream(&self.export_kel(&prefix)?, false);
+        for sn in 0..=self.get_own_state()?.sn {
+            for receipt in self.get_receipts(&prefix, sn)? {
+                if let Some(seal) = receipt.seal {
+                    out.push_str(&TCPCommunication::format_validator_receipt(sn, &seal));
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// Returns KEL of identifier along with stored witness receipts, as
+    /// signed event stream. KEL of delegated identifier is preceded by
+    /// KEL of its delegator, so delegation can be checked.
+    pub fn export_kel(&self, prefix: &IdentifierPrefix) -> Result<Vec<u8>, Error> {
+        let mut stream = match self.keri.get_delegator(prefix)? {
+            Some(delegator) => self.export_kel(&delegator)?,
+            None => vec![],
+        };
+        stream.extend(
+            self.keri
+                .get_kerl_for_prefix(prefix)?
+                .ok_or_else(|| Error::KelNotFound(prefix.to_str()))?,
+        );
+        let state = self
+            .get_state_for_prefix(prefix)?
+            .ok_or_else(|| Error::KelNotFound(prefix.to_str()))?;
+        for sn in 0..=state.sn {
+            let couplets = self.receipts.get(prefix, sn)?;
+            if couplets.is_empty() {
+                continue;
+            }
+            let event = self
+                .keri
+                .get_event_at_sn(prefix, sn)?
+                .ok_or(Error::Generic(format!("There is no event of sn {}", sn)))?;
+            let rct = make_witness_rct(&event)?;
+            stream.extend(SignedNontransferableReceipt::new(&rct, couplets).serialize()?);
+        }
+        Ok(stream)
+    }
+
+    /// Imports signed event stream made by `export_kel`. All events and
+    /// receipts are validated, witness receipts are stored. Nothing is
+    /// imported if any of them is invalid.
+    pub fn import_kel(&self, stream: &[u8]) -> Result<(), Error> {
+        // The whole stream is processed in scratch database first.
+        let scratch_dir = tempfile::tempdir()?;
+        let scratch_db = LmdbEventDatabase::new(scratch_dir.path())
+            .map_err(|e| Error::StorageError(e.to_string()))?;
+        KERL::new(scratch_db, IdentifierPrefix::default())?.import(stream)?;
+
+        for rct in self.keri.import(stream)? {
+            self.process_witness_receipt(&rct)?;
+        }
+        Ok(())
+    }
+
+    /// Returns stored witness receipts of own event of given sn.
+    pub fn get_witness_receipts(
+        &self,
+        sn: u64,
+    ) -> Result<Vec<(BasicPrefix, SelfSigningPrefix)>, Error> {
+        self.receipts.get(&self.get_prefix()?.parse()?, sn)
+    }
+
+    /// Returns true if event of given sn is receipted by at least
+    /// threshold of its witnesses.
+    pub fn is_committed(&self, sn: u64) -> Result<bool, Error> {
+        let prefix: IdentifierPrefix = self.get_prefix()?.parse()?;
+        let state = self
+            .keri
+            .get_state_at_sn(&prefix, sn)?
+            .ok_or(Error::Generic(format!("There is no event of sn {}", sn)))?;
+        Ok(self.receipts.get(&prefix, sn)?.len() as u64 >= state.tally)
+    }
+
+    /// Anchors digest of `msg` in interaction event. Empty message makes
+    /// interaction event without seals.
+    pub fn append(&mut self, msg: &str) -> Result<EventSeal, Error> {
+        let seals = if msg.is_empty() {
+            vec![]
+        } else {
+            vec![make_digest_seal(msg.as_bytes())]
+        };
+        self.anchor(seals)
+    }
+
+    /// Anchors seals in interaction event. Returns seal of that event, so
+    /// the anchor can be referenced later.
+    pub fn anchor(&mut self, seals: Vec<Seal>) -> Result<EventSeal, Error> {
+        self.check_active()?;
+        let ixn = self.keri.make_ixn(seals, &self.wallet)?.event_message;
+        Ok(EventSeal {
+            prefix: ixn.event.prefix.clone(),
+            sn: ixn.event.sn,
+            event_digest: SelfAddressing::Blake3_256.derive(&ixn.serialize()?),
+        })
+    }
+
+    /// Prepares interaction event which anchors seals, to be signed
+    /// outside, for example on offline machine. Returns serialized event.
+    /// Event waits for signatures until it's committed or discarded, also
+    /// over restart. It can't be committed if KEL was extended meanwhile.
+    pub fn prepare_anchor(&self, seals: Vec<Seal>) -> Result<Vec<u8>, Error> {
+        self.check_active()?;
+        let ixn = self.keri.prepare_ixn(seals)?;
+        let serialized = ixn.serialize()?;
+        self.pending.set(ixn, None)?;
+        Ok(serialized)
+    }
+
+    /// Returns serialized event which waits for signatures, if there is
+    /// one.
+    pub fn get_pending(&self) -> Result<Option<Vec<u8>>, Error> {
+        self.pending
+            .get()?
+            .map(|pending| pending.event.serialize().map_err(Error::from))
+            .transpose()
+    }
+
+    /// Adds detached signatures of pending event. Signature replaces the
+    /// previous one made with key of the same index.
+    pub fn add_signatures(&self, signatures: &[AttachedSignaturePrefix]) -> Result<(), Error> {
+        self.pending.add_signatures(signatures)
+    }
+
+    /// Processes pending event with collected signatures and returns its
+    /// seal. If they are wrong or don't satisfy the threshold, event keeps
+    /// waiting for more signatures.
+    pub fn commit_pending(&mut self) -> Result<EventSeal, Error> {
+        self.check_active()?;
+        let pending = self
+            .pending
+            .get()?
+            .ok_or(Error::Generic("There is no pending event".into()))?;
+        let ixn = self
+            .keri
+            .process(pending.event, pending.signatures)?
+            .event_message;
+        self.pending.clear()?;
+        Ok(EventSeal {
+            prefix: ixn.event.prefix.clone(),
+            sn: ixn.event.sn,
+            event_digest: SelfAddressing::Blake3_256.derive(&ixn.serialize()?),
+        })
+    }
+
+    /// Drops pending event along with its signatures.
+    pub fn discard_pending(&self) -> Result<(), Error> {
+        self.pending.clear()
+    }
+
     pub fn get_kerl(&self) -> Result<Vec<u8>, Error> {
         let kerl = self.keri.get_kerl()?.unwrap_or(vec![]);
         Ok(kerl)
@@ -65,6 +678,16 @@ impl Entity {
             .ok_or(Error::Generic("There is no prefix".into()))
     }
 
+    /// Returns prefix of X25519 key agreement key derived from the first
+    /// current key.
+    pub fn get_encryption_key(&self) -> Result<String, Error> {
+        Ok(self.wallet.encryption_key()?.derive_key_prefix().to_str())
+    }
+
+    pub fn is_transferable(&self) -> Result<bool, Error> {
+        Ok(!is_non_transferable(&self.get_prefix()?.parse()?))
+    }
+
     pub fn get_state_for_prefix(
         &self,
         id: &IdentifierPrefix,
@@ -73,9 +696,79 @@ impl Entity {
     }
 
     pub fn respond(&self, msg: &[u8]) -> Result<Vec<u8>, Error> {
-        self.keri
-            .respond(msg, &self.wallet)
-            .map_err(|e| Error::Generic(e.to_string()))
+        let (response, failures) = self.keri.respond(msg, &self.wallet)?;
+        for failure in failures {
+            if failure.kind == FailureKind::Duplicitous {
+                // Receipts of other events are sent anyway.
+                if let Err(e) = self.record_duplicity(&failure.event) {
+                    warn!(
+                        "Can't record duplicity of {}: {}",
+                        failure.event.event_message.event.prefix.to_str(),
+                        e
+                    );
+                }
+            }
+        }
+        let des_events = signed_event_stream(msg)
+            .map_err(|e| Error::ProtocolError(e.to_string()))?
+            .1;
+        for des_event in des_events {
+            if let Deserialized::Vrc(vrc) = des_event {
+                // Receipts of unknown events or validators can't be
+                // verified, they are skipped.
+                let _ = self.process_validator_receipt(&vrc);
+            }
+        }
+        Ok(response)
+    }
+
+    /// Stores conflicting event along with the accepted one, if the
+    /// conflicting event is properly signed. Then it's a proof of
+    /// duplicity.
+    fn record_duplicity(&self, conflicting: &SignedEventMessage) -> Result<(), Error> {
+        let event = &conflicting.event_message.event;
+        let accepted = match self.keri.get_signed_event_at_sn(&event.prefix, event.sn)? {
+            Some(accepted) => accepted,
+            None => return Ok(()),
+        };
+        let key_config = match self.keri.get_signing_keys(&conflicting.event_message)? {
+            Some(key_config) => key_config,
+            None => return Ok(()),
+        };
+        let msg = conflicting.event_message.serialize()?;
+        // Signatures indexed out of the key list are just wrong.
+        if verify_with_keys(&key_config, &msg, &conflicting.signatures).unwrap_or(false) {
+            self.duplicity.add(&accepted, conflicting)?;
+        }
+        Ok(())
+    }
+
+    /// Sets how long received events and receipts wait in escrow for
+    /// missing events or signatures.
+    pub fn set_escrow_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        self.keri.set_escrow_timeout(timeout)
+    }
+
+    pub fn get_escrow_timeout(&self) -> Result<Duration, Error> {
+        self.keri.get_escrow_timeout()
+    }
+
+    /// Returns proofs of duplicity of given identifier.
+    pub fn get_duplicity_reports(
+        &self,
+        prefix: &IdentifierPrefix,
+    ) -> Result<Vec<DuplicityReport>, Error> {
+        self.duplicity.get(prefix)
+    }
+
+    /// Fails if there is a proof of duplicity of given identifier. Nothing
+    /// signed by such identifier can be trusted.
+    pub fn check_duplicity(&self, prefix: &IdentifierPrefix) -> Result<(), Error> {
+        if self.duplicity.get(prefix)?.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Duplicitous(prefix.to_str()))
+        }
     }
 
     pub fn sign(&self, msg: &str) -> Result<Vec<u8>, Error> {
@@ -84,31 +777,201 @@ impl Entity {
             .map_err(|e| Error::KeriError(e))
     }
 
-    // Works only for currnet keys.
-    pub fn verify(&self, state: IdentifierState, msg: &str, signature: &str) -> Result<bool, Error> {
-        let signature_vec = base64::decode_config(signature, URL_SAFE)?;
+    /// Signs message with all current keys.
+    pub fn sign_indexed(&self, msg: &str) -> Result<Vec<AttachedSignaturePrefix>, Error> {
+        self.wallet.sign_indexed(msg.as_bytes())
+    }
+
+    /// Checks if signatures are valid and satisfy the signing threshold
+    /// of given state. Works only for current keys, see `verify_at` for
+    /// older ones.
+    pub fn verify(
+        &self,
+        state: IdentifierState,
+        msg: &str,
+        signatures: &[AttachedSignaturePrefix],
+    ) -> Result<bool, Error> {
+        if is_decommissioned(&state) {
+            return Err(Error::Decommissioned(state.prefix.to_str()));
+        }
+        self.check_duplicity(&state.prefix)?;
+        verify_with_keys(&state.current, msg.as_bytes(), signatures)
+    }
+
+    /// Verifies signatures with keys which were current at given sn, that
+    /// is keys of the last establishment event up to the sn. Sn has to be
+    /// in the known KEL, as keys could be rotated in events not seen yet.
+    /// Keys set by the final rotation of decommissioned identifier were
+    /// wiped, so verification with them fails with `Decommissioned`.
+    pub fn verify_at(
+        &self,
+        prefix: &IdentifierPrefix,
+        msg: &str,
+        signatures: &[AttachedSignaturePrefix],
+        sn: u64,
+    ) -> Result<KeyStateVerification, Error> {
+        self.check_duplicity(prefix)?;
+        let state = self
+            .get_state_for_prefix(prefix)?
+            .ok_or_else(|| Error::KelNotFound(prefix.to_str()))?;
+        if sn > state.sn {
+            return Err(Error::ProtocolError(format!(
+                "There is no event of {} at sn {}, the last one is at sn {}",
+                prefix.to_str(),
+                sn,
+                state.sn
+            )));
+        }
+        let establishment_events = self.keri.get_establishment_events(prefix)?;
+        let position = establishment_events
+            .iter()
+            .rposition(|(event_sn, _)| *event_sn <= sn)
+            .ok_or(Error::Generic(format!(
+                "There is no key state of {} at sn {}",
+                prefix.to_str(),
+                sn
+            )))?;
+        if is_decommissioned(&state) && position + 1 == establishment_events.len() {
+            return Err(Error::Decommissioned(prefix.to_str()));
+        }
+        let (establishment_sn, keys) = &establishment_events[position];
+        Ok(KeyStateVerification {
+            // Signatures indexed out of the key list are just wrong.
+            verified: verify_with_keys(keys, msg.as_bytes(), signatures).unwrap_or(false),
+            establishment_sn: Some(*establishment_sn),
+            rotated: position + 1 < establishment_events.len(),
+        })
+    }
+
+    /// Searches establishment events of identifier, starting from the
+    /// latest, for keys which verify signatures. Keys set by the final
+    /// rotation of decommissioned identifier are skipped, as anything
+    /// signed with them after wiping is forged.
+    pub fn verify_with_history(
+        &self,
+        prefix: &IdentifierPrefix,
+        msg: &str,
+        signatures: &[AttachedSignaturePrefix],
+    ) -> Result<KeyStateVerification, Error> {
+        self.check_duplicity(prefix)?;
+        let mut establishment_events = self.keri.get_establishment_events(prefix)?;
+        let latest = establishment_events.len();
+        let decommissioned = self
+            .get_state_for_prefix(prefix)?
+            .map(|state| is_decommissioned(&state))
+            .unwrap_or(false);
+        if decommissioned {
+            establishment_events.pop();
+        }
+        for (position, (establishment_sn, keys)) in establishment_events.iter().enumerate().rev() {
+            // Signatures may be indexed differently than keys of older
+            // establishment events.
+            if verify_with_keys(keys, msg.as_bytes(), signatures).unwrap_or(false) {
+                return Ok(KeyStateVerification {
+                    verified: true,
+                    establishment_sn: Some(*establishment_sn),
+                    rotated: position + 1 < latest,
+                });
+            }
+        }
+        Ok(KeyStateVerification {
+            verified: false,
+            establishment_sn: None,
+            rotated: false,
+        })
+    }
 
-        // This assumes that there is only one key.
-        let bp = state.current.public_keys.get(0).unwrap();
-        let key_type = bp.derivation_code();
-        let public_key = bp.derivative();
-        match key_type.as_str() {
-            "D" => {
-                use ed25519_dalek::{PublicKey, Signature, Verifier};
-                let pk = PublicKey::from_bytes(&public_key).unwrap();
-                let array_signature: [u8; 64] = signature.as_bytes().clone().try_into().unwrap();
-                let signature = Signature::new(array_signature);
-                Ok(pk.verify(msg.as_bytes(), &signature).is_ok())
+    /// Checks if TEL event is anchored by the KEL event its seal points at
+    /// and signed with keys which were current at that event.
+    pub fn verify_tel_event(&self, event: &SignedTelEvent) -> Result<(), Error> {
+        self.check_tel_anchor(event)?;
+        let seal = &event.seal;
+        let msg = String::from_utf8(event.event.serialize()?)?;
+        if !self
+            .verify_at(&seal.prefix, &msg, &event.get_signatures()?, seal.sn)?
+            .verified
+        {
+            return Err(Error::InvalidSignature("wrong TEL event signature".into()));
+        }
+        Ok(())
+    }
+
+    /// Checks if KEL event the TEL event seal points at contains seal of
+    /// the TEL event.
+    fn check_tel_anchor(&self, event: &SignedTelEvent) -> Result<(), Error> {
+        let seal = &event.seal;
+        let kel_event = self
+            .keri
+            .get_event_at_sn(&seal.prefix, seal.sn)?
+            .ok_or_else(|| Error::KelNotFound(seal.prefix.to_str()))?;
+        if !seal.event_digest.verify_binding(&kel_event.serialize()?) {
+            return Err(Error::InvalidSignature(
+                "TEL event seal doesn't match issuer's KEL".into(),
+            ));
+        }
+        if !anchors_tel_event(&kel_event, &event.event)? {
+            return Err(Error::InvalidSignature(
+                "TEL event isn't anchored in issuer's KEL".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Finds own KEL event which anchors the TEL event and returns its
+    /// seal, or None if the TEL event isn't anchored.
+    pub fn find_tel_anchor(&self, event: &TelEvent) -> Result<Option<EventSeal>, Error> {
+        let state = self.get_own_state()?;
+        // Anchor is usually one of the latest events.
+        for sn in (0..=state.sn).rev() {
+            let kel_event = match self.keri.get_event_at_sn(&state.prefix, sn)? {
+                Some(kel_event) => kel_event,
+                None => continue,
+            };
+            if anchors_tel_event(&kel_event, event)? {
+                return Ok(Some(EventSeal {
+                    prefix: state.prefix,
+                    sn,
+                    event_digest: SelfAddressing::Blake3_256.derive(&kel_event.serialize()?),
+                }));
+            }
+        }
+        Ok(None)
+    }
 
-            },
-            _ => todo!()
+    /// Finds event of issuer's KEL which anchors issuance of vc, that is
+    /// seal of `iss` event which identifier is digest of the vc. Returns sn
+    /// of the event, or None if vc isn't anchored by the issuer, whether
+    /// it's revoked or not.
+    pub fn find_vc_anchor(
+        &self,
+        issuer: &IdentifierPrefix,
+        vc: &[u8],
+        tel: &TEL,
+    ) -> Result<Option<u64>, Error> {
+        let issuance = match tel.get_events().first() {
+            Some(issuance) if issuance.event.event_type == EventType::Iss => issuance,
+            _ => return Ok(None),
+        };
+        if &issuance.seal.prefix != issuer {
+            return Ok(None);
+        }
+        if issuance.event.prefix != SelfAddressing::Blake3_256.derive(vc).to_str() {
+            return Ok(None);
+        }
+        match self.check_tel_anchor(issuance) {
+            Ok(()) => Ok(Some(issuance.seal.sn)),
+            Err(Error::InvalidSignature(_)) | Err(Error::KelNotFound(_)) => Ok(None),
+            Err(e) => Err(e),
         }
     }
 
+    /// Verifies vc signatures with keys current at issuance, as sealed in
+    /// vc TEL. Signatures have to satisfy the threshold of those keys.
+    /// Revoked vc is reported regardless of the signatures.
     pub fn verify_vc(
         &self,
         vc: &[u8],
-        signature: &[u8],
+        signatures: &[AttachedSignaturePrefix],
         tel: &TEL,
     ) -> Result<SignatureState, Error> {
         match tel.get_state() {
@@ -117,30 +980,10 @@ impl Entity {
                 let state = self.keri.get_state_for_seal(&event_seal);
                 let keys = match state? {
                     Some(state) => state.current,
-                    None => return Err(Error::Generic("There is no keys".into())),
-                };
-
-                // This assumes that there is only one key.
-                let bp = keys.public_keys.get(0).unwrap();
-                let key_type = bp.derivation_code();
-                let public_key = bp.derivative();
-                let verification = match key_type.as_str() {
-                    "D" => {
-                        // With dalek.
-                        use ed25519_dalek::{PublicKey, Signature, Verifier};
-                        let pk = PublicKey::from_bytes(&public_key).unwrap();
-                        let array_signature: [u8; 64] = signature.clone().try_into().unwrap();
-                        let signature = Signature::new(array_signature);
-                        pk.verify(vc, &signature).is_ok()
-
-                        // With ursa.
-                        // let pub_key = PublicKey {0: public_key.to_vec()};
-                        // let ed = ursa::signatures::ed25519::Ed25519Sha512::new();
-                        // let msg = &serde_json::to_vec(&self).unwrap();
-                        // ed.verify(msg, &self.signature, &pub_key).map_err(|e| Error::Generic(e.to_string()))?
-                    }
-                    _ => false,
+                    None => return Err(Error::KelNotFound(event_seal.prefix.to_str())),
                 };
+                // Signatures indexed out of the key list are just wrong.
+                let verification = verify_with_keys(&keys, vc, signatures).unwrap_or(false);
                 Ok(if verification {
                     SignatureState::Ok
                 } else {
@@ -152,37 +995,786 @@ impl Entity {
     }
 }
 
+/// Checks if KEL event contains seal of the TEL event.
+fn anchors_tel_event(kel_event: &EventMessage, event: &TelEvent) -> Result<bool, Error> {
+    let tel_digest = event.digest()?;
+    let seals = match &kel_event.event.event_data {
+        EventData::Ixn(ixn) => &ixn.data,
+        EventData::Rot(rot) => &rot.data,
+        _ => return Ok(false),
+    };
+    Ok(seals.iter().any(|anchor| match anchor {
+        Seal::Event(tel_seal) => {
+            tel_seal.prefix.to_str() == event.prefix
+                && tel_seal.sn == event.sn
+                && tel_seal.event_digest == tel_digest
+        }
+        _ => false,
+    }))
+}
+
+/// Verifies signature of non-transferable identifier. Its key is the
+/// prefix itself, so no KEL is needed.
+pub fn verify_non_transferable(
+    prefix: &IdentifierPrefix,
+    msg: &str,
+    signatures: &[AttachedSignaturePrefix],
+) -> Result<bool, Error> {
+    match prefix {
+        IdentifierPrefix::Basic(key) if is_non_transferable(prefix) => {
+            match signatures.iter().find(|sig| sig.index == 0) {
+                Some(signature) => {
+                    verify_signature(key, msg.as_bytes(), &signature.signature.derivative())
+                }
+                None => Ok(false),
+            }
+        }
+        _ => Err(Error::Generic(format!(
+            "{} is not non-transferable identifier",
+            prefix.to_str()
+        ))),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use keri::event::sections::seal::LocationSeal;
+    use std::str::from_utf8;
     use tempfile::tempdir;
 
+    #[test]
+    fn test_open() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let path = dir.path().to_str().unwrap();
+
+        let (prefix, public_key) = {
+            let mut ent = Entity::open(path, "passphrase")?;
+            ent.update_keys()?;
+            (ent.get_prefix()?, ent.wallet.public_key().0)
+        };
+
+        // Reopen the same database. Identifier and its keys should be restored.
+        let mut ent = Entity::open(path, "passphrase")?;
+        assert_eq!(ent.get_prefix()?, prefix);
+        assert_eq!(ent.wallet.public_key().0, public_key);
+
+        // Restored keys are still able to rotate the identifier.
+        ent.update_keys()?;
+        let state = ent.get_state_for_prefix(&prefix.parse()?)?.unwrap();
+        assert_eq!(state.sn, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_after_interrupted_rotation() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let path = dir.path().to_str().unwrap();
+
+        let public_key = {
+            let mut ent = Entity::open(path, "passphrase")?;
+            // Rotate as `update_keys` does, but stop before rotated keys
+            // are saved.
+            ent.wallet.prepare_rotation()?;
+            ent.save_wallet()?;
+            ent.keri.rotate(&mut ent.wallet, None)?;
+            ent.wallet.public_key().0
+        };
+
+        // Keystore is one rotation behind, but it has the keys identifier
+        // rotated to.
+        let mut ent = Entity::open(path, "passphrase")?;
+        assert_eq!(ent.wallet.public_key().0, public_key);
+        ent.update_keys()?;
+        drop(ent);
+        let ent = Entity::open(path, "passphrase")?;
+        assert_eq!(ent.get_own_state()?.sn, 2);
+
+        // Keystore without keys identifier rotated to can't be used.
+        let other_dir = tempdir()?;
+        let other_path = other_dir.path().to_str().unwrap();
+        {
+            let mut ent = Entity::open(other_path, "passphrase")?;
+            ent.keri.rotate(&mut ent.wallet, None)?;
+        }
+        assert!(matches!(
+            Entity::open(other_path, "passphrase"),
+            Err(Error::StorageError(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_multiple_keys() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let path = dir.path().to_str().unwrap();
+        let mut ent = Entity::new_with_keys(
+            path,
+            KeyType::Ed25519Sha512,
+            3,
+            SignatureThreshold::Simple(2),
+        )?;
+        let prefix: IdentifierPrefix = ent.get_prefix()?.parse()?;
+        assert!(matches!(prefix, IdentifierPrefix::SelfAddressing(_)));
+
+        let msg = "hello there!";
+        let signatures = ent.sign_indexed(msg)?;
+        let state = ent.get_state_for_prefix(&prefix)?.unwrap();
+        assert_eq!(state.current.public_keys.len(), 3);
+        assert!(ent.verify(state.clone(), msg, &signatures)?);
+        // Two signatures are enough.
+        assert!(ent.verify(state.clone(), msg, &signatures[1..])?);
+        // One signature doesn't satisfy the threshold.
+        assert!(!ent.verify(state.clone(), msg, &signatures[..1])?);
+        // Signatures of other message are wrong.
+        assert!(!ent.verify(state, "other message", &signatures)?);
+
+        ent.update_keys()?;
+        ent.append("some data")?;
+        let state = ent.get_state_for_prefix(&prefix)?.unwrap();
+        assert_eq!(state.sn, 2);
+        assert!(ent.verify(state, msg, &ent.sign_indexed(msg)?)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_self_addressing_prefix() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let ent = Entity::new(dir.path().to_str().unwrap(), PrefixType::SelfAddressing)?;
+        let prefix: IdentifierPrefix = ent.get_prefix()?.parse()?;
+        assert!(matches!(prefix, IdentifierPrefix::SelfAddressing(_)));
+
+        let dir = tempdir()?;
+        let ent = Entity::new(dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        let prefix: IdentifierPrefix = ent.get_prefix()?.parse()?;
+        assert!(matches!(prefix, IdentifierPrefix::Basic(_)));
+
+        // Basic prefix can't be derived from multiple keys.
+        let dir = tempdir()?;
+        let db = LmdbEventDatabase::new(dir.path()).unwrap();
+        let mut keri = KERL::new(db, IdentifierPrefix::default())?;
+        let wallet = Wallet::with_keys(2, SignatureThreshold::Simple(1))?;
+        assert!(keri.incept(&wallet, &WitnessConfig::default(), PrefixType::Basic).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_transferable() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let mut ent = Entity::new(dir.path().to_str().unwrap(), PrefixType::NonTransferable)?;
+        let prefix: IdentifierPrefix = ent.get_prefix()?.parse()?;
+        assert!(!ent.is_transferable()?);
+        assert_eq!(prefix.derivation_code(), "B");
+
+        // KEL consists of inception event only and can't be extended.
+        assert!(ent.update_keys().is_err());
+        let state = ent.get_state_for_prefix(&prefix)?.unwrap();
+        assert_eq!(state.sn, 0);
+
+        let msg = "hello there!";
+        let signatures = ent.sign_indexed(msg)?;
+        assert!(verify_non_transferable(&prefix, msg, &signatures)?);
+        assert!(!verify_non_transferable(&prefix, "other message", &signatures)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delegation() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let mut delegator = Entity::new(dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        let delegator_prefix: IdentifierPrefix = delegator.get_prefix()?.parse()?;
+
+        let delegated_dir = tempdir()?;
+        let mut delegated =
+            Entity::new_delegated(delegated_dir.path().to_str().unwrap(), &mut delegator)?;
+        let delegated_prefix: IdentifierPrefix = delegated.get_prefix()?.parse()?;
+        assert!(matches!(delegated_prefix, IdentifierPrefix::SelfAddressing(_)));
+        assert_eq!(delegated.get_delegator()?, Some(delegator_prefix.clone()));
+        // Delegator's KEL has interaction event approving the inception.
+        assert_eq!(delegator.get_own_state()?.sn, 1);
+
+        // Rotation has to be approved by delegator.
+        assert!(delegated.update_keys().is_err());
+        delegated.update_delegated_keys(&mut delegator)?;
+        assert_eq!(delegated.get_own_state()?.sn, 1);
+        assert_eq!(delegator.get_own_state()?.sn, 2);
+
+        // Rotation which isn't signed with the next keys can't be approved.
+        let mut drt = delegated
+            .keri
+            .make_drt(&mut delegated.wallet, &delegator.get_own_state()?)?;
+        drt.signatures = delegated
+            .wallet
+            .sign_indexed(&drt.event_message.serialize()?)?;
+        assert!(matches!(
+            delegated.keri.check_drt(&drt),
+            Err(Error::InvalidSignature(_))
+        ));
+        let current_key = delegated.wallet.public_keys()[0].derive_key_prefix();
+        delegated.update_delegated_keys(&mut delegator)?;
+        assert_ne!(
+            delegated.wallet.public_keys()[0].derive_key_prefix(),
+            current_key
+        );
+        assert_eq!(delegated.get_own_state()?.sn, 2);
+
+        // Other identifier can't approve the rotation.
+        let other_dir = tempdir()?;
+        let mut other = Entity::new(other_dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        assert!(delegated.update_delegated_keys(&mut other).is_err());
+
+        // Verifier which knows both KELs accepts delegation chain.
+        let verifier_dir = tempdir()?;
+        let verifier = Entity::new(verifier_dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        verifier.keri.process_kel(&delegator.get_kerl()?)?;
+        verifier.keri.process_kel(&delegated.get_kerl()?)?;
+        assert!(verifier.get_state_for_prefix(&delegated_prefix)?.is_some());
+        verifier.check_delegation(&delegated_prefix)?;
+        verifier.check_delegation(&delegator_prefix)?;
+
+        // Exported KEL of delegated identifier has delegator's KEL too.
+        let importer_dir = tempdir()?;
+        let importer = Entity::new(importer_dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        importer.import_kel(&delegated.export_kel(&delegated_prefix)?)?;
+        let state = importer.get_state_for_prefix(&delegated_prefix)?.unwrap();
+        assert_eq!(state.sn, 2);
+        importer.check_delegation(&delegated_prefix)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decommission() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let path = dir.path().to_str().unwrap();
+        let mut ent = Entity::open(path, "passphrase")?;
+        let prefix: IdentifierPrefix = ent.get_prefix()?.parse()?;
+        let msg = "hello there!";
+        let signatures = ent.sign_indexed(msg)?;
+
+        ent.decommission()?;
+        assert!(ent.is_decommissioned()?);
+        assert!(!ent.wallet.has_keys());
+        assert_eq!(ent.get_own_state()?.sn, 1);
+
+        // No new events nor signatures can be made.
+        assert!(matches!(ent.update_keys(), Err(Error::Decommissioned(_))));
+        assert!(matches!(ent.append("data"), Err(Error::Decommissioned(_))));
+        assert!(ent.sign(msg).is_err());
+        assert!(matches!(
+            ent.verify(ent.get_own_state()?, msg, &signatures),
+            Err(Error::Decommissioned(_))
+        ));
+
+        // Signatures made before are verifiable with keys of that time.
+        let verification = ent.verify_at(&prefix, msg, &signatures, 0)?;
+        assert!(verification.verified);
+        assert!(verification.rotated);
+        let verification = ent.verify_with_history(&prefix, msg, &signatures)?;
+        assert_eq!(verification.establishment_sn, Some(0));
+
+        // Decommissioned identifier is restored without keys.
+        drop(ent);
+        let ent = Entity::open(path, "passphrase")?;
+        assert_eq!(ent.get_prefix()?, prefix.to_str());
+        assert!(ent.is_decommissioned()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decommission_with_recovered_keys() -> Result<(), Error> {
+        let seeds = r#"[
+            "rwXoACJgOleVZ2PY7kXn7rA0II0mHYDhc6WrBH8fDAc=",
+            "6zz7M08-HQSFq92sJ8KJOT2cZ47x7pXFQLPB0pckB3Q=",
+            "cwFTk-wgk3ZT2buPRIbK-zxgPx-TKbaegQvPEivN90Y="
+        ]"#;
+        let dir = tempdir()?;
+        let mut ent = Entity::new_from_seeds(dir.path().to_str().unwrap(), seeds)?;
+        let prefix: IdentifierPrefix = ent.get_prefix()?.parse()?;
+        ent.decommission()?;
+
+        // Keys the final rotation switched to are recovered from seeds.
+        let recovered_dir = tempdir()?;
+        let mut recovered = Entity::new_from_seeds(recovered_dir.path().to_str().unwrap(), seeds)?;
+        recovered.update_keys()?;
+        let msg = "hello there!";
+        let signatures = recovered.sign_indexed(msg)?;
+
+        assert!(matches!(
+            ent.verify_at(&prefix, msg, &signatures, 1),
+            Err(Error::Decommissioned(_))
+        ));
+        let verification = ent.verify_with_history(&prefix, msg, &signatures)?;
+        assert!(!verification.verified);
+        assert_eq!(verification.establishment_sn, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_and_import() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let mut ent = Entity::new(dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        let prefix: IdentifierPrefix = ent.get_prefix()?.parse()?;
+        ent.update_keys()?;
+        ent.append("some data")?;
+        let kel = ent.export_kel(&prefix)?;
+
+        let other_dir = tempdir()?;
+        let other = Entity::new(other_dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        other.import_kel(&kel)?;
+        assert_eq!(other.get_state_for_prefix(&prefix)?.unwrap().sn, 2);
+        // Importing the same KEL again doesn't change anything.
+        other.import_kel(&kel)?;
+        assert_eq!(other.export_kel(&prefix)?, kel);
+
+        // Tampered KEL is rejected.
+        let tampered_dir = tempdir()?;
+        let tampered = Entity::new(tampered_dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        let kel_str = String::from_utf8(kel)?.replacen(r#""s":"2""#, r#""s":"3""#, 1);
+        assert!(tampered.import_kel(kel_str.as_bytes()).is_err());
+        // Events before the tampered one aren't imported either.
+        assert!(tampered.get_state_for_prefix(&prefix)?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicity() -> Result<(), Error> {
+        let seeds = r#"[
+            "rwXoACJgOleVZ2PY7kXn7rA0II0mHYDhc6WrBH8fDAc=",
+            "6zz7M08-HQSFq92sJ8KJOT2cZ47x7pXFQLPB0pckB3Q="
+        ]"#;
+        // The same keys sign two different events of sn 1.
+        let dir = tempdir()?;
+        let mut ent = Entity::new_from_seeds(dir.path().to_str().unwrap(), seeds)?;
+        let prefix: IdentifierPrefix = ent.get_prefix()?.parse()?;
+        let msg = "hello there!";
+        let signatures = ent.sign_indexed(msg)?;
+        ent.append("some data")?;
+        let duplicate_dir = tempdir()?;
+        let mut duplicate = Entity::new_from_seeds(duplicate_dir.path().to_str().unwrap(), seeds)?;
+        duplicate.append("other data")?;
+
+        let verifier_dir = tempdir()?;
+        let verifier = Entity::new(verifier_dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        verifier.respond(&ent.get_kerl()?)?;
+        let state = verifier.get_state_for_prefix(&prefix)?.unwrap();
+        assert!(verifier.verify(state.clone(), msg, &signatures)?);
+        assert!(verifier.get_duplicity_reports(&prefix)?.is_empty());
+
+        // Known events are not duplicitous.
+        verifier.respond(&ent.get_kerl()?)?;
+        assert!(verifier.get_duplicity_reports(&prefix)?.is_empty());
+
+        verifier.respond(&duplicate.get_kerl()?)?;
+        let reports = verifier.get_duplicity_reports(&prefix)?;
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].sn, 1);
+        assert!(matches!(
+            verifier.verify(state, msg, &signatures),
+            Err(Error::Duplicitous(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicity_with_wrong_index() -> Result<(), Error> {
+        let seeds = r#"[
+            "rwXoACJgOleVZ2PY7kXn7rA0II0mHYDhc6WrBH8fDAc=",
+            "6zz7M08-HQSFq92sJ8KJOT2cZ47x7pXFQLPB0pckB3Q="
+        ]"#;
+        let dir = tempdir()?;
+        let mut ent = Entity::new_from_seeds(dir.path().to_str().unwrap(), seeds)?;
+        let prefix: IdentifierPrefix = ent.get_prefix()?.parse()?;
+        ent.append("some data")?;
+        let duplicate_dir = tempdir()?;
+        let mut duplicate = Entity::new_from_seeds(duplicate_dir.path().to_str().unwrap(), seeds)?;
+        duplicate.append("other data")?;
+
+        let verifier_dir = tempdir()?;
+        let verifier = Entity::new(verifier_dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        verifier.respond(&ent.get_kerl()?)?;
+
+        // Conflicting event signed with index out of the key list comes
+        // along with the next valid event.
+        ent.append("more data")?;
+        let next = split_kel(&ent.get_kerl()?)?.remove(2);
+        let conflicting = split_kel(&duplicate.get_kerl()?)?.remove(1);
+        let wrong_index = conflicting
+            .signatures
+            .iter()
+            .map(|sig| AttachedSignaturePrefix {
+                index: 5,
+                ..sig.clone()
+            })
+            .collect();
+        let mut msg = conflicting.event_message.sign(wrong_index).serialize()?;
+        msg.extend(next.serialize()?);
+
+        // Valid event is still accepted and receipted.
+        let response = verifier.respond(&msg)?;
+        assert!(!response.is_empty());
+        assert_eq!(verifier.get_state_for_prefix(&prefix)?.unwrap().sn, 2);
+        // Wrongly signed event isn't proof of duplicity.
+        assert!(verifier.get_duplicity_reports(&prefix)?.is_empty());
+
+        Ok(())
+    }
+
+    /// Splits KEL into separate signed events.
+    fn split_kel(kel: &[u8]) -> Result<Vec<SignedEventMessage>, Error> {
+        Ok(signed_event_stream(kel)
+            .map_err(|e| Error::Generic(e.to_string()))?
+            .1
+            .into_iter()
+            .filter_map(|des_event| match des_event {
+                Deserialized::Event(ev) => Some(ev.event.event.sign(ev.signatures.clone())),
+                _ => None,
+            })
+            .collect())
+    }
+
+    #[test]
+    fn test_escrow() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let mut ent = Entity::new_with_keys(
+            dir.path().to_str().unwrap(),
+            KeyType::Ed25519Sha512,
+            2,
+            SignatureThreshold::Simple(2),
+        )?;
+        let prefix: IdentifierPrefix = ent.get_prefix()?.parse()?;
+        ent.update_keys()?;
+        ent.append("some data")?;
+        let events = split_kel(&ent.get_kerl()?)?;
+
+        // Events which arrive before their predecessors wait in escrow.
+        let verifier_dir = tempdir()?;
+        let verifier = Entity::new(verifier_dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        verifier.respond(&events[2].serialize()?)?;
+        verifier.respond(&events[1].serialize()?)?;
+        assert!(verifier.get_state_for_prefix(&prefix)?.is_none());
+        assert_eq!(
+            verifier
+                .keri
+                .get_escrowed_count(FailureKind::UnknownPrefix)?,
+            2
+        );
+        verifier.respond(&events[0].serialize()?)?;
+        assert_eq!(verifier.get_state_for_prefix(&prefix)?.unwrap().sn, 2);
+        assert_eq!(
+            verifier
+                .keri
+                .get_escrowed_count(FailureKind::UnknownPrefix)?,
+            0
+        );
+
+        // Signatures of partially signed event are collected in escrow.
+        ent.append("other data")?;
+        let ixn = split_kel(&ent.get_kerl()?)?.remove(3);
+        for signature in ixn.signatures {
+            let partial = ixn.event_message.sign(vec![signature]);
+            assert_eq!(verifier.get_state_for_prefix(&prefix)?.unwrap().sn, 2);
+            verifier.respond(&partial.serialize()?)?;
+        }
+        assert_eq!(verifier.get_state_for_prefix(&prefix)?.unwrap().sn, 3);
+
+        // Invalid signature sent first doesn't take place of the valid one
+        // of the same index.
+        ent.append("more data")?;
+        let ixn = split_kel(&ent.get_kerl()?)?.remove(4);
+        let mut forged = ixn.signatures[1].clone();
+        forged.index = 0;
+        verifier.respond(&ixn.event_message.sign(vec![forged]).serialize()?)?;
+        assert_eq!(
+            verifier
+                .keri
+                .get_escrowed_count(FailureKind::PartiallySigned)?,
+            0
+        );
+        for signature in ixn.signatures {
+            let partial = ixn.event_message.sign(vec![signature]);
+            verifier.respond(&partial.serialize()?)?;
+        }
+        assert_eq!(verifier.get_state_for_prefix(&prefix)?.unwrap().sn, 4);
+
+        // Signatures of partially signed inception of self-addressing
+        // identifier are collected too.
+        let multi_dir = tempdir()?;
+        let multi = Entity::new_with_keys(
+            multi_dir.path().to_str().unwrap(),
+            KeyType::Ed25519Sha512,
+            2,
+            SignatureThreshold::Simple(2),
+        )?;
+        let multi_prefix: IdentifierPrefix = multi.get_prefix()?.parse()?;
+        let icp = split_kel(&multi.get_kerl()?)?.remove(0);
+        for signature in icp.signatures {
+            let partial = icp.event_message.sign(vec![signature]);
+            assert!(verifier.get_state_for_prefix(&multi_prefix)?.is_none());
+            verifier.respond(&partial.serialize()?)?;
+        }
+        assert_eq!(verifier.get_state_for_prefix(&multi_prefix)?.unwrap().sn, 0);
+
+        // Expired events are dropped.
+        let other_dir = tempdir()?;
+        let other = Entity::new(other_dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        other.set_escrow_timeout(Duration::from_secs(0))?;
+        other.respond(&events[1].serialize()?)?;
+        other.respond(&events[0].serialize()?)?;
+        assert_eq!(other.get_state_for_prefix(&prefix)?.unwrap().sn, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_receipts() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let mut ent = Entity::new(dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        let prefix: IdentifierPrefix = ent.get_prefix()?.parse()?;
+        let validator_dir = tempdir()?;
+        let validator = Entity::new(validator_dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        let validator_prefix = validator.get_prefix()?;
+
+        // Validator responds with its KEL and receipt of the inception.
+        ent.respond(&validator.respond(&ent.get_kerl()?)?)?;
+        let receipts = ent.get_receipts(&prefix, 0)?;
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].prefix, validator_prefix);
+        assert_eq!(receipts[0].seal.as_ref().map(|seal| seal.sn), Some(0));
+
+        ent.update_keys()?;
+        ent.respond(&validator.respond(&ent.get_kerl()?)?)?;
+        let coverage = ent.get_receipt_coverage(&prefix)?;
+        assert_eq!(coverage.len(), 2);
+        assert!(coverage
+            .iter()
+            .all(|event| event.validators == vec![validator_prefix.clone()]));
+        assert!(coverage.iter().all(|event| event.witnesses.is_empty()));
+
+        // Receipts are shown along with the KEL.
+        let formatted = ent.get_formatted_kerl()?;
+        assert_eq!(formatted.matches(&validator_prefix).count(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_anchor() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let mut ent = Entity::new(dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        let prefix: IdentifierPrefix = ent.get_prefix()?.parse()?;
+        let other_dir = tempdir()?;
+        let other = Entity::new(other_dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        let other_prefix: IdentifierPrefix = other.get_prefix()?.parse()?;
+        let other_icp = other.keri.get_event_at_sn(&other_prefix, 0)?.unwrap();
+
+        let seals = vec![
+            make_digest_seal(b"some data"),
+            make_digest_seal(b"other data"),
+            Seal::Event(EventSeal {
+                prefix: other_prefix.clone(),
+                sn: 0,
+                event_digest: SelfAddressing::Blake3_256.derive(&other_icp.serialize()?),
+            }),
+            Seal::Location(LocationSeal {
+                prefix: other_prefix,
+                sn: 1,
+                ilk: "ixn".into(),
+                prior_digest: SelfAddressing::Blake3_256.derive(&other_icp.serialize()?),
+            }),
+        ];
+        let anchor = ent.anchor(seals)?;
+        assert_eq!(anchor.prefix, prefix);
+        assert_eq!(anchor.sn, 1);
+        let ixn = ent.keri.get_event_at_sn(&prefix, 1)?.unwrap();
+        assert!(anchor.event_digest.verify_binding(&ixn.serialize()?));
+        match ixn.event.event_data {
+            EventData::Ixn(ixn) => assert_eq!(ixn.data.len(), 4),
+            _ => panic!("Not an interaction event"),
+        }
+
+        assert_eq!(ent.append("some data")?.sn, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_external_signing() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let path = dir.path().to_str().unwrap();
+        let ixn = {
+            let ent = Entity::open(path, "passphrase")?;
+            assert!(ent.get_pending()?.is_none());
+            let ixn = ent.prepare_anchor(vec![make_digest_seal(b"some data")])?;
+            // Only one event can wait for signatures.
+            assert!(ent.prepare_anchor(vec![]).is_err());
+            ixn
+        };
+
+        // Pending event is kept over restart.
+        let mut ent = Entity::open(path, "passphrase")?;
+        assert_eq!(ent.get_pending()?, Some(ixn.clone()));
+        assert!(ent.commit_pending().is_err());
+        ent.add_signatures(&ent.sign_indexed(from_utf8(&ixn).unwrap())?)?;
+        let seal = ent.commit_pending()?;
+        assert_eq!(seal.sn, 1);
+        assert!(ent.get_pending()?.is_none());
+
+        // Event prepared for outdated state can't be committed.
+        let ixn = ent.prepare_anchor(vec![])?;
+        ent.append("some data")?;
+        ent.add_signatures(&ent.sign_indexed(from_utf8(&ixn).unwrap())?)?;
+        assert!(ent.commit_pending().is_err());
+        ent.discard_pending()?;
+        assert!(ent.get_pending()?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_vc_anchor() -> Result<(), Error> {
+        use crate::tel::tel_event::TelEvent;
+
+        let dir = tempdir()?;
+        let mut ent = Entity::new(dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        let issuer: IdentifierPrefix = ent.get_prefix()?.parse()?;
+        let vc = b"Some vc";
+        let vc_id = SelfAddressing::Blake3_256.derive(vc).to_str();
+        let iss = TelEvent::issuance(&vc_id, "Eregistry")?;
+        let msg = String::from_utf8(iss.serialize()?)?;
+        let signatures = ent.sign_indexed(&msg)?;
+
+        // Issuance seal points at event which doesn't anchor it.
+        let unanchored = SignedTelEvent::new(iss.clone(), ent.append("Some vc")?, &signatures);
+        let tel = TEL::new().update(unanchored)?;
+        assert_eq!(ent.find_vc_anchor(&issuer, vc, &tel)?, None);
+
+        let seal = ent.anchor(vec![Seal::Event(EventSeal {
+            prefix: vc_id.parse()?,
+            sn: iss.sn,
+            event_digest: iss.digest()?,
+        })])?;
+        let anchor_sn = seal.sn;
+        let tel = TEL::new().update(SignedTelEvent::new(iss.clone(), seal, &signatures))?;
+        assert_eq!(ent.find_vc_anchor(&issuer, vc, &tel)?, Some(anchor_sn));
+        // Anchored issuance doesn't prove issuance of other vc.
+        assert_eq!(ent.find_vc_anchor(&issuer, b"Other vc", &tel)?, None);
+        assert_eq!(ent.find_vc_anchor(&issuer, vc, &TEL::new())?, None);
+
+        // Vc is anchored by issuer only, also after revocation.
+        let other_dir = tempdir()?;
+        let other = Entity::new(other_dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        let other_id: IdentifierPrefix = other.get_prefix()?.parse()?;
+        assert_eq!(ent.find_vc_anchor(&other_id, vc, &tel)?, None);
+        let rev = TelEvent::revocation(&vc_id, 1, "Eregistry", &iss.digest()?)?;
+        let rev_seal = ent.append("Revocation")?;
+        let revoked = tel.update(SignedTelEvent::new(rev, rev_seal, &signatures))?;
+        assert_eq!(ent.find_vc_anchor(&issuer, vc, &revoked)?, Some(anchor_sn));
+        assert_eq!(ent.find_vc_anchor(&other_id, vc, &revoked)?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_at() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let mut ent = Entity::new(dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        let prefix: IdentifierPrefix = ent.get_prefix()?.parse()?;
+        let msg = "hello there!";
+        let old_signatures = ent.sign_indexed(msg)?;
+        ent.update_keys()?;
+        ent.append("some data")?;
+        let signatures = ent.sign_indexed(msg)?;
+
+        // Current keys don't verify signature made before rotation.
+        assert!(!ent.verify(ent.get_own_state()?, msg, &old_signatures)?);
+        let verification = ent.verify_at(&prefix, msg, &old_signatures, 0)?;
+        assert!(verification.verified);
+        assert_eq!(verification.establishment_sn, Some(0));
+        assert!(verification.rotated);
+        // Key state at sn 2 is set by rotation of sn 1.
+        let verification = ent.verify_at(&prefix, msg, &signatures, 2)?;
+        assert!(verification.verified);
+        assert_eq!(verification.establishment_sn, Some(1));
+        assert!(!verification.rotated);
+        assert!(!ent.verify_at(&prefix, msg, &old_signatures, 2)?.verified);
+        // Keys at sn after the last known event are unknown.
+        assert!(matches!(
+            ent.verify_at(&prefix, msg, &signatures, 3),
+            Err(Error::ProtocolError(_))
+        ));
+
+        let verification = ent.verify_with_history(&prefix, msg, &old_signatures)?;
+        assert_eq!(verification.establishment_sn, Some(0));
+        assert!(verification.rotated);
+        let verification = ent.verify_with_history(&prefix, msg, &signatures)?;
+        assert_eq!(verification.establishment_sn, Some(1));
+        assert!(!verification.rotated);
+        let verification = ent.verify_with_history(&prefix, "other message", &signatures)?;
+        assert!(!verification.verified);
+        assert_eq!(verification.establishment_sn, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_secp256k1() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let path = dir.path().to_str().unwrap();
+        let mut ent = Entity::new_with_keys(
+            path,
+            KeyType::EcdsaSecp256k1Sha256,
+            1,
+            SignatureThreshold::Simple(1),
+        )?;
+        let prefix: IdentifierPrefix = ent.get_prefix()?.parse()?;
+
+        // Events signed with secp256k1 keys are accepted.
+        ent.update_keys()?;
+        ent.append("some data")?;
+        let state = ent.get_state_for_prefix(&prefix)?.unwrap();
+        assert_eq!(state.sn, 2);
+        assert_eq!(state.current.public_keys[0].derivation_code(), "1AAB");
+
+        let msg = "hello there!";
+        assert!(ent.verify(state.clone(), msg, &ent.sign_indexed(msg)?)?);
+        assert!(!ent.verify(state, "other message", &ent.sign_indexed(msg)?)?);
+
+        Ok(())
+    }
+
     #[test]
     fn test_signing() -> Result<(), Error> {
-        // let dir = tempdir().unwrap();
-        // let path = dir.path().to_str().unwrap();
-        // let _addresses_path = [path, "addresses"].join("");
-        // let seeds = "[
-        //     \"rwXoACJgOleVZ2PY7kXn7rA0II0mHYDhc6WrBH8fDAc=\",
-        //     \"6zz7M08-HQSFq92sJ8KJOT2cZ47x7pXFQLPB0pckB3Q=\"]";
-        // // "cwFTk-wgk3ZT2buPRIbK-zxgPx-TKbaegQvPEivN90Y=",
-        // // "lntkt3u6dDgiQxTATr01dy8M72uuaZEf9eTdM-70Gk8=",
-        // // "1-QxDkso9-MR1A8rZz_Naw6fgaAtayda8hrbkRVVu1E=",
-        // // "KuYMe09COczwf2nIoD5AE119n7GLFOVFlNLxZcKuswc=",
-        // // "xFfJTcSuEE11FINfXMqWttkZGnUZ8KaREhrnyAXTsjw=",
-        // // "Lq-w1UKkdrppwZzGTtz4PWYEeWm0-sDHzOv5sq96xJY="
-        // let mut ent = Entity::new_from_seeds(path, seeds.trim())?;
-
-        // let msg = "hello there!";
-        // let signature = ent.sign(msg)?;
-        // let _signature_b64 = base64::encode_config(signature, URL_SAFE);
-
-        // // let v = ent.verify(&ent.get_prefix()?, msg, &signature_b64)?;
-        // // assert!(v);
-
-        // ent.update_keys()?;
-        // // let v = ent.verify(&ent.get_prefix()?, msg, &signature_b64)?;
-        // // assert!(!v);
+        let seeds = r#"[
+            "rwXoACJgOleVZ2PY7kXn7rA0II0mHYDhc6WrBH8fDAc=",
+            "6zz7M08-HQSFq92sJ8KJOT2cZ47x7pXFQLPB0pckB3Q=",
+            "cwFTk-wgk3ZT2buPRIbK-zxgPx-TKbaegQvPEivN90Y="
+        ]"#;
+
+        let dir = tempdir()?;
+        let mut ent = Entity::new_from_seeds(dir.path().to_str().unwrap(), seeds)?;
+        let other_dir = tempdir()?;
+        let mut other_ent = Entity::new_from_seeds(other_dir.path().to_str().unwrap(), seeds)?;
+
+        // The same seeds give the same identifier and keys.
+        assert_eq!(ent.get_prefix()?, other_ent.get_prefix()?);
+        let msg = "hello there!";
+        assert_eq!(ent.sign(msg)?, other_ent.sign(msg)?);
+
+        ent.update_keys()?;
+        other_ent.update_keys()?;
+        assert_eq!(ent.get_kerl()?, other_ent.get_kerl()?);
+
+        assert!(Entity::new_from_seeds(tempdir()?.path().to_str().unwrap(), "[]").is_err());
 
         Ok(())
     }
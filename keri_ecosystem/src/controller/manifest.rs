@@ -0,0 +1,160 @@
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, storage::RecordLog};
+
+const MANIFEST_FILE: &str = "manifest";
+
+/// Identifier added to controller next to the main one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub prefix: String,
+    pub alias: String,
+    /// Directory of identifier's event database and keystore.
+    pub db_path: String,
+    /// Unix time of adding the identifier, in seconds.
+    pub created: u64,
+    pub delegated: bool,
+}
+
+impl ManifestEntry {
+    /// Makes entry with current time as creation time.
+    pub fn new(prefix: &str, alias: &str, db_path: &str, delegated: bool) -> Result<Self, Error> {
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| Error::Generic(e.to_string()))?
+            .as_secs();
+        Ok(Self {
+            prefix: prefix.into(),
+            alias: alias.into(),
+            db_path: db_path.into(),
+            created,
+            delegated,
+        })
+    }
+}
+
+/// Change of the manifest, as kept in log.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum ManifestRecord {
+    Add(ManifestEntry),
+    Remove { prefix: String },
+}
+
+/// List of identifiers managed by controller, so they can be opened again
+/// after restart. It's kept in file in the main identifier's event
+/// database directory.
+pub struct Manifest {
+    log: RecordLog<ManifestRecord>,
+}
+
+impl Manifest {
+    pub fn new(db_path: &str) -> Self {
+        Self {
+            log: RecordLog::new(Path::new(db_path).join(MANIFEST_FILE))
+                .with_legacy(legacy_manifest),
+        }
+    }
+
+    /// Adds identifier. Alias has to be unique.
+    pub fn add(&self, entry: &ManifestEntry) -> Result<(), Error> {
+        if self.get_by_alias(&entry.alias)?.is_some() {
+            return Err(Error::Generic(format!(
+                "Alias {} is already used",
+                entry.alias
+            )));
+        }
+        self.log.append(&ManifestRecord::Add(entry.clone()))
+    }
+
+    /// Removes identifier, so it isn't opened with the controller anymore.
+    pub fn remove(&self, prefix: &str) -> Result<(), Error> {
+        self.log.append(&ManifestRecord::Remove {
+            prefix: prefix.into(),
+        })
+    }
+
+    /// Returns identifiers in order of adding.
+    pub fn get_all(&self) -> Result<Vec<ManifestEntry>, Error> {
+        let mut entries: Vec<ManifestEntry> = vec![];
+        for record in self.log.load()? {
+            match record {
+                ManifestRecord::Add(entry) => entries.push(entry),
+                ManifestRecord::Remove { prefix } => entries.retain(|entry| entry.prefix != prefix),
+            }
+        }
+        Ok(entries)
+    }
+
+    pub fn get_by_alias(&self, alias: &str) -> Result<Option<ManifestEntry>, Error> {
+        Ok(self
+            .get_all()?
+            .into_iter()
+            .find(|entry| entry.alias == alias))
+    }
+}
+
+/// Reads manifest of the first version, which was json list of entries.
+fn legacy_manifest(content: &[u8]) -> Result<Vec<ManifestRecord>, Error> {
+    let entries: Vec<ManifestEntry> =
+        serde_json::from_slice(content).map_err(|e| Error::StorageError(e.to_string()))?;
+    Ok(entries.into_iter().map(ManifestRecord::Add).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_manifest() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let path = dir.path().to_str().unwrap();
+        let manifest = Manifest::new(path);
+        assert!(manifest.get_all()?.is_empty());
+
+        let entry = ManifestEntry::new("DA", "first", "/tmp/first", false)?;
+        manifest.add(&entry)?;
+        manifest.add(&ManifestEntry::new("DB", "second", "/tmp/second", true)?)?;
+        assert!(manifest
+            .add(&ManifestEntry::new("DC", "first", "/tmp/third", false)?)
+            .is_err());
+
+        // Entries are read back by other instance, as after restart.
+        let reopened = Manifest::new(path);
+        let entries = reopened.get_all()?;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0], entry);
+        assert_eq!(reopened.get_by_alias("second")?.unwrap().prefix, "DB");
+        assert!(reopened.get_by_alias("third")?.is_none());
+
+        reopened.remove("DA")?;
+        assert_eq!(manifest.get_all()?.len(), 1);
+        assert!(manifest.get_by_alias("first")?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_legacy_manifest() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let path = dir.path().to_str().unwrap();
+        let entry = ManifestEntry::new("DA", "first", "/tmp/first", false)?;
+        std::fs::write(
+            dir.path().join(MANIFEST_FILE),
+            serde_json::to_vec(&vec![entry.clone()]).unwrap(),
+        )?;
+
+        let manifest = Manifest::new(path);
+        assert_eq!(manifest.get_all()?, vec![entry]);
+        manifest.remove("DA")?;
+        assert!(manifest.get_all()?.is_empty());
+
+        Ok(())
+    }
+}
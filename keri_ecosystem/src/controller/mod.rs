@@ -1,40 +1,126 @@
 use std::{
     collections::HashMap,
+    fs,
     str::from_utf8,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, MutexGuard},
     thread::{self},
+    time::Duration,
 };
 
 use crate::{datum::{SignedAttestationDatum, create_attestation, sign_attestation}, tel::TEL};
 use acdc::{attestation::Attestation, datum::Message, signed_attestation::SignedAttestation};
 use base64::URL_SAFE;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
 use keri::{
-    derivation::self_addressing::SelfAddressing,
-    event::sections::seal::EventSeal,
-    prefix::{IdentifierPrefix, Prefix},
+    derivation::{self_addressing::SelfAddressing, self_signing::SelfSigning},
+    event::sections::{
+        seal::{DigestSeal, EventSeal, LocationSeal, Seal},
+        threshold::SignatureThreshold,
+    },
+    prefix::{AttachedSignaturePrefix, BasicPrefix, IdentifierPrefix, Prefix, SelfAddressingPrefix},
     signer::KeyManager,
     state::IdentifierState,
 };
 
 pub mod entity;
+pub mod manifest;
 
 use crate::{
     communication::tcp_communication::TCPCommunication,
-    controller::entity::Entity,
+    controller::{
+        entity::{verify_non_transferable, Entity},
+        manifest::{Manifest, ManifestEntry},
+    },
     error::Error,
+    kerl::{
+        duplicity::DuplicityReport,
+        event_generator::{is_non_transferable, make_digest_seal, PrefixType, WitnessConfig},
+    },
     tel::{
-        tel_event::{Operation, TelEvent},
+        tel_event::{SignedTelEvent, TelEvent},
         tel_manager::TelManager,
+        TelReply,
     },
+    wallet::Wallet,
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum SignatureState {
     Ok,
     Wrong,
     Revoked,
 }
 
+/// Status of identifier managed by controller.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdentifierStatus {
+    Active,
+    /// Identifier made its final rotation and its keys are wiped.
+    Decommissioned,
+}
+
+/// Identifier which receipted an event.
+#[derive(Debug, Clone, Serialize)]
+pub struct Receipt {
+    pub prefix: String,
+    /// Seal of receiptor's establishment event. Witnesses are
+    /// non-transferable, so they have none.
+    pub seal: Option<EventSeal>,
+}
+
+/// Receipts of event of given sn.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReceiptCoverage {
+    pub sn: u64,
+    /// Witnesses which receipted the event.
+    pub witnesses: Vec<String>,
+    /// Number of witness receipts needed to commit the event.
+    pub witness_threshold: u64,
+    /// Other identifiers which receipted the event.
+    pub validators: Vec<String>,
+}
+
+/// Result of verification against keys of some establishment event.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct KeyStateVerification {
+    pub verified: bool,
+    /// Sn of establishment event which keys were used. None if no keys
+    /// verified the signature.
+    pub establishment_sn: Option<u64>,
+    /// True if the keys were rotated since.
+    pub rotated: bool,
+}
+
+/// Identifier listed in manifest which couldn't be opened, along with the
+/// reason.
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedIdentifier {
+    pub entry: ManifestEntry,
+    pub error: String,
+}
+
+/// Result of vc verification.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VcVerification {
+    pub state: SignatureState,
+    /// Sn of issuer's KEL event which anchors vc issuance. None if vc isn't
+    /// anchored, then it's never valid.
+    pub anchored_at: Option<u64>,
+}
+
+impl VcVerification {
+    pub fn state(&self) -> SignatureState {
+        self.state
+    }
+
+    pub fn anchored_at(&self) -> Option<u64> {
+        self.anchored_at
+    }
+}
+
 #[derive(Clone)]
 pub struct SharedController {
     controller: Arc<Mutex<Controller>>,
@@ -47,7 +133,81 @@ impl SharedController {
                 db_path,
                 address,
                 address_store_path,
-            ))),
+                PrefixType::Basic,
+            )?)),
+        })
+    }
+
+    /// Creates controller with identifier prefix of given type, `basic`
+    /// or `self_addressing`.
+    pub fn new_with_prefix_type(
+        db_path: &str,
+        address: &str,
+        address_store_path: &str,
+        prefix_type: &str,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            controller: Arc::new(Mutex::new(Controller::new(
+                db_path,
+                address,
+                address_store_path,
+                prefix_type.parse()?,
+            )?)),
+        })
+    }
+
+    /// Opens controller of identifier stored in `db_path` or creates a new
+    /// one if there is none.
+    pub fn open(
+        db_path: &str,
+        address: &str,
+        address_store_path: &str,
+        passphrase: &str,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            controller: Arc::new(Mutex::new(Controller::open(
+                db_path,
+                address,
+                address_store_path,
+                passphrase,
+            )?)),
+        })
+    }
+
+    /// Creates controller of identifier with designated witnesses.
+    /// `witnesses` is json list of witness prefixes and `threshold` is
+    /// number of receipts required to fully commit an event.
+    pub fn new_with_witnesses(
+        db_path: &str,
+        address: &str,
+        address_store_path: &str,
+        witnesses: &str,
+        threshold: u64,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            controller: Arc::new(Mutex::new(Controller::new_with_witnesses(
+                db_path,
+                address,
+                address_store_path,
+                witnesses,
+                threshold,
+            )?)),
+        })
+    }
+
+    /// Creates witness. It only receipts events of identifiers which
+    /// designated it, once it's running.
+    pub fn new_witness(
+        db_path: &str,
+        address: &str,
+        address_store_path: &str,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            controller: Arc::new(Mutex::new(Controller::new_witness(
+                db_path,
+                address,
+                address_store_path,
+            )?)),
         })
     }
 
@@ -57,52 +217,296 @@ impl SharedController {
         })
     }
 
-    // pub fn new_from_seeds(
-    //     db_path: &str,
-    //     address: &str,
-    //     seeds: &str,
-    //     address_store_path: &str,
-    // ) -> Result<Self, Error> {
-    //     Ok(Self {
-    //         ent: Arc::new(Mutex::new(
-    //             Entity::new_from_seeds(db_path, address, seeds, address_store_path).unwrap(),
-    //         )),
-    //     })
-    // }
+    pub fn new_with_keys(
+        db_path: &str,
+        address: &str,
+        address_store_path: &str,
+        key_type: &str,
+        key_count: u32,
+        threshold: &str,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            controller: Arc::new(Mutex::new(Controller::new_with_keys(
+                db_path,
+                address,
+                address_store_path,
+                key_type,
+                key_count as usize,
+                threshold,
+            )?)),
+        })
+    }
+
+    pub fn new_from_seeds(
+        db_path: &str,
+        address: &str,
+        seeds: &str,
+        address_store_path: &str,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            controller: Arc::new(Mutex::new(Controller::new_from_seeds(
+                db_path,
+                address,
+                seeds,
+                address_store_path,
+            )?)),
+        })
+    }
+
+    pub fn new_from_mnemonic(
+        db_path: &str,
+        address: &str,
+        phrase: &str,
+        address_store_path: &str,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            controller: Arc::new(Mutex::new(Controller::new_from_mnemonic(
+                db_path,
+                address,
+                phrase,
+                address_store_path,
+            )?)),
+        })
+    }
+
+    pub fn generate_mnemonic() -> Result<String, Error> {
+        Ok(Wallet::generate_mnemonic())
+    }
+
+    fn lock(&self) -> Result<MutexGuard<Controller>, Error> {
+        self.controller
+            .lock()
+            .map_err(|_| Error::Generic("controller is poisoned".into()))
+    }
 
     // pub fn get_did_doc(&self, id: &str) -> Result<String, Error> {
-    //     let e = self.controller.lock().unwrap();
+    //     let e = self.lock()?;
     //     Ok(serde_json::to_string_pretty(&e.get_did_doc(id, &e.main_entity)?).unwrap())
     // }
 
     pub fn update_keys(&mut self) -> Result<(), Error> {
-        let mut e = self.controller.lock().unwrap();
+        let mut e = self.lock()?;
         e.update_keys()
     }
 
-    pub fn append(&mut self, msg: &str) -> Result<(), Error> {
-        let mut e = self.controller.lock().unwrap();
-        e.main_entity.append(msg)
+    pub fn update_witnesses(&mut self, witnesses: &str, threshold: u64) -> Result<(), Error> {
+        let mut e = self.lock()?;
+        e.update_witnesses(witnesses, threshold)
+    }
+
+    pub fn update_witnesses_for(
+        &mut self,
+        id: &str,
+        witnesses: &str,
+        threshold: u64,
+    ) -> Result<(), Error> {
+        let mut e = self.lock()?;
+        e.update_witnesses_for(id, witnesses, threshold)
+    }
+
+    pub fn collect_receipts(&self) -> Result<(), Error> {
+        let e = self.lock()?;
+        e.collect_receipts()
+    }
+
+    pub fn collect_receipts_for(&self, id: &str) -> Result<(), Error> {
+        let e = self.lock()?;
+        e.collect_receipts_for(id)
+    }
+
+    pub fn is_committed(&self, sn: u64) -> Result<bool, Error> {
+        let e = self.lock()?;
+        e.is_committed(sn)
+    }
+
+    pub fn is_committed_for(&self, id: &str, sn: u64) -> Result<bool, Error> {
+        let e = self.lock()?;
+        e.is_committed_for(id, sn)
+    }
+
+    /// Anchors digest of `msg` in KEL. Returns json with sn and digest of
+    /// the anchoring event.
+    pub fn append(&mut self, msg: &str) -> Result<String, Error> {
+        let mut e = self.lock()?;
+        format_anchor(&e.append(msg)?)
+    }
+
+    /// Anchors seals in KEL. `seals` is json list of seals, see
+    /// `parse_seals`. Returns json with sn and digest of the anchoring
+    /// event.
+    pub fn anchor(&mut self, seals: &str) -> Result<String, Error> {
+        let mut e = self.lock()?;
+        format_anchor(&e.anchor(parse_seals(seals)?)?)
+    }
+
+    /// Anchors digest of `msg` in KEL of given identifier, like `append`.
+    pub fn append_by(&mut self, id: &str, msg: &str) -> Result<String, Error> {
+        let mut e = self.lock()?;
+        format_anchor(&e.append_by(id, msg)?)
+    }
+
+    /// Anchors seals in KEL of given identifier, like `anchor`.
+    pub fn anchor_by(&mut self, id: &str, seals: &str) -> Result<String, Error> {
+        let mut e = self.lock()?;
+        format_anchor(&e.anchor_by(id, parse_seals(seals)?)?)
+    }
+
+    /// Prepares interaction event which anchors seals, to be signed
+    /// outside, for example on offline machine. `seals` is json list of
+    /// seals, like in `anchor`. Returns serialized event. It waits for
+    /// signatures until it's committed or discarded, also over restart.
+    pub fn prepare_anchor(&self, seals: &str) -> Result<String, Error> {
+        let e = self.lock()?;
+        e.prepare_anchor(parse_seals(seals)?)
+    }
+
+    pub fn prepare_anchor_by(&self, id: &str, seals: &str) -> Result<String, Error> {
+        let e = self.lock()?;
+        e.prepare_anchor_by(id, parse_seals(seals)?)
+    }
+
+    /// Returns serialized event which waits for signatures, or empty
+    /// string if there is none.
+    pub fn get_pending(&self) -> Result<String, Error> {
+        let e = self.lock()?;
+        Ok(e.get_pending()?.unwrap_or_default())
+    }
+
+    pub fn get_pending_for(&self, id: &str) -> Result<String, Error> {
+        let e = self.lock()?;
+        Ok(e.get_pending_for(id)?.unwrap_or_default())
+    }
+
+    /// Adds signatures of pending event, made for example with
+    /// `sign_indexed` on other machine.
+    pub fn add_signatures(&self, signatures: &str) -> Result<(), Error> {
+        let e = self.lock()?;
+        e.add_signatures(signatures)
+    }
+
+    pub fn add_signatures_by(&self, id: &str, signatures: &str) -> Result<(), Error> {
+        let e = self.lock()?;
+        e.add_signatures_by(id, signatures)
+    }
+
+    /// Commits pending event if collected signatures satisfy the
+    /// threshold. Returns json with sn and digest of the event.
+    pub fn commit_pending(&mut self) -> Result<String, Error> {
+        let mut e = self.lock()?;
+        format_anchor(&e.commit_pending()?)
+    }
+
+    pub fn commit_pending_by(&mut self, id: &str) -> Result<String, Error> {
+        let mut e = self.lock()?;
+        format_anchor(&e.commit_pending_by(id)?)
+    }
+
+    pub fn discard_pending(&self) -> Result<(), Error> {
+        let e = self.lock()?;
+        e.discard_pending()
+    }
+
+    pub fn discard_pending_by(&self, id: &str) -> Result<(), Error> {
+        let e = self.lock()?;
+        e.discard_pending_by(id)
     }
 
     pub fn get_prefix(&self) -> Result<String, Error> {
-        let e = self.controller.lock().unwrap();
+        let e = self.lock()?;
         e.main_entity.get_prefix()
     }
 
+    pub fn get_encryption_key(&self) -> Result<String, Error> {
+        let e = self.lock()?;
+        e.get_encryption_key()
+    }
+
+    pub fn get_encryption_key_for(&self, id: &str) -> Result<String, Error> {
+        let e = self.lock()?;
+        e.get_encryption_key_for(id)
+    }
+
+    /// Adds new identifier, with its database path as alias.
     pub fn add_identifier(&mut self, db_path: &str) -> Result<(), Error> {
-        let mut e = self.controller.lock().unwrap();
-        e.add_entity(db_path)
+        self.add_identifier_with_alias(db_path, db_path, false)?;
+        Ok(())
+    }
+
+    /// Adds new identifier under unique `alias` and returns its prefix.
+    /// If `delegated` is true, identifier is delegated by the main
+    /// identifier.
+    pub fn add_identifier_with_alias(
+        &mut self,
+        alias: &str,
+        db_path: &str,
+        delegated: bool,
+    ) -> Result<String, Error> {
+        let mut e = self.lock()?;
+        if delegated {
+            e.add_delegated_entity(alias, db_path)
+        } else {
+            e.add_entity(alias, db_path)
+        }
+    }
+
+    /// Returns json list of identifiers added with `add_identifier`, with
+    /// their prefix, alias, database path, creation time and whether they
+    /// are delegated.
+    pub fn identifiers(&self) -> Result<String, Error> {
+        let e = self.lock()?;
+        serde_json::to_string(&e.identifiers()?).map_err(|e| Error::Generic(e.to_string()))
+    }
+
+    /// Returns json list of identifiers which couldn't be opened, with
+    /// their manifest entry and the error.
+    pub fn skipped_identifiers(&self) -> Result<String, Error> {
+        let e = self.lock()?;
+        serde_json::to_string(&e.skipped_identifiers()).map_err(|e| Error::Generic(e.to_string()))
+    }
+
+    pub fn update_keys_for(&mut self, id: &str) -> Result<(), Error> {
+        let mut e = self.lock()?;
+        e.update_keys_for(id)
+    }
+
+    #[deprecated(note = "use `update_keys_for`")]
+    pub fn update_identifier_keys(&mut self, id: &str) -> Result<(), Error> {
+        self.update_keys_for(id)
     }
 
+    /// Stops managing identifier. It isn't decommissioned, see
+    /// `decommission_identifier`.
     pub fn remove_identifier(&mut self, id: &str) -> Result<(), Error> {
-        let mut e = self.controller.lock().unwrap();
+        let mut e = self.lock()?;
         e.remove_entity(id)
     }
 
     pub fn current_identifiers(&self) -> Result<Vec<String>, Error> {
-        let e = self.controller.lock().unwrap();
-        Ok(e.entities.keys().cloned().collect())
+        let e = self.lock()?;
+        Ok(e.current_identifiers())
+    }
+
+    /// Decommissions identifier. It's irreversible, the identifier can't
+    /// make any new events and its private keys are wiped.
+    pub fn decommission_identifier(&mut self, id: &str) -> Result<(), Error> {
+        let mut e = self.lock()?;
+        e.decommission_entity(id)
+    }
+
+    /// Returns status of identifier, `active` or `decommissioned`.
+    pub fn identifier_status(&self, id: &str) -> Result<String, Error> {
+        let e = self.lock()?;
+        Ok(match e.identifier_status(id)? {
+            IdentifierStatus::Active => "active",
+            IdentifierStatus::Decommissioned => "decommissioned",
+        }
+        .into())
+    }
+
+    /// Decommissions the main identifier.
+    pub fn decommission(&mut self) -> Result<(), Error> {
+        let mut e = self.lock()?;
+        e.decommission()
     }
 
     pub fn run(self) -> Result<(), Error> {
@@ -110,36 +514,131 @@ impl SharedController {
     }
 
     pub fn sign_by(&self, id: &str, msg: &str) -> Result<Vec<u8>, Error> {
-        let e = self.controller.lock().unwrap();
+        let e = self.lock()?;
         e.sign_by(id, msg)
     }
 
     pub fn sign(&self, msg: &str) -> Result<Vec<u8>, Error> {
-        let e = self.controller.lock().unwrap();
+        let e = self.lock()?;
         e.sign(msg)
     }
 
+    pub fn sign_indexed(&self, msg: &str) -> Result<String, Error> {
+        let e = self.lock()?;
+        e.sign_indexed(msg)
+    }
+
+    pub fn sign_indexed_by(&self, id: &str, msg: &str) -> Result<String, Error> {
+        let e = self.lock()?;
+        e.sign_indexed_by(id, msg)
+    }
+
     pub fn verify(&self, issuer_id: &str, msg: &str, signature: &str) -> Result<bool, Error> {
-        let e = self.controller.lock().unwrap();
+        let e = self.lock()?;
         e.verify(issuer_id, msg, signature)
     }
 
+    /// Verifies signature with keys which were current at given sn.
+    /// Returns json with verification result, sn of establishment event
+    /// which keys were used and whether they were rotated since.
+    pub fn verify_at(
+        &self,
+        issuer_id: &str,
+        msg: &str,
+        signature: &str,
+        sn: u64,
+    ) -> Result<String, Error> {
+        let e = self.lock()?;
+        serde_json::to_string(&e.verify_at(issuer_id, msg, signature, sn)?)
+            .map_err(|e| Error::Generic(e.to_string()))
+    }
+
+    /// Verifies signature with keys of any establishment event of issuer.
+    /// Returns json like `verify_at`.
+    pub fn verify_with_history(
+        &self,
+        issuer_id: &str,
+        msg: &str,
+        signature: &str,
+    ) -> Result<String, Error> {
+        let e = self.lock()?;
+        serde_json::to_string(&e.verify_with_history(issuer_id, msg, signature)?)
+            .map_err(|e| Error::Generic(e.to_string()))
+    }
+
     pub fn get_kerl(&self) -> Result<String, Error> {
-        let e = self.controller.lock().unwrap();
+        let e = self.lock()?;
         Ok(TCPCommunication::format_event_stream(
             &e.main_entity.get_kerl()?,
             false,
         ))
     }
 
+    pub fn get_kerl_for(&self, id: &str) -> Result<String, Error> {
+        let e = self.lock()?;
+        Ok(TCPCommunication::format_event_stream(
+            &e.get_kerl_for(id)?,
+            false,
+        ))
+    }
+
+    /// Returns json list of duplicity proofs of given identifier. Each
+    /// proof consists of accepted and conflicting signed event.
+    pub fn duplicity_reports(&self, prefix: &str) -> Result<String, Error> {
+        let e = self.lock()?;
+        serde_json::to_string(&e.duplicity_reports(prefix)?)
+            .map_err(|e| Error::Generic(e.to_string()))
+    }
+
+    /// Sets how many seconds received events wait in escrow for missing
+    /// events or signatures.
+    pub fn set_escrow_timeout(&self, seconds: u64) -> Result<(), Error> {
+        let e = self.lock()?;
+        e.set_escrow_timeout(seconds)
+    }
+
+    pub fn export_kel(&self, prefix: &str, path: &str) -> Result<(), Error> {
+        let e = self.lock()?;
+        e.export_kel(prefix, path)
+    }
+
+    pub fn import_kel(&self, path: &str) -> Result<(), Error> {
+        let e = self.lock()?;
+        e.import_kel(path)
+    }
+
+    /// Returns formatted KEL of the main identifier along with receipts of
+    /// its events.
     pub fn get_formatted_kerl(&self) -> Result<String, Error> {
-        let e = self.controller.lock().unwrap();
-        let kerl = &e.main_entity.get_kerl()?;
-        Ok(TCPCommunication::format_event_stream(kerl, false))
+        let e = self.lock()?;
+        e.main_entity.get_formatted_kerl()
+    }
+
+    /// Returns formatted KEL of given identifier managed by controller,
+    /// along with receipts of its events.
+    pub fn get_formatted_kerl_for(&self, id: &str) -> Result<String, Error> {
+        let e = self.lock()?;
+        e.entity(id)?.get_formatted_kerl()
+    }
+
+    /// Returns json list of identifiers which receipted event of given
+    /// identifier and sn, along with seals of their establishment events.
+    pub fn receipts(&self, prefix: &str, sn: u64) -> Result<String, Error> {
+        let e = self.lock()?;
+        serde_json::to_string(&e.receipts(prefix, sn)?)
+            .map_err(|e| Error::Generic(e.to_string()))
+    }
+
+    /// Returns json list of receipt summaries of each event of given
+    /// identifier.
+    pub fn receipt_coverage(&self, prefix: &str) -> Result<String, Error> {
+        let e = self.lock()?;
+        serde_json::to_string(&e.receipt_coverage(prefix)?)
+            .map_err(|e| Error::Generic(e.to_string()))
     }
 
     pub fn get_formatted_tel(&self, vc_dig: &str) -> Result<String, Error> {
-        let e = self.controller.lock().unwrap();
+        let e = self.lock()?;
         let vc_dig_vec = base64::decode_config(vc_dig, URL_SAFE)?;
         let tel = e.tels.get_tel(&vc_dig_vec)?;
         Ok(tel.to_string())
@@ -148,26 +647,65 @@ impl SharedController {
     pub fn verify_vc(
         &self,
         signed_datum: &SignedAttestationDatum,
-    ) -> Result<bool, Error> {
-        let e = self.controller.lock().unwrap();
+    ) -> Result<SignatureState, Error> {
+        let mut e = self.lock()?;
+        Ok(e.verify_vc(&signed_datum.sa)?.state)
+    }
+
+    /// Verifies vc as `verify_vc` does. Returns verification result along
+    /// with sn of issuer's KEL event which anchors vc issuance.
+    pub fn verify_vc_anchor(
+        &self,
+        signed_datum: &SignedAttestationDatum,
+    ) -> Result<VcVerification, Error> {
+        let mut e = self.lock()?;
         e.verify_vc(&signed_datum.sa)
     }
 
     // Returns signed acdc.
     pub fn issue_vc(&self, schema: &str, msg: &str) -> Result<SignedAttestationDatum, Error> {
-        let mut e = self.controller.lock().unwrap();
+        let mut e = self.lock()?;
         let pref = e.main_entity.get_prefix()?.to_string();
         let ad = create_attestation(&pref, &["did:", &pref,"/att_id"].join(""), msg, schema)?;
         let sad = e.issue_vc(&ad)?;
         Ok(SignedAttestationDatum {sa: sad})
     }
 
+    /// Issues vc by given identifier managed by controller, like
+    /// `issue_vc`.
+    pub fn issue_vc_by(
+        &self,
+        id: &str,
+        schema: &str,
+        msg: &str,
+    ) -> Result<SignedAttestationDatum, Error> {
+        let mut e = self.lock()?;
+        let ad = create_attestation(id, &["did:", id, "/att_id"].join(""), msg, schema)?;
+        let sad = e.issue_vc_by(id, &ad)?;
+        Ok(SignedAttestationDatum { sa: sad })
+    }
+
     pub fn revoke_vc(&self, msg: &str) -> Result<(), Error> {
-        let mut e = self.controller.lock().unwrap();
-        let ad: Attestation<String, Message, String> = serde_json::from_str(&msg).unwrap();
+        let mut e = self.lock()?;
+        let ad: Attestation<String, Message, String> =
+            serde_json::from_str(&msg).map_err(|e| Error::ProtocolError(e.to_string()))?;
         e.revoke_vc(&ad)
     }
 
+    /// Returns identifier of TEL registry of given identifier, which is
+    /// incepted on first use.
+    pub fn registry_by(&mut self, id: &str) -> Result<String, Error> {
+        let mut e = self.lock()?;
+        e.registry_by(id)
+    }
+
+    pub fn revoke_vc_by(&self, id: &str, msg: &str) -> Result<(), Error> {
+        let mut e = self.lock()?;
+        let ad: Attestation<String, Message, String> =
+            serde_json::from_str(&msg).map_err(|e| Error::ProtocolError(e.to_string()))?;
+        e.revoke_vc_by(id, &ad)
+    }
+
     pub fn sign_message(&self, msg: &str) -> Result<SignedAttestation<String, Message, String>, Error> {
         let pref = self.get_prefix()?;
         let ad = create_attestation(&pref.to_string(), "att_id", msg, "123")?;
@@ -183,214 +721,899 @@ pub struct Controller {
     comm: TCPCommunication,
     entities: HashMap<String, Entity>,
     tels: TelManager,
+    manifest: Manifest,
+    /// Identifiers added next to the main one, in order of adding. Only
+    /// those with stored keys are listed in manifest.
+    entries: Vec<ManifestEntry>,
+    /// Identifiers listed in manifest which couldn't be opened.
+    skipped: Vec<SkippedIdentifier>,
+    /// Passphrase of keystores. Keys of controller created without it
+    /// aren't stored.
+    passphrase: Option<String>,
 }
 
 impl Controller {
-    pub fn new(db_path: &str, adr: &str, adr_store_path: &str) -> Self {
-        let comm = TCPCommunication::new(adr, adr_store_path);
-        match &comm {
-            Ok(_) => {}
-            Err(e) => {
-                println!("\n{:?}\n", e);
-            }
-        }
-        let comm = comm.unwrap();
-        let ent = Entity::new(db_path).unwrap();
-        let pref = &ent.get_prefix().unwrap();
-        let entities = HashMap::new();
-        comm.register(pref).unwrap();
-        Controller {
-            main_entity: ent,
-            comm,
-            entities,
-            tels: TelManager::new(),
-        }
+    pub fn new(
+        db_path: &str,
+        adr: &str,
+        adr_store_path: &str,
+        prefix_type: PrefixType,
+    ) -> Result<Self, Error> {
+        let ent = Entity::new(db_path, prefix_type)?;
+        Self::with_entity(ent, db_path, adr, adr_store_path)
     }
 
-    pub fn add_entity(&mut self, db_path: &str) -> Result<(), Error> {
-        let ent = Entity::new(db_path)?;
-        let id = ent.get_prefix()?;
-        self.comm.register(&id)?;
-        match self.entities.insert(id, ent) {
-            Some(_) => Err(Error::Generic("Entity already exist".into())),
-            None => Ok(()),
+    /// Opens controller of identifier stored in `db_path` or creates a new
+    /// one if there is none. Keys are kept in keystore encrypted with
+    /// `passphrase`. Identifiers listed in manifest are opened too. Those
+    /// which can't be opened are skipped, see `skipped_identifiers`.
+    pub fn open(
+        db_path: &str,
+        adr: &str,
+        adr_store_path: &str,
+        passphrase: &str,
+    ) -> Result<Self, Error> {
+        let ent = Entity::open(db_path, passphrase)?;
+        let mut controller = Self::with_entity(ent, db_path, adr, adr_store_path)?;
+        controller.passphrase = Some(passphrase.into());
+        for entry in controller.manifest.get_all()? {
+            match Self::load_entity(&entry, passphrase) {
+                Ok(ent) => {
+                    controller.attach_entity(ent)?;
+                    controller.entries.push(entry);
+                }
+                Err(e) => {
+                    warn!("Identifier {} can't be opened: {}", entry.prefix, e);
+                    controller.skipped.push(SkippedIdentifier {
+                        entry,
+                        error: e.to_string(),
+                    });
+                }
+            }
         }
+        controller.recover_tel_events();
+        Ok(controller)
     }
 
-    pub fn remove_entity(&mut self, id: &str) -> Result<(), Error> {
-        match self.entities.remove(id.into()) {
-            Some(_) => Ok(()),
-            None => Err(Error::Generic("No such entity".into())),
+    fn load_entity(entry: &ManifestEntry, passphrase: &str) -> Result<Entity, Error> {
+        let ent = Entity::load(&entry.db_path, passphrase)?;
+        if ent.get_prefix()? != entry.prefix {
+            return Err(Error::StorageError(format!(
+                "{} doesn't contain identifier {}",
+                entry.db_path, entry.prefix
+            )));
         }
+        Ok(ent)
     }
 
-    pub fn sign_by(&self, id: &str, msg: &str) -> Result<Vec<u8>, Error> {
-        let ent = self.entities.get(id).unwrap();
-        ent.sign(msg)
+    /// Returns identifiers listed in manifest which couldn't be opened,
+    /// for example because their database was moved. They stay in
+    /// manifest, so they're opened again once the problem is fixed.
+    pub fn skipped_identifiers(&self) -> Vec<SkippedIdentifier> {
+        self.skipped.clone()
     }
 
-    pub fn sign(&self, msg: &str) -> Result<Vec<u8>, Error> {
-        self.main_entity.sign(msg)
+    /// Creates controller of identifier with `key_count` keys of
+    /// `key_type` (`ed25519` or `secp256k1`). `threshold` is json encoded
+    /// signing threshold, as in `kt` field of the event, for example `"2"`
+    /// or `["1/2", "1/2", "1/2"]`.
+    pub fn new_with_keys(
+        db_path: &str,
+        adr: &str,
+        adr_store_path: &str,
+        key_type: &str,
+        key_count: usize,
+        threshold: &str,
+    ) -> Result<Self, Error> {
+        let threshold: SignatureThreshold =
+            serde_json::from_str(threshold).map_err(|e| Error::ProtocolError(e.to_string()))?;
+        let ent = Entity::new_with_keys(db_path, key_type.parse()?, key_count, threshold)?;
+        Self::with_entity(ent, db_path, adr, adr_store_path)
     }
 
-    pub fn verify(&self, issuer_id: &str, msg: &str, signature: &str) -> Result<bool, Error> {
-        let ddoc = self.get_state(&issuer_id.parse::<IdentifierPrefix>()?, &self.main_entity)?.unwrap();
-
-        self.main_entity.verify(ddoc, msg, signature)
+    /// Creates controller of identifier incepted with keys derived from
+    /// seeds. `seeds` is json list of base64 encoded seeds.
+    pub fn new_from_seeds(
+        db_path: &str,
+        adr: &str,
+        seeds: &str,
+        adr_store_path: &str,
+    ) -> Result<Self, Error> {
+        let ent = Entity::new_from_seeds(db_path, seeds)?;
+        Self::with_entity(ent, db_path, adr, adr_store_path)
     }
 
-    pub fn update_keys(&mut self) -> Result<(), Error> {
-        self.main_entity.update_keys()
+    /// Creates controller of identifier incepted with keys derived from
+    /// BIP39 mnemonic phrase.
+    pub fn new_from_mnemonic(
+        db_path: &str,
+        adr: &str,
+        phrase: &str,
+        adr_store_path: &str,
+    ) -> Result<Self, Error> {
+        let ent = Entity::new_from_mnemonic(db_path, phrase)?;
+        Self::with_entity(ent, db_path, adr, adr_store_path)
     }
 
-    pub fn append(&mut self, msg: &str) -> Result<(), Error> {
-        self.main_entity.append(msg)
+    /// Creates controller of identifier with designated witnesses and
+    /// sends them the inception event. `witnesses` is json list of witness
+    /// prefixes.
+    pub fn new_with_witnesses(
+        db_path: &str,
+        adr: &str,
+        adr_store_path: &str,
+        witnesses: &str,
+        threshold: u64,
+    ) -> Result<Self, Error> {
+        let witness_config = parse_witness_config(witnesses, threshold)?;
+        let ent = Entity::new_with_witnesses(db_path, &witness_config)?;
+        let controller = Self::with_entity(ent, db_path, adr, adr_store_path)?;
+        controller.collect_receipts()?;
+        Ok(controller)
     }
 
-    pub fn get_kerl(&self) -> Result<Vec<u8>, Error> {
-        self.main_entity.get_kerl()
+    /// Creates controller of non-transferable identifier, which is meant
+    /// to act as witness only.
+    pub fn new_witness(db_path: &str, adr: &str, adr_store_path: &str) -> Result<Self, Error> {
+        let ent = Entity::new(db_path, PrefixType::NonTransferable)?;
+        Self::with_entity(ent, db_path, adr, adr_store_path)
     }
 
-    // pub fn get_did_doc(&self, id: &str, ent: &Entity) -> Result<DIDDocument, Error> {
-    //     let pref: IdentifierPrefix = id.parse().map_err(|e| Error::KeriError(e))?;
-    //     let state = self
-    //         .get_state(&pref, ent)?
-    //         .ok_or(Error::Generic(format!("There is no state for {}.", id)))?;
-    //     Ok(state_to_did_document(state, "keri"))
-    // }
+    fn with_entity(
+        ent: Entity,
+        db_path: &str,
+        adr: &str,
+        adr_store_path: &str,
+    ) -> Result<Self, Error> {
+        let comm = TCPCommunication::new(adr, adr_store_path)?;
+        let mut controller = Controller {
+            main_entity: ent,
+            comm,
+            entities: HashMap::new(),
+            tels: TelManager::new(db_path)?,
+            manifest: Manifest::new(db_path),
+            entries: vec![],
+            skipped: vec![],
+            passphrase: None,
+        };
+        controller.register(&controller.main_entity.get_prefix()?)?;
+        controller.recover_tel_events();
+        Ok(controller)
+    }
 
-    /// Make Transaction Event Log event.
-    ///
-    /// Construct TEL event for given operation and sign it.
-    fn make_tel_event(&self, operation: Operation) -> Result<TelEvent, Error> {
-        // Create event seal.
-        let event_seal = {
-            let prefix: IdentifierPrefix = self.main_entity.get_prefix()?.parse()?;
-            let state = self.get_state(&prefix, &self.main_entity)?.unwrap();
-            let sn = state.sn;
-            let last = state.last;
-            let event_digest = SelfAddressing::Blake3_256.derive(&last);
-
-            EventSeal {
-                prefix,
-                sn,
-                event_digest,
-            }
+    fn register(&self, prefix: &str) -> Result<(), Error> {
+        // Don't register the same address again after restart.
+        let registered = match self.comm.get_address_for_prefix(prefix) {
+            Ok(address) => address == Some(self.comm.get_address()),
+            Err(_) => false,
         };
+        if !registered {
+            self.comm.register(prefix)?;
+        }
+        Ok(())
+    }
 
-        // Then create tel event with that seal.
-        let mut tel_ev = TelEvent::new(event_seal, operation);
-        // Sign this event with current keys.
-        let msg = serde_json::to_vec(&tel_ev).unwrap();
-        let signature = self.sign(from_utf8(&msg).unwrap())?;
-        tel_ev.attach_signature(&signature);
+    /// Adds identifier under unique `alias` and returns its prefix.
+    pub fn add_entity(&mut self, alias: &str, db_path: &str) -> Result<String, Error> {
+        self.check_alias(alias)?;
+        let ent = Entity::new(db_path, PrefixType::Basic)?;
+        self.insert_entity(ent, alias, db_path, false)
+    }
 
-        Ok(tel_ev)
+    /// Adds identifier delegated by the main identifier. Its inception is
+    /// approved by seal anchored in the main identifier's KEL.
+    pub fn add_delegated_entity(&mut self, alias: &str, db_path: &str) -> Result<String, Error> {
+        self.check_alias(alias)?;
+        let ent = Entity::new_delegated(db_path, &mut self.main_entity)?;
+        self.collect_receipts()?;
+        self.insert_entity(ent, alias, db_path, true)
     }
 
-    pub fn issue_vc(&mut self, vc: &Attestation<String, Message, String>) -> Result<SignedAttestation<String, Message, String>, Error> {
-        // Sign vc.
-        let vc_str = serde_json::to_string(&vc)
-            .map_err(|_e| Error::Generic("Can't serialize attestation datum".into()))?;
-        let signature = self.sign(&vc_str)?;
-        let signed_vc =  sign_attestation(vc.to_owned(), signature);
+    fn check_alias(&self, alias: &str) -> Result<(), Error> {
+        let used = self.entries.iter().any(|entry| entry.alias == alias)
+            || self.manifest.get_by_alias(alias)?.is_some();
+        if used {
+            Err(Error::Generic(format!("Alias {} is already used", alias)))
+        } else {
+            Ok(())
+        }
+    }
 
-        // Append interaction event to KEL.
-        self.main_entity.append(&vc_str)?;
+    /// Stores keys of new identifier, if controller keeps them, and then
+    /// lists it in manifest. Identifiers which keys aren't stored can't be
+    /// opened after restart, so they aren't listed.
+    fn insert_entity(
+        &mut self,
+        mut ent: Entity,
+        alias: &str,
+        db_path: &str,
+        delegated: bool,
+    ) -> Result<String, Error> {
+        let id = ent.get_prefix()?;
+        if self.entities.contains_key(&id) {
+            return Err(Error::Generic("Entity already exist".into()));
+        }
+        let entry = ManifestEntry::new(&id, alias, db_path, delegated)?;
+        if let Some(passphrase) = &self.passphrase {
+            ent.save_keys(db_path, passphrase)?;
+            self.manifest.add(&entry)?;
+        }
+        self.attach_entity(ent)?;
+        self.entries.push(entry);
+        Ok(id)
+    }
 
-        let vc_digest = blake3::hash(&vc_str.as_bytes()).as_bytes().to_vec();
-        let issuance_event = self.make_tel_event(Operation::Issue)?;
+    fn attach_entity(&mut self, ent: Entity) -> Result<(), Error> {
+        ent.set_escrow_timeout(self.main_entity.get_escrow_timeout()?)?;
+        let id = ent.get_prefix()?;
+        self.register(&id)?;
+        self.entities.insert(id, ent);
+        Ok(())
+    }
 
-        // Update tels.
-        self.tels.process_tel_event(&vc_digest, issuance_event)?;
-        signed_vc
+    /// Returns identifiers added with `add_entity` or
+    /// `add_delegated_entity`, in order of adding.
+    pub fn identifiers(&self) -> Result<Vec<ManifestEntry>, Error> {
+        Ok(self.entries.clone())
     }
 
-    pub fn revoke_vc(&mut self, vc: &Attestation<String, Message, String>) -> Result<(), Error> {
-        let vc_str = serde_json::to_string(&vc)
-            .map_err(|_e| Error::Generic("Can't serialize attestation datum".into()))?;
+    /// Returns entity of the main identifier or of identifier added with
+    /// `add_entity` or `add_delegated_entity`.
+    fn entity(&self, id: &str) -> Result<&Entity, Error> {
+        if id == self.main_entity.get_prefix()? {
+            return Ok(&self.main_entity);
+        }
+        self.entities
+            .get(id)
+            .ok_or_else(|| Error::UnknownIdentifier(id.into()))
+    }
 
-        // Append interaction event to KEL.
-        self.main_entity.append(&vc_str)?;
+    fn entity_mut(&mut self, id: &str) -> Result<&mut Entity, Error> {
+        if id == self.main_entity.get_prefix()? {
+            return Ok(&mut self.main_entity);
+        }
+        self.entities
+            .get_mut(id)
+            .ok_or_else(|| Error::UnknownIdentifier(id.into()))
+    }
 
-        let vc_digest = blake3::hash(&vc_str.as_bytes()).as_bytes().to_vec();
-        let revocation_event = self.make_tel_event(Operation::Revoke)?;
+    /// Rotates keys of given identifier. Rotation of identifier delegated
+    /// by the main identifier is approved by it.
+    pub fn update_keys_for(&mut self, id: &str) -> Result<(), Error> {
+        let main_prefix = self.main_entity.get_prefix()?;
+        if id == main_prefix {
+            return self.update_keys();
+        }
+        let ent = self
+            .entities
+            .get_mut(id)
+            .ok_or_else(|| Error::UnknownIdentifier(id.into()))?;
+        if ent.get_delegator()? == Some(main_prefix.parse()?) {
+            ent.update_delegated_keys(&mut self.main_entity)?;
+            // Both the approving event and the rotation go to witnesses.
+            self.collect_receipts()?;
+            self.collect_receipts_for(id)
+        } else {
+            ent.update_keys()?;
+            self.collect_receipts_for(id)
+        }
+    }
 
-        // Update tels.
-        self.tels.process_tel_event(&vc_digest, revocation_event)?;
+    /// Stops managing identifier added with `add_entity`. Its KEL and
+    /// keys are left in its database directory, so it stays usable.
+    pub fn remove_entity(&mut self, id: &str) -> Result<(), Error> {
+        if self.entities.remove(id).is_none() {
+            return Err(Error::UnknownIdentifier(id.into()));
+        }
+        self.entries.retain(|entry| entry.prefix != id);
+        if self.passphrase.is_some() {
+            self.manifest.remove(id)?;
+        }
         Ok(())
     }
 
-    pub fn verify_vc(
-        &self,
-        signed_datum: &SignedAttestation<String, Message, String>,
-    ) -> Result<bool, Error> {
-        let issuer = &signed_datum.get_id().testator_id.get_id();
-        let pref: IdentifierPrefix = issuer.split(":").collect::<Vec<_>>()[1].parse()?;
+    /// Returns prefixes of identifiers added with `add_entity`.
+    pub fn current_identifiers(&self) -> Vec<String> {
+        self.entities.keys().cloned().collect()
+    }
 
-        let state = self.get_state(&pref, &self.main_entity)?.unwrap();
+    /// Decommissions identifier added with `add_entity` and publishes the
+    /// final rotation to its witnesses. It's kept, so its status can be
+    /// reported, but it can't be used anymore.
+    pub fn decommission_entity(&mut self, id: &str) -> Result<(), Error> {
+        match self.entities.get_mut(id) {
+            Some(ent) => ent.decommission()?,
+            None => return Err(Error::UnknownIdentifier(id.into())),
+        };
+        self.collect_receipts_for(id)
+    }
 
-        let key = state.current.public_keys.get(0).unwrap().derivative().to_vec();
-        let mut key_map = HashMap::new();
-        key_map.insert(issuer.to_owned(), key);
+    /// Returns status of the main identifier or of identifier added with
+    /// `add_entity`.
+    pub fn identifier_status(&self, id: &str) -> Result<IdentifierStatus, Error> {
+        Ok(if self.entity(id)?.is_decommissioned()? {
+            IdentifierStatus::Decommissioned
+        } else {
+            IdentifierStatus::Active
+        })
+    }
 
-        // // Ask issuer for tel
-        // let address = self.comm.get_address_for_prefix(&issuer)?.unwrap();
-        // let tel: TEL = {
-        //     let tel_vec = TCPCommunication::ask_for_tel(attestation_datum.as_bytes(), &address)?;
-        //     let tel_str = from_utf8(&tel_vec).map_err(|e| Error::Generic(e.to_string()))?;
-        //     serde_json::from_str(tel_str.trim()).map_err(|e| Error::Generic(e.to_string()))?
-        // };
+    /// Decommissions the main identifier and publishes the final rotation
+    /// to witnesses.
+    pub fn decommission(&mut self) -> Result<(), Error> {
+        self.main_entity.decommission()?;
+        self.collect_receipts()
+    }
 
-        signed_datum.verify(&vec![], &key_map).map_err(|e| Error::Generic(e.to_string()))
+    pub fn sign_by(&self, id: &str, msg: &str) -> Result<Vec<u8>, Error> {
+        self.entity(id)?.sign(msg)
     }
 
-    pub fn sign_message(&mut self, msg: &str) -> Result<SignedAttestation<String, Message, String>, Error> {
-        let attestation_datum = create_attestation(&self.main_entity.get_prefix()?, "att_id", msg, "1234")?;
-        let signed_attestation_datum = self.issue_vc(&attestation_datum)?;
+    pub fn sign(&self, msg: &str) -> Result<Vec<u8>, Error> {
+        self.main_entity.sign(msg)
+    }
 
-        Ok(signed_attestation_datum)
+    /// Signs message with all current keys. Returns attached signatures
+    /// concatenated into one string.
+    pub fn sign_indexed(&self, msg: &str) -> Result<String, Error> {
+        self.sign_indexed_by(&self.get_prefix()?, msg)
     }
 
-    pub fn run(controller: Arc<Mutex<Controller>>) -> Result<(), Error> {
-        let address = {
-            let cont = controller.lock().unwrap();
+    /// Signs message with all current keys of given identifier, like
+    /// `sign_indexed`.
+    pub fn sign_indexed_by(&self, id: &str, msg: &str) -> Result<String, Error> {
+        Ok(self
+            .entity(id)?
+            .sign_indexed(msg)?
+            .iter()
+            .map(|sig| sig.to_str())
+            .collect::<Vec<_>>()
+            .join(""))
+    }
+
+    /// Verifies signature of given identifier. `signature` may be base64
+    /// encoded signature made with the first key or attached signatures
+    /// returned by `sign_indexed`.
+    pub fn verify(&self, issuer_id: &str, msg: &str, signature: &str) -> Result<bool, Error> {
+        let issuer: IdentifierPrefix = issuer_id.parse()?;
+        let signatures = parse_signatures(signature)?;
+        // Non-transferable prefix is the key itself, there is no need to
+        // ask for KEL.
+        if is_non_transferable(&issuer) {
+            return verify_non_transferable(&issuer, msg, &signatures);
+        }
+        let ddoc = self
+            .get_state(&issuer, &self.main_entity)?
+            .ok_or_else(|| Error::KelNotFound(issuer.to_str()))?;
+
+        self.main_entity.verify(ddoc, msg, &signatures)
+    }
+
+    /// Verifies signature with keys which were current at given sn of
+    /// issuer's KEL.
+    pub fn verify_at(
+        &self,
+        issuer_id: &str,
+        msg: &str,
+        signature: &str,
+        sn: u64,
+    ) -> Result<KeyStateVerification, Error> {
+        let issuer: IdentifierPrefix = issuer_id.parse()?;
+        let signatures = parse_signatures(signature)?;
+        if is_non_transferable(&issuer) {
+            return verify_non_transferable(&issuer, msg, &signatures)
+                .map(non_transferable_verification);
+        }
+        self.get_state(&issuer, &self.main_entity)?;
+        self.main_entity.verify_at(&issuer, msg, &signatures, sn)
+    }
+
+    /// Verifies signature with keys of any establishment event of issuer,
+    /// so documents signed before rotation can be verified too.
+    pub fn verify_with_history(
+        &self,
+        issuer_id: &str,
+        msg: &str,
+        signature: &str,
+    ) -> Result<KeyStateVerification, Error> {
+        let issuer: IdentifierPrefix = issuer_id.parse()?;
+        let signatures = parse_signatures(signature)?;
+        if is_non_transferable(&issuer) {
+            return verify_non_transferable(&issuer, msg, &signatures)
+                .map(non_transferable_verification);
+        }
+        self.get_state(&issuer, &self.main_entity)?;
+        self.main_entity.verify_with_history(&issuer, msg, &signatures)
+    }
+
+    pub fn update_keys(&mut self) -> Result<(), Error> {
+        self.main_entity.update_keys()?;
+        self.collect_receipts()
+    }
+
+    /// Rotates keys and designates new witnesses. `witnesses` is json list
+    /// of witness prefixes.
+    pub fn update_witnesses(&mut self, witnesses: &str, threshold: u64) -> Result<(), Error> {
+        self.update_witnesses_for(&self.get_prefix()?, witnesses, threshold)
+    }
+
+    /// Rotates keys of given identifier and designates its new witnesses.
+    pub fn update_witnesses_for(
+        &mut self,
+        id: &str,
+        witnesses: &str,
+        threshold: u64,
+    ) -> Result<(), Error> {
+        let witness_config = parse_witness_config(witnesses, threshold)?;
+        self.entity_mut(id)?.update_witnesses(&witness_config)?;
+        self.collect_receipts_for(id)
+    }
+
+    /// Anchors digest of `msg` in KEL. Returns seal of the anchoring
+    /// event.
+    pub fn append(&mut self, msg: &str) -> Result<EventSeal, Error> {
+        self.append_by(&self.get_prefix()?, msg)
+    }
+
+    /// Anchors digest of `msg` in KEL of given identifier.
+    pub fn append_by(&mut self, id: &str, msg: &str) -> Result<EventSeal, Error> {
+        let seal = self.entity_mut(id)?.append(msg)?;
+        self.collect_receipts_for(id)?;
+        Ok(seal)
+    }
+
+    /// Anchors seals in KEL. Returns seal of the anchoring event.
+    pub fn anchor(&mut self, seals: Vec<Seal>) -> Result<EventSeal, Error> {
+        self.anchor_by(&self.get_prefix()?, seals)
+    }
+
+    /// Anchors seals in KEL of given identifier.
+    pub fn anchor_by(&mut self, id: &str, seals: Vec<Seal>) -> Result<EventSeal, Error> {
+        let seal = self.entity_mut(id)?.anchor(seals)?;
+        self.collect_receipts_for(id)?;
+        Ok(seal)
+    }
+
+    /// Prepares interaction event which anchors seals, to be signed
+    /// outside. Returns serialized event.
+    pub fn prepare_anchor(&self, seals: Vec<Seal>) -> Result<String, Error> {
+        self.prepare_anchor_by(&self.get_prefix()?, seals)
+    }
+
+    /// Prepares interaction event of given identifier, like
+    /// `prepare_anchor`.
+    pub fn prepare_anchor_by(&self, id: &str, seals: Vec<Seal>) -> Result<String, Error> {
+        Ok(String::from_utf8(self.entity(id)?.prepare_anchor(seals)?)?)
+    }
+
+    /// Returns serialized event which waits for signatures, if there is
+    /// one.
+    pub fn get_pending(&self) -> Result<Option<String>, Error> {
+        self.get_pending_for(&self.get_prefix()?)
+    }
+
+    pub fn get_pending_for(&self, id: &str) -> Result<Option<String>, Error> {
+        self.entity(id)?
+            .get_pending()?
+            .map(|event| String::from_utf8(event).map_err(Error::from))
+            .transpose()
+    }
+
+    /// Adds detached signatures of pending event. `signatures` are
+    /// concatenated attached signatures, as returned by `sign_indexed`.
+    pub fn add_signatures(&self, signatures: &str) -> Result<(), Error> {
+        self.add_signatures_by(&self.get_prefix()?, signatures)
+    }
+
+    pub fn add_signatures_by(&self, id: &str, signatures: &str) -> Result<(), Error> {
+        self.entity(id)?
+            .add_signatures(&parse_signatures(signatures)?)
+    }
+
+    /// Processes pending event with collected signatures. Returns seal of
+    /// the event.
+    pub fn commit_pending(&mut self) -> Result<EventSeal, Error> {
+        self.commit_pending_by(&self.get_prefix()?)
+    }
+
+    pub fn commit_pending_by(&mut self, id: &str) -> Result<EventSeal, Error> {
+        let seal = self.entity_mut(id)?.commit_pending()?;
+        self.collect_receipts_for(id)?;
+        Ok(seal)
+    }
+
+    /// Drops pending event along with its signatures.
+    pub fn discard_pending(&self) -> Result<(), Error> {
+        self.discard_pending_by(&self.get_prefix()?)
+    }
+
+    pub fn discard_pending_by(&self, id: &str) -> Result<(), Error> {
+        self.entity(id)?.discard_pending()
+    }
+
+    /// Sends KEL to current witnesses and stores receipts they return.
+    pub fn collect_receipts(&self) -> Result<(), Error> {
+        self.collect_receipts_for(&self.get_prefix()?)
+    }
+
+    /// Sends KEL of given identifier to its witnesses and stores receipts
+    /// they return. Receipts which can't be processed are logged and
+    /// skipped, since the events they receipt are already accepted.
+    pub fn collect_receipts_for(&self, id: &str) -> Result<(), Error> {
+        let ent = self.entity(id)?;
+        let kerl = ent.get_kerl()?;
+        for witness in ent.get_witness_config()?.witnesses {
+            let witness_id = IdentifierPrefix::Basic(witness).to_str();
+            // Unreachable witness is skipped, receipts can be collected
+            // again later.
+            let address = match self.comm.get_address_for_prefix(&witness_id) {
+                Ok(Some(address)) => address,
+                _ => continue,
+            };
+            match TCPCommunication::publish(&kerl, &address, &witness_id) {
+                Ok(response) => {
+                    if let Err(e) = ent.process_witness_receipts(&response) {
+                        warn!("Receipts of witness {} rejected: {}", witness_id, e);
+                    }
+                }
+                Err(_) => continue,
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns true if event of given sn is receipted by enough witnesses.
+    pub fn is_committed(&self, sn: u64) -> Result<bool, Error> {
+        self.main_entity.is_committed(sn)
+    }
+
+    /// Returns true if event of given identifier and sn is receipted by
+    /// enough witnesses.
+    pub fn is_committed_for(&self, id: &str, sn: u64) -> Result<bool, Error> {
+        self.entity(id)?.is_committed(sn)
+    }
+
+    pub fn get_kerl(&self) -> Result<Vec<u8>, Error> {
+        self.main_entity.get_kerl()
+    }
+
+    /// Returns KEL of given identifier managed by controller.
+    pub fn get_kerl_for(&self, id: &str) -> Result<Vec<u8>, Error> {
+        self.entity(id)?.get_kerl()
+    }
+
+    /// Writes KEL of identifier along with its receipts to file, as signed
+    /// event stream.
+    pub fn export_kel(&self, prefix: &str, path: &str) -> Result<(), Error> {
+        let prefix: IdentifierPrefix = prefix.parse()?;
+        fs::write(path, self.entity_for(&prefix).export_kel(&prefix)?)?;
+        Ok(())
+    }
+
+    /// Returns identifiers which receipted event of given identifier and
+    /// sn.
+    pub fn receipts(&self, prefix: &str, sn: u64) -> Result<Vec<Receipt>, Error> {
+        let prefix: IdentifierPrefix = prefix.parse()?;
+        self.entity_for(&prefix).get_receipts(&prefix, sn)
+    }
+
+    /// Returns summary of receipts of each event of given identifier.
+    pub fn receipt_coverage(&self, prefix: &str) -> Result<Vec<ReceiptCoverage>, Error> {
+        let prefix: IdentifierPrefix = prefix.parse()?;
+        self.entity_for(&prefix).get_receipt_coverage(&prefix)
+    }
+
+    /// Returns entity which manages given identifier. Identifiers added
+    /// with `add_entity` keep KEL in their own database, KELs of others
+    /// are kept by the main entity.
+    fn entity_for(&self, prefix: &IdentifierPrefix) -> &Entity {
+        self.entities
+            .get(&prefix.to_str())
+            .unwrap_or(&self.main_entity)
+    }
+
+    /// Reads KEL written by `export_kel` and validates it. Imported KEL is
+    /// available for verification without asking its controller.
+    pub fn import_kel(&self, path: &str) -> Result<(), Error> {
+        self.main_entity.import_kel(&fs::read(path)?)
+    }
+
+    // pub fn get_did_doc(&self, id: &str, ent: &Entity) -> Result<DIDDocument, Error> {
+    //     let pref: IdentifierPrefix = id.parse().map_err(|e| Error::KeriError(e))?;
+    //     let state = self
+    //         .get_state(&pref, ent)?
+    //         .ok_or(Error::Generic(format!("There is no state for {}.", id)))?;
+    //     Ok(state_to_did_document(state, "keri"))
+    // }
+
+    /// Returns identifier of TEL registry of given identifier. Registry is
+    /// incepted, with inception anchored in KEL, on first use.
+    pub fn registry_by(&mut self, id: &str) -> Result<String, Error> {
+        if let Some(registry) = self.tels.get_registry_of(id) {
+            return Ok(registry.get_id().to_owned());
+        }
+        let inception = TelEvent::registry_inception(id)?;
+        let registry_id = inception.prefix.clone();
+        self.anchor_tel_event(id, inception)?;
+        Ok(registry_id)
+    }
+
+    /// Anchors seal of TEL event in KEL of given identifier, signs the
+    /// event with its keys and processes it. Event is logged before it's
+    /// anchored, so it's accepted on restart if processing is interrupted,
+    /// see `recover_tel_events`.
+    fn anchor_tel_event(&mut self, id: &str, event: TelEvent) -> Result<(), Error> {
+        let tel_seal = EventSeal {
+            prefix: event.prefix.parse()?,
+            sn: event.sn,
+            event_digest: event.digest()?,
+        };
+        self.tels.check(id, &event)?;
+        self.tels.prepare(id, &event)?;
+        let anchored = self.entity_mut(id)?.anchor(vec![Seal::Event(tel_seal)]);
+        let seal = match anchored {
+            Ok(seal) => seal,
+            Err(e) => {
+                self.tels.abandon(&event)?;
+                return Err(e);
+            }
+        };
+        let signatures = self
+            .entity(id)?
+            .sign_indexed(&String::from_utf8(event.serialize()?)?)?;
+        self.process_tel_event(SignedTelEvent::new(event, seal, &signatures))?;
+        self.collect_receipts_for(id)
+    }
+
+    /// Accepts own TEL events which were anchored in KEL, but not stored
+    /// before restart, and abandons those which weren't anchored. Events
+    /// of identifiers which aren't opened wait for the next time.
+    fn recover_tel_events(&mut self) {
+        let prepared = match self.tels.get_prepared() {
+            Ok(prepared) => prepared,
+            Err(e) => {
+                warn!("Can't read prepared TEL events: {}", e);
+                return;
+            }
+        };
+        for (issuer, event) in prepared {
+            if let Err(e) = self.recover_tel_event(&issuer, event) {
+                warn!("Can't recover TEL event of {}: {}", issuer, e);
+            }
+        }
+    }
+
+    fn recover_tel_event(&mut self, issuer: &str, event: TelEvent) -> Result<(), Error> {
+        let entity = match self.entity(issuer) {
+            Ok(entity) => entity,
+            Err(_) => return Ok(()),
+        };
+        match entity.find_tel_anchor(&event)? {
+            Some(seal) => {
+                let signatures = entity.sign_indexed(&String::from_utf8(event.serialize()?)?)?;
+                self.process_tel_event(SignedTelEvent::new(event, seal, &signatures))
+            }
+            None => self.tels.abandon(&event),
+        }
+    }
+
+    /// Processes TEL event, checking it against KEL of its issuer.
+    fn process_tel_event(&mut self, event: SignedTelEvent) -> Result<(), Error> {
+        let issuer = &event.seal.prefix;
+        // Fields are borrowed separately, as `tels` is updated.
+        let issuer_kel = self
+            .entities
+            .get(&issuer.to_str())
+            .unwrap_or(&self.main_entity);
+        if issuer_kel.get_state_for_prefix(issuer)?.is_none() {
+            return Err(Error::KelNotFound(issuer.to_str()));
+        }
+        self.tels.process_tel_event(event, issuer_kel)
+    }
+
+    pub fn issue_vc(&mut self, vc: &Attestation<String, Message, String>) -> Result<SignedAttestation<String, Message, String>, Error> {
+        self.issue_vc_by(&self.get_prefix()?, vc)
+    }
+
+    /// Signs vc with all current keys of given identifier and issues it in
+    /// identifier's registry. Issuance event is anchored in KEL. Proof of
+    /// the vc holds attached signatures, as returned by `sign_indexed`.
+    pub fn issue_vc_by(
+        &mut self,
+        id: &str,
+        vc: &Attestation<String, Message, String>,
+    ) -> Result<SignedAttestation<String, Message, String>, Error> {
+        // Sign vc.
+        let vc_str = serde_json::to_string(&vc)
+            .map_err(|_e| Error::Generic("Can't serialize attestation datum".into()))?;
+        let signatures = self.sign_indexed_by(id, &vc_str)?;
+        let signed_vc = sign_attestation(vc.to_owned(), signatures.into_bytes());
+
+        let registry_id = self.registry_by(id)?;
+        let vc_id = SelfAddressing::Blake3_256
+            .derive(vc_str.as_bytes())
+            .to_str();
+        let issuance_event = TelEvent::issuance(&vc_id, &registry_id)?;
+        self.anchor_tel_event(id, issuance_event)?;
+        signed_vc
+    }
+
+    pub fn revoke_vc(&mut self, vc: &Attestation<String, Message, String>) -> Result<(), Error> {
+        self.revoke_vc_by(&self.get_prefix()?, vc)
+    }
+
+    /// Records revocation of vc issued by given identifier. Revocation
+    /// event is anchored in KEL.
+    pub fn revoke_vc_by(
+        &mut self,
+        id: &str,
+        vc: &Attestation<String, Message, String>,
+    ) -> Result<(), Error> {
+        let vc_str = serde_json::to_string(&vc)
+            .map_err(|_e| Error::Generic("Can't serialize attestation datum".into()))?;
+        let vc_id = SelfAddressing::Blake3_256.derive(vc_str.as_bytes());
+
+        let revocation_event = {
+            let last = self
+                .tels
+                .get_tel(&vc_id.derivative())
+                .ok()
+                .and_then(|tel| tel.get_events().last())
+                .ok_or_else(|| Error::TelStateConflict("VC is not issued".into()))?;
+            let registry_id = last
+                .event
+                .registry_id
+                .as_ref()
+                .ok_or_else(|| Error::ProtocolError("missing registry identifier".into()))?;
+            TelEvent::revocation(
+                &vc_id.to_str(),
+                last.event.sn + 1,
+                registry_id,
+                &last.event.digest()?,
+            )?
+        };
+        self.anchor_tel_event(id, revocation_event)?;
+        Ok(())
+    }
+
+    /// Updates TEL of vc with events known by its issuer. If issuer can't
+    /// be reached or its reply is broken, TEL known so far is used. Events
+    /// which can't be accepted are skipped.
+    fn update_tel(&mut self, issuer: &IdentifierPrefix, vc: &str) -> Result<(), Error> {
+        // TELs of own identifiers are always up to date.
+        if self.entity(&issuer.to_str()).is_ok() {
+            return Ok(());
+        }
+        let address = match self.comm.get_address_for_prefix(&issuer.to_str())? {
+            Some(address) => address,
+            None => return Ok(()),
+        };
+        let reply = TCPCommunication::ask_for_tel(vc.as_bytes(), &address).and_then(|reply| {
+            serde_json::from_slice::<TelReply>(&reply)
+                .map_err(|e| Error::ProtocolError(e.to_string()))
+        });
+        let reply = match reply {
+            Ok(reply) => reply,
+            Err(e) => {
+                warn!("Can't get TEL from {}: {}", issuer.to_str(), e);
+                return Ok(());
+            }
+        };
+        // Events anchoring TEL events may be newer than known KEL.
+        if !reply.kel.is_empty() {
+            if let Err(e) = self.main_entity.import_kel(reply.kel.as_bytes()) {
+                warn!("Can't import KEL of {}: {}", issuer.to_str(), e);
+            }
+        }
+        for event in reply.events {
+            if self.tels.is_known(&event).unwrap_or(false) {
+                continue;
+            }
+            if let Err(e) = self.process_tel_event(event) {
+                warn!("TEL event of {} rejected: {}", issuer.to_str(), e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks vc TEL, asking issuer for its latest events first, and
+    /// verifies vc signature with keys current at issuance. Vc stays valid
+    /// after issuer rotates keys. Issuance has to be anchored in issuer's
+    /// KEL, as proof that issuer publicly committed to the vc.
+    pub fn verify_vc(
+        &mut self,
+        signed_datum: &SignedAttestation<String, Message, String>,
+    ) -> Result<VcVerification, Error> {
+        let issuer = &signed_datum.get_id().testator_id.get_id();
+        let pref: IdentifierPrefix = issuer
+            .split(":")
+            .nth(1)
+            .ok_or_else(|| Error::ProtocolError(format!("improper issuer id {}", issuer)))?
+            .parse()?;
+
+        self.get_state(&pref, &self.main_entity)?
+            .ok_or_else(|| Error::KelNotFound(pref.to_str()))?;
+        self.main_entity.check_duplicity(&pref)?;
+
+        let vc_str = signed_datum.get_attestation_datum().to_string();
+        self.update_tel(&pref, &vc_str)?;
+        let vc_dig = blake3::hash(vc_str.as_bytes()).as_bytes().to_vec();
+        let empty_tel = TEL::new();
+        let tel = self.tels.get_tel(&vc_dig).unwrap_or(&empty_tel);
+        let unanchored = VcVerification {
+            state: SignatureState::Wrong,
+            anchored_at: None,
+        };
+        // Vc has to be issued by its signer.
+        let issuer_kel = self.entity_for(&pref);
+        let anchored_at = match issuer_kel.find_vc_anchor(&pref, vc_str.as_bytes(), tel)? {
+            Some(sn) => sn,
+            None => return Ok(unanchored),
+        };
+
+        let signature = signed_datum
+            .get_signature()
+            .map_err(|e| Error::InvalidSignature(e.to_string()))?;
+        let signatures = parse_vc_signature(&signature)?;
+        Ok(VcVerification {
+            state: issuer_kel.verify_vc(vc_str.as_bytes(), &signatures, tel)?,
+            anchored_at: Some(anchored_at),
+        })
+    }
+
+    pub fn sign_message(&mut self, msg: &str) -> Result<SignedAttestation<String, Message, String>, Error> {
+        let attestation_datum = create_attestation(&self.main_entity.get_prefix()?, "att_id", msg, "1234")?;
+        let signed_attestation_datum = self.issue_vc(&attestation_datum)?;
+
+        Ok(signed_attestation_datum)
+    }
+
+    pub fn run(controller: Arc<Mutex<Controller>>) -> Result<(), Error> {
+        let address = {
+            let cont = controller
+                .lock()
+                .map_err(|_| Error::Generic("controller is poisoned".into()))?;
             cont.comm.get_address()
         };
         thread::spawn(move || {
-            TCPCommunication::run(address, controller).expect("server runing failure");
+            if let Err(e) = TCPCommunication::run(address, controller) {
+                error!("Server stopped: {}", e);
+            }
         });
         Ok(())
     }
 
     pub fn parse_message(&self, message: &[u8]) -> Result<Vec<u8>, Error> {
-        let msg_str = from_utf8(message).map_err(|e| Error::Generic(e.to_string()))?;
+        let msg_str = from_utf8(message).map_err(|e| Error::ProtocolError(e.to_string()))?;
         let mut splitter = msg_str.splitn(2, ' ');
         let command = splitter
             .next()
-            .ok_or(Error::Generic("Improper message format".into()))?;
+            .ok_or(Error::ProtocolError("improper message format".into()))?;
         let arg = splitter
             .next()
-            .ok_or(Error::Generic("Improper message format".into()))?;
+            .ok_or(Error::ProtocolError("improper message format".into()))?;
 
         match command {
             "tel" => {
                 let vc_dig = blake3::hash(arg.as_bytes()).as_bytes().to_vec();
-                // let vc_dig_b64 = base64::encode(vc_dig).as_bytes().to_vec();
-                let tel = self.tels.get_tel(&vc_dig)?;
-                serde_json::to_vec(tel).map_err(|e| Error::Generic(e.to_string()))
+                let events = self.tels.get_tel_events(&vc_dig)?;
+                let kel = match events.first() {
+                    Some(vcp) => String::from_utf8(
+                        self.entity_for(&vcp.seal.prefix)
+                            .export_kel(&vcp.seal.prefix)?,
+                    )?,
+                    None => String::new(),
+                };
+                let reply = serde_json::to_vec(&TelReply { kel, events })
+                    .map_err(|e| Error::Generic(e.to_string()))?;
+                Ok(TCPCommunication::frame(&reply))
             }
             _ => {
                 println!(
                     "{}",
                     TCPCommunication::format_event_stream(arg.as_bytes(), true)
                 );
-                let ent = if command == self.main_entity.get_prefix().unwrap() {
-                    &self.main_entity
-                } else {
-                    self.entities.get(command).unwrap()
-                };
-                ent.respond(arg.as_bytes())
+                self.entity(command)?.respond(arg.as_bytes())
             }
         }
     }
@@ -400,28 +1623,59 @@ impl Controller {
         id: &IdentifierPrefix,
         entity: &Entity,
     ) -> Result<Option<IdentifierState>, Error> {
-        match entity.get_state_for_prefix(id)? {
-            Some(state) => Ok(Some(state)),
+        let state = match entity.get_state_for_prefix(id)? {
+            Some(state) => Some(state),
             None => {
                 let kerl = entity.get_kerl()?;
-                let addr =
-                    self.comm
-                        .get_address_for_prefix(&id.to_str())?
-                        .ok_or(Error::Generic(format!(
-                            "Can't find address for prefix {}",
-                            id.to_str()
-                        )))?;
+                let addr = self
+                    .comm
+                    .get_address_for_prefix(&id.to_str())?
+                    .ok_or_else(|| Error::AddressNotFound(id.to_str()))?;
                 TCPCommunication::send(&kerl, &addr, &id.to_str(), entity)?;
 
-                Ok(entity.get_state_for_prefix(id)?)
+                entity.get_state_for_prefix(id)?
             }
+        };
+        // State of delegated identifier is valid only if its events are
+        // approved by delegator.
+        if state.is_some() {
+            entity.check_delegation(id)?;
         }
+        Ok(state)
     }
 
     pub fn get_prefix(&self) -> Result<String, Error> {
         self.main_entity.get_prefix()
     }
 
+    /// Returns X25519 key agreement key of the main identifier, derived
+    /// from its first current key.
+    pub fn get_encryption_key(&self) -> Result<String, Error> {
+        self.main_entity.get_encryption_key()
+    }
+
+    /// Returns X25519 key agreement key of given identifier.
+    pub fn get_encryption_key_for(&self, id: &str) -> Result<String, Error> {
+        self.entity(id)?.get_encryption_key()
+    }
+
+    /// Returns proofs of duplicity of given identifier, which were found
+    /// while processing its events.
+    pub fn duplicity_reports(&self, prefix: &str) -> Result<Vec<DuplicityReport>, Error> {
+        self.main_entity.get_duplicity_reports(&prefix.parse()?)
+    }
+
+    /// Sets escrow timeout of all identifiers. Out of order and partially
+    /// signed events are dropped after that many seconds.
+    pub fn set_escrow_timeout(&self, seconds: u64) -> Result<(), Error> {
+        let timeout = Duration::from_secs(seconds);
+        self.main_entity.set_escrow_timeout(timeout)?;
+        for ent in self.entities.values() {
+            ent.set_escrow_timeout(timeout)?;
+        }
+        Ok(())
+    }
+
     pub fn get_current_pk(&self) -> Vec<u8> {
         self.main_entity.wallet.public_key().0.clone()
     }
@@ -437,9 +1691,176 @@ impl Controller {
     // }
 }
 
+/// Parses signature of vc proof. It's either attached signatures or raw
+/// signature made with the first key, as in vcs issued before.
+fn parse_vc_signature(signature: &[u8]) -> Result<Vec<AttachedSignaturePrefix>, Error> {
+    if signature.len() == 64 {
+        return parse_signatures(&base64::encode_config(signature, URL_SAFE));
+    }
+    parse_signatures(from_utf8(signature).map_err(|e| Error::InvalidSignature(e.to_string()))?)
+}
+
+/// Parses signature string. It can be base64 encoded ed25519 signature of
+/// the first key or concatenated attached signatures.
+fn parse_signatures(signature: &str) -> Result<Vec<AttachedSignaturePrefix>, Error> {
+    // Attached signature of all supported key types is 88 characters long.
+    const ATTACHED_SIGNATURE_LEN: usize = 88;
+    match base64::decode_config(signature, URL_SAFE) {
+        Ok(raw) if raw.len() == 64 => Ok(vec![AttachedSignaturePrefix::new(
+            SelfSigning::Ed25519Sha512,
+            raw,
+            0,
+        )]),
+        _ => signature
+            .as_bytes()
+            .chunks(ATTACHED_SIGNATURE_LEN)
+            .map(|chunk| -> Result<AttachedSignaturePrefix, Error> {
+                Ok(from_utf8(chunk)
+                    .map_err(|e| Error::InvalidSignature(e.to_string()))?
+                    .parse()?)
+            })
+            .collect(),
+    }
+}
+
+/// Keys of non-transferable identifier are set by its only establishment
+/// event and never rotated.
+fn non_transferable_verification(verified: bool) -> KeyStateVerification {
+    KeyStateVerification {
+        verified,
+        establishment_sn: if verified { Some(0) } else { None },
+        rotated: false,
+    }
+}
+
+/// Seal as passed in json.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum SealInput {
+    /// Digest of data. Exactly one of data as text, data as raw bytes or
+    /// precomputed digest prefix has to be given.
+    Digest {
+        #[serde(default)]
+        data: Option<String>,
+        #[serde(default)]
+        bytes: Option<Vec<u8>>,
+        #[serde(default)]
+        digest: Option<String>,
+    },
+    /// Event of some identifier.
+    Event {
+        prefix: String,
+        sn: u64,
+        digest: String,
+    },
+    /// Location of some identifier's event in its KEL.
+    Location {
+        prefix: String,
+        sn: u64,
+        ilk: String,
+        prior_digest: String,
+    },
+}
+
+/// Parses json list of seals, for example
+/// `[{"type": "digest", "data": "some data"},
+/// {"type": "digest", "bytes": [115, 111, 109, 101]},
+/// {"type": "digest", "digest": "E..."},
+/// {"type": "event", "prefix": "D...", "sn": 1, "digest": "E..."},
+/// {"type": "location", "prefix": "D...", "sn": 2, "ilk": "ixn",
+/// "prior_digest": "E..."}]`.
+fn parse_seals(seals: &str) -> Result<Vec<Seal>, Error> {
+    let seals: Vec<SealInput> =
+        serde_json::from_str(seals).map_err(|e| Error::ProtocolError(e.to_string()))?;
+    seals
+        .into_iter()
+        .map(|seal| {
+            Ok(match seal {
+                SealInput::Digest {
+                    data: Some(data),
+                    bytes: None,
+                    digest: None,
+                } => make_digest_seal(data.as_bytes()),
+                SealInput::Digest {
+                    data: None,
+                    bytes: Some(bytes),
+                    digest: None,
+                } => make_digest_seal(&bytes),
+                SealInput::Digest {
+                    data: None,
+                    bytes: None,
+                    digest: Some(digest),
+                } => Seal::Digest(DigestSeal {
+                    dig: digest.parse::<SelfAddressingPrefix>()?,
+                }),
+                SealInput::Digest { .. } => {
+                    return Err(Error::ProtocolError(
+                        "digest seal needs one of data, bytes or digest".into(),
+                    ))
+                }
+                SealInput::Event { prefix, sn, digest } => Seal::Event(EventSeal {
+                    prefix: prefix.parse()?,
+                    sn,
+                    event_digest: digest.parse::<SelfAddressingPrefix>()?,
+                }),
+                SealInput::Location {
+                    prefix,
+                    sn,
+                    ilk,
+                    prior_digest,
+                } => Seal::Location(LocationSeal {
+                    prefix: prefix.parse()?,
+                    sn,
+                    ilk,
+                    prior_digest: prior_digest.parse::<SelfAddressingPrefix>()?,
+                }),
+            })
+        })
+        .collect()
+}
+
+/// Formats seal of anchoring event as json with its sn and digest.
+fn format_anchor(seal: &EventSeal) -> Result<String, Error> {
+    serde_json::to_string(&serde_json::json!({
+        "sn": seal.sn,
+        "digest": seal.event_digest.to_str(),
+    }))
+    .map_err(|e| Error::Generic(e.to_string()))
+}
+
+/// Parses json list of witness prefixes.
+fn parse_witness_config(witnesses: &str, threshold: u64) -> Result<WitnessConfig, Error> {
+    let witnesses: Vec<String> =
+        serde_json::from_str(witnesses).map_err(|e| Error::ProtocolError(e.to_string()))?;
+    let witnesses = witnesses
+        .iter()
+        .map(|w| w.parse::<BasicPrefix>().map_err(Error::from))
+        .collect::<Result<Vec<_>, _>>()?;
+    WitnessConfig::new(witnesses, threshold)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::net::{TcpListener, TcpStream};
+
+    /// Returns address with port which is free at the moment, so tests
+    /// running in parallel don't collide.
+    fn free_address() -> Result<String, Error> {
+        let listener = TcpListener::bind("localhost:0")?;
+        Ok(format!("localhost:{}", listener.local_addr()?.port()))
+    }
+
+    /// Waits until controller started with `run` listens on `address`.
+    fn wait_for_listener(address: &str) -> Result<(), Error> {
+        for _ in 0..100 {
+            if TcpStream::connect(address).is_ok() {
+                return Ok(());
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        Err(Error::Generic(format!("nothing listens on {}", address)))
+    }
 
     #[test]
     fn test_vc() -> Result<(), Error> {
@@ -449,48 +1870,613 @@ mod tests {
         let db_path = db_dir.path().to_str().unwrap();
         let adr_store_path = [db_dir.path().to_str().unwrap(), "adr"].join("");
 
-        let mut cont = Controller::new(db_path, "localhost:1212", &adr_store_path);
+        let mut cont = Controller::new(db_path, "localhost:1212", &adr_store_path, PrefixType::Basic)?;
 
         // Compute vc related stuff
         let msg = "Some message";
 
         let attestation_datum = create_attestation(&cont.main_entity.get_prefix()?, &["did:", &cont.get_prefix()?, "/fdfd/dsds"].join(""), msg, "321")?; //AttestationDatum::new(msg, &cont.main_entity.get_prefix()?);
         let signed_attestation_datum = cont.issue_vc(&attestation_datum)?;
-        let ad_str = serde_json::to_string(&attestation_datum).unwrap();
-        // let vc_signature = signed_attestation_datum.get_signature()?;
 
         let vc_digest = blake3::hash(serde_json::to_string(&attestation_datum).unwrap().as_bytes()).as_bytes().to_vec();
 
+        let issuance_sn = match cont.tels.get_state(&vc_digest)? {
+            TelState::Issued(seal) => seal.sn,
+            _ => panic!("VC should be issued"),
+        };
+
+        let ver = cont.verify_vc(&signed_attestation_datum)?;
+        assert_eq!(ver.state, SignatureState::Ok);
+        // Issuance is anchored by the event TEL points at.
+        assert_eq!(ver.anchored_at, Some(issuance_sn));
+
+        // Rotate keys and verify vc again. It was signed with keys current
+        // at issuance, so it's still valid.
+        cont.update_keys()?;
+        let ver = cont.verify_vc(&signed_attestation_datum)?;
+        assert_eq!(ver.state, SignatureState::Ok);
+
+        // Vc signed with new keys, but not issued, is wrong.
+        let not_issued = create_attestation(
+            &cont.get_prefix()?,
+            &["did:", &cont.get_prefix()?, "/not_issued"].join(""),
+            msg,
+            "321",
+        )?;
+        let not_issued_str = serde_json::to_string(&not_issued).unwrap();
+        let signed_not_issued = sign_attestation(not_issued, cont.sign(&not_issued_str)?)?;
+        let ver = cont.verify_vc(&signed_not_issued)?;
+        assert_eq!(ver.state, SignatureState::Wrong);
+        assert_eq!(ver.anchored_at, None);
+
+        cont.revoke_vc(&attestation_datum)?;
+
         let vc_state = cont.tels.get_state(&vc_digest)?;
-        assert!(matches!(vc_state, TelState::Issued(_)));
+        assert!(matches!(vc_state, TelState::Revoked));
 
         let ver = cont.verify_vc(&signed_attestation_datum)?;
-        // let ver = {
-        //     let tel = cont.tels.get_tel(&vc_digest)?;
-        //     cont.main_entity
-        //         .verify_vc(ad_str.as_bytes(), &vc_signature, tel)?
-        // };
-        assert!(ver);
-
-        // // Rotate keys and verify vc again.
-        // cont.update_keys()?;
-        // let ver = {
-        //     let tel = cont.tels.get_tel(&vc_digest)?;
-        //     cont.main_entity
-        //         .verify_vc(ad_str.as_bytes(), &vc_signature, tel)?
-        // };
-        // assert!(matches!(ver, SignatureState::Ok));
-
-        // cont.revoke_vc(&attestation_datum)?;
-
-        // let vc_state = cont.tels.get_state(&vc_digest)?;
-        // assert!(matches!(vc_state, TelState::Revoked));
-
-        // let tel = cont.tels.get_tel(&vc_digest)?;
-        // let ver = cont
-        //     .main_entity
-        //     .verify_vc(ad_str.as_bytes(), &vc_signature, tel)?;
-        // assert!(matches!(ver, SignatureState::Revoked));
+        assert_eq!(ver.state, SignatureState::Revoked);
+        assert_eq!(ver.anchored_at, Some(issuance_sn));
+
+        // TEL event of identifier which KEL isn't known is rejected.
+        let unknown: IdentifierPrefix = "DhaJFfaW1aoKXZospMvka-VdQlmj0BQd4HlL3JvCOUg8".parse()?;
+        let vcp = TelEvent::registry_inception(&unknown.to_str())?;
+        let seal = EventSeal {
+            prefix: unknown.clone(),
+            sn: 1,
+            event_digest: vcp.digest()?,
+        };
+        assert!(matches!(
+            cont.process_tel_event(SignedTelEvent::new(vcp, seal, &[])),
+            Err(Error::KelNotFound(id)) if id == unknown.to_str()
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open() -> Result<(), Error> {
+        use tempfile::tempdir;
+        let db_dir = tempdir()?;
+        let db_path = db_dir.path().to_str().unwrap();
+        let adr_store_path = [db_dir.path().to_str().unwrap(), "adr"].join("");
+
+        let prefix = {
+            let mut cont = Controller::open(db_path, "localhost:1313", &adr_store_path, "pass")?;
+            cont.update_keys()?;
+            cont.get_prefix()?
+        };
+
+        let mut cont = Controller::open(db_path, "localhost:1313", &adr_store_path, "pass")?;
+        assert_eq!(cont.get_prefix()?, prefix);
+        cont.update_keys()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_with_identifiers() -> Result<(), Error> {
+        use tempfile::tempdir;
+        let db_dir = tempdir()?;
+        let db_path = db_dir.path().to_str().unwrap();
+        let adr_store_path = [db_dir.path().to_str().unwrap(), "adr"].join("");
+        let entity_dir = tempdir()?;
+        let delegated_dir = tempdir()?;
+        let msg = "Some message";
+
+        let (id, delegated_id, signature) = {
+            let mut cont = Controller::open(db_path, "localhost:1771", &adr_store_path, "pass")?;
+            let id = cont.add_entity("tenant", entity_dir.path().to_str().unwrap())?;
+            let delegated_id =
+                cont.add_delegated_entity("branch", delegated_dir.path().to_str().unwrap())?;
+            assert!(cont
+                .add_entity("tenant", tempdir()?.path().to_str().unwrap())
+                .is_err());
+            cont.update_keys_for(&id)?;
+            (id.clone(), delegated_id, cont.sign_by(&id, msg)?)
+        };
+
+        let mut cont = Controller::open(db_path, "localhost:1771", &adr_store_path, "pass")?;
+        let identifiers = cont.identifiers()?;
+        assert_eq!(identifiers.len(), 2);
+        assert_eq!(identifiers[0].prefix, id);
+        assert_eq!(identifiers[0].alias, "tenant");
+        assert!(!identifiers[0].delegated);
+        assert_eq!(identifiers[1].prefix, delegated_id);
+        assert!(identifiers[1].delegated);
+        // Reloaded identifier signs with keys it rotated to.
+        assert_eq!(cont.sign_by(&id, msg)?, signature);
+        cont.sign_by(&delegated_id, msg)?;
+
+        // Removed identifier isn't opened again.
+        cont.remove_entity(&delegated_id)?;
+        assert_eq!(cont.current_identifiers(), vec![id.clone()]);
+        drop(cont);
+        let cont = Controller::open(db_path, "localhost:1771", &adr_store_path, "pass")?;
+        assert_eq!(cont.current_identifiers(), vec![id.clone()]);
+        drop(cont);
+
+        // Identifier which can't be opened is skipped and reported.
+        std::fs::remove_dir_all(entity_dir.path())?;
+        let cont = Controller::open(db_path, "localhost:1771", &adr_store_path, "pass")?;
+        assert!(cont.current_identifiers().is_empty());
+        let skipped = cont.skipped_identifiers();
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].entry.prefix, id);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_identifiers_without_keystore() -> Result<(), Error> {
+        use tempfile::tempdir;
+        let db_dir = tempdir()?;
+        let db_path = db_dir.path().to_str().unwrap();
+        let adr_store_path = [db_dir.path().to_str().unwrap(), "adr"].join("");
+        let entity_dir = tempdir()?;
+
+        // Keys of controller made without passphrase aren't stored, so
+        // its identifiers aren't listed in manifest.
+        let mut cont = Controller::new(
+            db_path,
+            "localhost:1776",
+            &adr_store_path,
+            PrefixType::Basic,
+        )?;
+        let id = cont.add_entity("tenant", entity_dir.path().to_str().unwrap())?;
+        assert_eq!(cont.identifiers()?[0].prefix, id);
+        assert!(cont
+            .add_entity("tenant", tempdir()?.path().to_str().unwrap())
+            .is_err());
+        assert!(cont.manifest.get_all()?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tel_after_restart() -> Result<(), Error> {
+        use crate::tel::tel_event::TelState;
+        use tempfile::tempdir;
+        let db_dir = tempdir()?;
+        let db_path = db_dir.path().to_str().unwrap();
+        let adr_store_path = [db_dir.path().to_str().unwrap(), "adr"].join("");
+
+        let (issued_digest, revoked_digest, interrupted_digest) = {
+            let mut cont = Controller::open(db_path, "localhost:1773", &adr_store_path, "pass")?;
+            let prefix = cont.get_prefix()?;
+            let issued = create_attestation(&prefix, "did:issued", "Issued vc", "321")?;
+            let revoked = create_attestation(&prefix, "did:revoked", "Revoked vc", "321")?;
+            cont.issue_vc(&issued)?;
+            cont.issue_vc(&revoked)?;
+            cont.revoke_vc(&revoked)?;
+            let digest = |vc: &Attestation<String, Message, String>| {
+                blake3::hash(serde_json::to_string(vc).unwrap().as_bytes())
+                    .as_bytes()
+                    .to_vec()
+            };
+
+            // Issuance anchored just before restart, but not stored.
+            let registry_id = cont.registry_by(&prefix)?;
+            let vc_id = |vc: &[u8]| SelfAddressing::Blake3_256.derive(vc).to_str();
+            let interrupted = TelEvent::issuance(&vc_id(b"Interrupted vc"), &registry_id)?;
+            cont.tels.prepare(&prefix, &interrupted)?;
+            cont.main_entity.anchor(vec![Seal::Event(EventSeal {
+                prefix: interrupted.prefix.parse()?,
+                sn: interrupted.sn,
+                event_digest: interrupted.digest()?,
+            })])?;
+            // Issuance prepared, but never anchored.
+            let unanchored = TelEvent::issuance(&vc_id(b"Unanchored vc"), &registry_id)?;
+            cont.tels.prepare(&prefix, &unanchored)?;
+
+            (digest(&issued), digest(&revoked), interrupted.vc_digest()?)
+        };
+
+        // TELs are rebuilt from stored events.
+        let cont = Controller::open(db_path, "localhost:1773", &adr_store_path, "pass")?;
+        assert!(matches!(
+            cont.tels.get_state(&issued_digest)?,
+            TelState::Issued(_)
+        ));
+        assert!(matches!(
+            cont.tels.get_state(&revoked_digest)?,
+            TelState::Revoked
+        ));
+        cont.tels.get_tel(&revoked_digest)?;
+        // Anchored event is accepted and the other one abandoned.
+        assert!(matches!(
+            cont.tels.get_state(&interrupted_digest)?,
+            TelState::Issued(_)
+        ));
+        assert!(cont.tels.get_prepared()?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encryption_key() -> Result<(), Error> {
+        use tempfile::tempdir;
+        let db_dir = tempdir()?;
+        let db_path = db_dir.path().to_str().unwrap();
+        let adr_store_path = [db_dir.path().to_str().unwrap(), "adr"].join("");
+        let cont = Controller::new(
+            db_path,
+            "localhost:1774",
+            &adr_store_path,
+            PrefixType::Basic,
+        )?;
+
+        let key: BasicPrefix = cont.get_encryption_key()?.parse()?;
+        assert_eq!(key.derivation_code(), "C");
+        assert_eq!(
+            cont.get_encryption_key_for(&cont.get_prefix()?)?,
+            key.to_str()
+        );
+        assert!(matches!(
+            cont.get_encryption_key_for("Dunknown"),
+            Err(Error::UnknownIdentifier(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_transferable_verification() -> Result<(), Error> {
+        use tempfile::tempdir;
+        let db_dir = tempdir()?;
+        let db_path = db_dir.path().to_str().unwrap();
+        let adr_store_path = [db_dir.path().to_str().unwrap(), "adr"].join("");
+        let cont = Controller::new(
+            db_path,
+            "localhost:1515",
+            &adr_store_path,
+            PrefixType::NonTransferable,
+        )?;
+
+        let verifier_dir = tempdir()?;
+        let verifier = Controller::new(
+            verifier_dir.path().to_str().unwrap(),
+            "localhost:1516",
+            &adr_store_path,
+            PrefixType::Basic,
+        )?;
+
+        // Verifier doesn't know the KEL and the controller isn't running,
+        // signature is verified with the prefix itself.
+        let msg = "Some message";
+        let signature = cont.sign_indexed(msg)?;
+        assert!(verifier.verify(&cont.get_prefix()?, msg, &signature)?);
+        assert!(!verifier.verify(&cont.get_prefix()?, "Other message", &signature)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_witness_receipts() -> Result<(), Error> {
+        use tempfile::tempdir;
+        let db_dir = tempdir()?;
+        let adr_store_path = [db_dir.path().to_str().unwrap(), "adr"].join("");
+
+        let witness_dirs = [tempdir()?, tempdir()?];
+        let mut witness_ids = vec![];
+        for dir in witness_dirs.iter() {
+            let address = free_address()?;
+            let witness = SharedController::new_witness(
+                dir.path().to_str().unwrap(),
+                &address,
+                &adr_store_path,
+            )?;
+            witness_ids.push(witness.get_prefix()?);
+            witness.run()?;
+            wait_for_listener(&address)?;
+        }
+        // Third witness is designated, but it's not running.
+        let offline_dir = tempdir()?;
+        let offline = Controller::new_witness(
+            offline_dir.path().to_str().unwrap(),
+            &free_address()?,
+            &adr_store_path,
+        )?;
+        witness_ids.push(offline.get_prefix()?);
+
+        let mut cont = Controller::new_with_witnesses(
+            db_dir.path().to_str().unwrap(),
+            "localhost:1730",
+            &adr_store_path,
+            &serde_json::to_string(&witness_ids).unwrap(),
+            2,
+        )?;
+        assert_eq!(cont.main_entity.get_witness_receipts(0)?.len(), 2);
+        assert!(cont.is_committed(0)?);
+
+        cont.append("some data")?;
+        cont.update_keys()?;
+        assert!(cont.is_committed(1)?);
+        assert!(cont.is_committed(2)?);
+
+        // Toad can't be reached with one running witness.
+        cont.update_witnesses(&serde_json::to_string(&witness_ids[1..]).unwrap(), 2)?;
+        assert_eq!(cont.main_entity.get_witness_receipts(3)?.len(), 1);
+        assert!(!cont.is_committed(3)?);
+
+        // Witness threshold can't exceed number of witnesses.
+        assert!(cont
+            .update_witnesses(&serde_json::to_string(&witness_ids[..1]).unwrap(), 2)
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delegated_identifier() -> Result<(), Error> {
+        use tempfile::tempdir;
+        let db_dir = tempdir()?;
+        let adr_store_path = [db_dir.path().to_str().unwrap(), "adr"].join("");
+        let mut cont = Controller::new(
+            db_dir.path().to_str().unwrap(),
+            "localhost:1740",
+            &adr_store_path,
+            PrefixType::Basic,
+        )?;
+        let delegated_dir = tempdir()?;
+        let delegated_id =
+            cont.add_delegated_entity("delegated", delegated_dir.path().to_str().unwrap())?;
+        cont.update_keys_for(&delegated_id)?;
+        let msg = "Some message";
+        let signature = base64::encode_config(cont.sign_by(&delegated_id, msg)?, URL_SAFE);
+
+        let shared = SharedController::from_controller(cont)?;
+        shared.clone().run()?;
+
+        // Verifier gets KELs of delegated identifier and its delegator
+        // and checks the delegation chain.
+        let verifier_dir = tempdir()?;
+        let verifier = Controller::new(
+            verifier_dir.path().to_str().unwrap(),
+            "localhost:1741",
+            &adr_store_path,
+            PrefixType::Basic,
+        )?;
+        assert!(verifier.verify(&delegated_id, msg, &signature)?);
+        let state = verifier.get_state(&delegated_id.parse()?, &verifier.main_entity)?;
+        assert_eq!(state.unwrap().sn, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decommission() -> Result<(), Error> {
+        use tempfile::tempdir;
+        let db_dir = tempdir()?;
+        let adr_store_path = [db_dir.path().to_str().unwrap(), "adr"].join("");
+        let mut cont = Controller::new(
+            db_dir.path().to_str().unwrap(),
+            "localhost:1750",
+            &adr_store_path,
+            PrefixType::Basic,
+        )?;
+        let entity_dir = tempdir()?;
+        let id = cont.add_entity("entity", entity_dir.path().to_str().unwrap())?;
+        let msg = "Some message";
+        let signature = base64::encode_config(cont.sign_by(&id, msg)?, URL_SAFE);
+
+        cont.decommission_entity(&id)?;
+        assert_eq!(cont.current_identifiers(), vec![id.clone()]);
+        assert_eq!(
+            cont.identifier_status(&id)?,
+            IdentifierStatus::Decommissioned
+        );
+        assert!(cont.sign_by(&id, msg).is_err());
+        assert!(cont.decommission_entity(&id).is_err());
+
+        let shared = SharedController::from_controller(cont)?;
+        shared.clone().run()?;
+
+        // Verifier rejects signatures of decommissioned identifier, even
+        // the ones made before.
+        let verifier_dir = tempdir()?;
+        let verifier = Controller::new(
+            verifier_dir.path().to_str().unwrap(),
+            "localhost:1751",
+            &adr_store_path,
+            PrefixType::Basic,
+        )?;
+        assert!(matches!(
+            verifier.verify(&id, msg, &signature),
+            Err(Error::Decommissioned(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_and_import_kel() -> Result<(), Error> {
+        use tempfile::tempdir;
+        let db_dir = tempdir()?;
+        let adr_store_path = [db_dir.path().to_str().unwrap(), "adr"].join("");
+        let mut issuer = Controller::new(
+            db_dir.path().to_str().unwrap(),
+            "localhost:1760",
+            &adr_store_path,
+            PrefixType::Basic,
+        )?;
+        issuer.update_keys()?;
+        let msg = "Some message";
+        let signature = issuer.sign_indexed(msg)?;
+        let kel_path = db_dir.path().join("kel.cesr");
+        let kel_path = kel_path.to_str().unwrap();
+        issuer.export_kel(&issuer.get_prefix()?, kel_path)?;
+
+        // Verifier works offline, issuer isn't running.
+        let verifier_dir = tempdir()?;
+        let verifier = Controller::new(
+            verifier_dir.path().to_str().unwrap(),
+            "localhost:1761",
+            &adr_store_path,
+            PrefixType::Basic,
+        )?;
+        verifier.import_kel(kel_path)?;
+        assert!(verifier.verify(&issuer.get_prefix()?, msg, &signature)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_multisig_verification() -> Result<(), Error> {
+        use tempfile::tempdir;
+        let db_dir = tempdir()?;
+        let db_path = db_dir.path().to_str().unwrap();
+        let adr_store_path = [db_dir.path().to_str().unwrap(), "adr"].join("");
+
+        let mut cont = Controller::new_with_keys(
+            db_path,
+            "localhost:1414",
+            &adr_store_path,
+            "ed25519",
+            3,
+            r#"["1/2", "1/2", "1/2"]"#,
+        )?;
+        let msg = "Some message";
+        let signatures = cont.sign_indexed(msg)?;
+        assert!(cont.verify(&cont.get_prefix()?, msg, &signatures)?);
+
+        // Signature of only one key doesn't satisfy the threshold.
+        let single = base64::encode_config(cont.sign(msg)?, URL_SAFE);
+        assert!(!cont.verify(&cont.get_prefix()?, msg, &single)?);
+
+        // The same applies to vcs.
+        let vc = create_attestation(&cont.get_prefix()?, "did:multisig", msg, "321")?;
+        let signed_vc = cont.issue_vc(&vc)?;
+        assert_eq!(cont.verify_vc(&signed_vc)?.state, SignatureState::Ok);
+        let vc_str = serde_json::to_string(&vc).unwrap();
+        let single_signed_vc = sign_attestation(vc, cont.sign(&vc_str)?)?;
+        assert_eq!(
+            cont.verify_vc(&single_signed_vc)?.state,
+            SignatureState::Wrong
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_kinds() -> Result<(), Error> {
+        use tempfile::tempdir;
+        let db_dir = tempdir()?;
+        let adr_store_path = [db_dir.path().to_str().unwrap(), "adr"].join("");
+        let cont = Controller::new(
+            db_dir.path().to_str().unwrap(),
+            "localhost:1770",
+            &adr_store_path,
+            PrefixType::Basic,
+        )?;
+        let msg = "Some message";
+
+        let unknown_dir = tempdir()?;
+        let unknown = Entity::new(unknown_dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        let unknown_id = unknown.get_prefix()?;
+        let result = cont.sign_by(&unknown_id, msg);
+        assert!(matches!(result, Err(Error::UnknownIdentifier(_))));
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .starts_with("UnknownIdentifier: "));
+
+        // Identifier isn't registered in address store, so its KEL can't
+        // be fetched.
+        let signature = base64::encode_config(unknown.sign(msg)?, URL_SAFE);
+        assert!(matches!(
+            cont.verify(&unknown_id, msg, &signature),
+            Err(Error::AddressNotFound(_))
+        ));
+
+        assert!(matches!(
+            cont.parse_message(b"no_argument"),
+            Err(Error::ProtocolError(_))
+        ));
+        assert!(matches!(
+            cont.parse_message([&unknown_id, " "].join("").as_bytes()),
+            Err(Error::UnknownIdentifier(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_seals() -> Result<(), Error> {
+        let digest = SelfAddressing::Blake3_256.derive(b"some data");
+        let seals = parse_seals(&format!(
+            r#"[{{"type": "digest", "data": "some data"}},
+            {{"type": "digest", "bytes": [115, 111, 109, 101, 32, 100, 97, 116, 97]}},
+            {{"type": "digest", "digest": "{}"}}]"#,
+            digest.to_str()
+        ))?;
+        assert_eq!(seals.len(), 3);
+        assert!(seals.iter().all(|seal| match seal {
+            Seal::Digest(d) => d.dig == digest,
+            _ => false,
+        }));
+
+        // Digest seal needs exactly one source of digest.
+        assert!(parse_seals(r#"[{"type": "digest"}]"#).is_err());
+        assert!(parse_seals(&format!(
+            r#"[{{"type": "digest", "data": "some data", "digest": "{}"}}]"#,
+            digest.to_str()
+        ))
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_identifier_operations() -> Result<(), Error> {
+        use crate::tel::tel_event::TelState;
+        use tempfile::tempdir;
+        let db_dir = tempdir()?;
+        let adr_store_path = [db_dir.path().to_str().unwrap(), "adr"].join("");
+        let mut cont = Controller::new(
+            db_dir.path().to_str().unwrap(),
+            "localhost:1772",
+            &adr_store_path,
+            PrefixType::Basic,
+        )?;
+        let entity_dir = tempdir()?;
+        let id = cont.add_entity("entity", entity_dir.path().to_str().unwrap())?;
+
+        // Operations on secondary identifier don't touch the main KEL.
+        cont.update_keys_for(&id)?;
+        let seal = cont.append_by(&id, "Some message")?;
+        assert_eq!(seal.sn, 2);
+        assert!(cont.is_committed_for(&id, 2)?);
+        let main_kerl = cont.get_kerl()?;
+
+        let vc = create_attestation(&id, &["did:", &id, "/att_id"].join(""), "Some vc", "321")?;
+        cont.issue_vc_by(&id, &vc)?;
+        let vc_digest = blake3::hash(serde_json::to_string(&vc).unwrap().as_bytes())
+            .as_bytes()
+            .to_vec();
+        match cont.tels.get_state(&vc_digest)? {
+            TelState::Issued(issuance_seal) => {
+                assert_eq!(issuance_seal.prefix.to_str(), id);
+                // Registry inception is anchored at sn 3.
+                assert_eq!(issuance_seal.sn, 4);
+            }
+            _ => panic!("VC should be issued"),
+        }
+        let registry_id = cont.registry_by(&id)?;
+        assert_ne!(registry_id, cont.registry_by(&cont.get_prefix()?)?);
+        cont.revoke_vc_by(&id, &vc)?;
+        assert!(matches!(
+            cont.tels.get_state(&vc_digest)?,
+            TelState::Revoked
+        ));
+
+        assert_eq!(cont.get_kerl()?, main_kerl);
+        assert_ne!(cont.get_kerl_for(&id)?, main_kerl);
+        assert_eq!(cont.get_kerl_for(&cont.get_prefix()?)?, main_kerl);
+        assert!(matches!(
+            cont.update_keys_for("DUnknownIdentifier"),
+            Err(Error::UnknownIdentifier(_))
+        ));
 
         Ok(())
     }
@@ -502,14 +2488,15 @@ mod tests {
         let db_path = db_dir.path().to_str().unwrap();
         let adr_store_path = [db_dir.path().to_str().unwrap(), "adr"].join("");
 
-        let mut cont = Controller::new(db_path, "localhost:1212", &adr_store_path);
+        let mut cont = Controller::new(db_path, "localhost:1212", &adr_store_path, PrefixType::Basic)?;
         let prefix = cont.main_entity.get_prefix()?;
         // Compute vc related stuff
         let msg = "Some message";
         let ad = create_attestation( &prefix, &["did:", &prefix, "/fdfd/dsds"].join(""), msg, "321")?;
 
         let signed_ad = cont.issue_vc(&ad)?;
-        // cont.update_keys()?;
+        // Asker has to use keys current at issuance, not the rotated ones.
+        cont.update_keys()?;
 
         let issuer_state = cont.main_entity.get_state_for_prefix(&prefix.parse()?)?;
 
@@ -521,8 +2508,9 @@ mod tests {
         let shared_asker = SharedController::new(db_path, "localhost:3232", &adr_store_path)?;
         shared_asker.clone().run()?;
 
-        let ver = shared_asker.verify_vc(&SignedAttestationDatum {sa: signed_ad})?;
-        assert!(ver);
+        let signed_ad = SignedAttestationDatum { sa: signed_ad };
+        let ver = shared_asker.verify_vc(&signed_ad)?;
+        assert_eq!(ver, SignatureState::Ok);
 
         // let issuer_state_in_asker = shared_asker
         //     .controller
@@ -532,9 +2520,11 @@ mod tests {
         //     .get_state_for_prefix(&prefix.parse()?)?;
         // assert_eq!(issuer_state.unwrap().sn, issuer_state_in_asker.unwrap().sn);
 
-        // issuer.revoke_vc(&serde_json::to_string(&ad).unwrap())?;
-        // let ver = shared_asker.verify_vc(&signed_ad)?;
-        // assert!(ver);
+        // Revocation is anchored in event asker doesn't know yet, so it's
+        // fetched along with the TEL.
+        issuer.revoke_vc(&serde_json::to_string(&ad).unwrap())?;
+        let ver = shared_asker.verify_vc(&signed_ad)?;
+        assert_eq!(ver, SignatureState::Revoked);
 
         Ok(())
     }
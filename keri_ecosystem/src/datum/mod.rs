@@ -11,7 +11,8 @@ pub fn create_attestation(testator_id: &str, attestation_id: &str, message: &str
     let tmp_attestation_id: AttestationId = AttestationId::new(testator_id.clone(), &testator_id.clone().get_id());
     
     let tmp_attestation: Attestation<String, Message, String> = Attestation::new(tmp_attestation_id, None, vec![], schema.to_string(), Message::new(message), None);
-    let att_hash = base64::encode_config(blake3::hash(&serde_json::to_vec(&tmp_attestation).unwrap()).as_bytes(), URL_SAFE);
+    let tmp_attestation = serde_json::to_vec(&tmp_attestation).map_err(|e| Error::Generic(e.to_string()))?;
+    let att_hash = base64::encode_config(blake3::hash(&tmp_attestation).as_bytes(), URL_SAFE);
     let attestation_id: AttestationId = AttestationId::new(testator_id.clone(), &vec![format!("did:{}", testator_id.clone().get_id()), att_hash].join("/"));
     
     Ok(Attestation::new(attestation_id, None, vec![], schema.to_string(), Message::new(message), None))
@@ -43,7 +44,7 @@ impl SignedAttestationDatum {
     }
 
     pub fn deserialize(msg: &str) -> Result<SignedAttestationDatum, Error> {
-        let sa = msg.parse::<SignedAttestation<String, Message, String>>().map_err(|e| Error::Generic(e.to_string()))?;
+        let sa = msg.parse::<SignedAttestation<String, Message, String>>().map_err(|e| Error::ProtocolError(e.to_string()))?;
         Ok(SignedAttestationDatum {sa})
     }
     pub fn get_issuer(&self) -> Result<String, Error> {
@@ -4,18 +4,117 @@ use base64::DecodeError;
 use keri::error::Error as KeriError;
 use thiserror::Error;
 
+/// Errors of the crate. Messages start with the error kind, as returned by
+/// `kind`, followed by a colon. Python bindings get the message only, so
+/// they can tell the kind from it.
 #[derive(Error, Debug)]
 pub enum Error {
-    #[error(transparent)]
+    #[error("Error: {0}")]
     DynError(#[from] Box<dyn std::error::Error>),
-    #[error(transparent)]
+    /// Error of keriox. Signature errors are of `InvalidSignature` kind.
+    #[error("{}: {0}", keri_error_kind(.0))]
     KeriError(#[from] KeriError),
-    #[error(transparent)]
+    #[error("EncodingError: {0}")]
     StringFromUtf8Error(#[from] std::string::FromUtf8Error),
-    #[error(transparent)]
+    #[error("EncodingError: {0}")]
     Decode64Error(#[from] DecodeError),
-    #[error(transparent)]
-    CommunicationError(#[from] io::Error),
-    #[error("{0}")]
+    /// Other identifier can't be reached.
+    #[error("CommunicationError: {0}")]
+    CommunicationError(io::Error),
+    /// Files can't be read or written.
+    #[error("StorageError: {0}")]
+    IoError(#[from] io::Error),
+    #[error("Decommissioned: identifier {0} is decommissioned")]
+    Decommissioned(String),
+    #[error("Duplicitous: identifier {0} is duplicitous")]
+    Duplicitous(String),
+    /// Identifier isn't managed by the controller.
+    #[error("UnknownIdentifier: identifier {0} is not managed by controller")]
+    UnknownIdentifier(String),
+    /// There is no KEL of identifier and it can't be fetched.
+    #[error("KelNotFound: there is no KEL of {0}")]
+    KelNotFound(String),
+    /// Address of identifier isn't known.
+    #[error("AddressNotFound: can't find address of {0}")]
+    AddressNotFound(String),
+    /// Signature doesn't match the keys or can't be verified at all.
+    #[error("InvalidSignature: {0}")]
+    InvalidSignature(String),
+    /// TEL event doesn't fit the current state of the TEL.
+    #[error("TelStateConflict: {0}")]
+    TelStateConflict(String),
+    /// Received message or data has unexpected format.
+    #[error("ProtocolError: {0}")]
+    ProtocolError(String),
+    /// Database or file store can't be read or written.
+    #[error("StorageError: {0}")]
+    StorageError(String),
+    #[error("Error: {0}")]
     Generic(String),
 }
+
+impl Error {
+    /// Name of the error kind, which prefixes the error message.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::DynError(_) | Error::Generic(_) => "Error",
+            Error::KeriError(e) => keri_error_kind(e),
+            Error::StringFromUtf8Error(_) | Error::Decode64Error(_) => "EncodingError",
+            Error::CommunicationError(_) => "CommunicationError",
+            Error::IoError(_) => "StorageError",
+            Error::Decommissioned(_) => "Decommissioned",
+            Error::Duplicitous(_) => "Duplicitous",
+            Error::UnknownIdentifier(_) => "UnknownIdentifier",
+            Error::KelNotFound(_) => "KelNotFound",
+            Error::AddressNotFound(_) => "AddressNotFound",
+            Error::InvalidSignature(_) => "InvalidSignature",
+            Error::TelStateConflict(_) => "TelStateConflict",
+            Error::ProtocolError(_) => "ProtocolError",
+            Error::StorageError(_) => "StorageError",
+        }
+    }
+}
+
+fn keri_error_kind(error: &KeriError) -> &'static str {
+    match error {
+        KeriError::SignatureVerificationError
+        | KeriError::FaultySignatureVerification
+        | KeriError::NotEnoughSigsError => "InvalidSignature",
+        _ => "KeriError",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kind_prefix() {
+        let errors = vec![
+            Error::UnknownIdentifier("DA".into()),
+            Error::KelNotFound("DA".into()),
+            Error::AddressNotFound("DA".into()),
+            Error::InvalidSignature("wrong signature".into()),
+            Error::TelStateConflict("VC is already revoked".into()),
+            Error::ProtocolError("improper message format".into()),
+            Error::StorageError("can't read keystore".into()),
+            Error::Decommissioned("DA".into()),
+            Error::Generic("other".into()),
+            Error::KeriError(KeriError::SemanticError("wrong event".into())),
+            Error::KeriError(KeriError::SignatureVerificationError),
+            Error::CommunicationError(io::ErrorKind::ConnectionRefused.into()),
+            Error::IoError(io::ErrorKind::NotFound.into()),
+        ];
+        for error in errors {
+            let message = error.to_string();
+            assert!(message.starts_with(&format!("{}: ", error.kind())));
+        }
+
+        // Errors of files and of keriox signature checks aren't reported
+        // as communication or keriox errors.
+        let error: Error = io::Error::from(io::ErrorKind::NotFound).into();
+        assert_eq!(error.kind(), "StorageError");
+        let error: Error = KeriError::FaultySignatureVerification.into();
+        assert_eq!(error.kind(), "InvalidSignature");
+    }
+}
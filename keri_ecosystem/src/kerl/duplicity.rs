@@ -0,0 +1,130 @@
+use std::{collections::HashMap, path::Path};
+
+use keri::{
+    event_message::SignedEventMessage,
+    prefix::{IdentifierPrefix, Prefix},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, storage::RecordLog};
+
+const EVIDENCE_FILE: &str = "duplicity_evidence";
+
+/// Reason why received event couldn't be accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    /// Other event of the same sn is already accepted.
+    Duplicitous,
+    /// Some previous events are missing.
+    OutOfOrder,
+    /// Signatures don't satisfy threshold or event is otherwise invalid.
+    BadSignature,
+    /// There is no KEL of event's identifier.
+    UnknownPrefix,
+    /// Signatures are valid, but there is not enough of them yet.
+    PartiallySigned,
+    /// Receipted event isn't known. Used for receipts only.
+    UnknownEvent,
+    /// Event processor failed for other reason.
+    Unknown,
+}
+
+impl FailureKind {
+    /// Returns true if item which failed for that reason is escrowed, as
+    /// it can be processed later.
+    pub fn is_escrowed(&self) -> bool {
+        matches!(
+            self,
+            FailureKind::OutOfOrder
+                | FailureKind::UnknownPrefix
+                | FailureKind::PartiallySigned
+                | FailureKind::UnknownEvent
+        )
+    }
+}
+
+/// Event which couldn't be processed along with the reason.
+pub struct ProcessingFailure {
+    pub kind: FailureKind,
+    pub event: SignedEventMessage,
+}
+
+/// Proof of duplicity: two different signed events of the same sn.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DuplicityReport {
+    pub sn: u64,
+    /// Accepted event, serialized with its signatures.
+    pub accepted: String,
+    /// Conflicting event, serialized with its signatures.
+    pub conflicting: String,
+}
+
+/// Duplicity report of identifier.
+#[derive(Serialize, Deserialize)]
+struct Evidence {
+    prefix: String,
+    report: DuplicityReport,
+}
+
+/// Duplicity reports by identifier prefix. They are kept in file in
+/// event database directory.
+pub struct DuplicityEvidence {
+    log: RecordLog<Evidence>,
+}
+
+impl DuplicityEvidence {
+    pub fn new(db_path: &str) -> Self {
+        Self {
+            log: RecordLog::new(Path::new(db_path).join(EVIDENCE_FILE))
+                .with_legacy(legacy_evidence),
+        }
+    }
+
+    pub fn add(
+        &self,
+        accepted: &SignedEventMessage,
+        conflicting: &SignedEventMessage,
+    ) -> Result<(), Error> {
+        let report = DuplicityReport {
+            sn: conflicting.event_message.event.sn,
+            accepted: String::from_utf8(accepted.serialize()?)?,
+            conflicting: String::from_utf8(conflicting.serialize()?)?,
+        };
+        let prefix = &conflicting.event_message.event.prefix;
+        if self.get(prefix)?.contains(&report) {
+            return Ok(());
+        }
+        self.log.append(&Evidence {
+            prefix: prefix.to_str(),
+            report,
+        })
+    }
+
+    pub fn get(&self, prefix: &IdentifierPrefix) -> Result<Vec<DuplicityReport>, Error> {
+        let prefix = prefix.to_str();
+        Ok(self
+            .log
+            .load()?
+            .into_iter()
+            .filter(|evidence| evidence.prefix == prefix)
+            .map(|evidence| evidence.report)
+            .collect())
+    }
+}
+
+/// Reads evidence file of the first version, which was json map of
+/// reports by identifier prefix.
+fn legacy_evidence(content: &[u8]) -> Result<Vec<Evidence>, Error> {
+    let evidence: HashMap<String, Vec<DuplicityReport>> =
+        serde_json::from_slice(content).map_err(|e| Error::StorageError(e.to_string()))?;
+    Ok(evidence
+        .into_iter()
+        .flat_map(|(prefix, reports)| {
+            reports.into_iter().map(move |report| Evidence {
+                prefix: prefix.clone(),
+                report,
+            })
+        })
+        .collect())
+}
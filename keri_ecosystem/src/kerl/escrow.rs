@@ -0,0 +1,169 @@
+use std::time::{Duration, Instant};
+
+use keri::event_message::SignedEventMessage;
+
+use crate::error::Error;
+
+use super::duplicity::FailureKind;
+
+/// How long escrowed items wait for missing events or signatures by
+/// default.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(20 * 60);
+
+/// Maximal number of escrowed items. When it's reached, the oldest item is
+/// dropped to make room for the new one.
+pub const MAX_ITEMS: usize = 1000;
+
+enum Escrowed {
+    Event(SignedEventMessage),
+    /// Serialized receipt of event which isn't known yet.
+    Receipt(Vec<u8>),
+}
+
+struct EscrowedItem {
+    kind: FailureKind,
+    item: Escrowed,
+    since: Instant,
+}
+
+impl EscrowedItem {
+    fn serialize(&self) -> Result<Vec<u8>, Error> {
+        match &self.item {
+            Escrowed::Event(event) => Ok(event.serialize()?),
+            Escrowed::Receipt(receipt) => Ok(receipt.clone()),
+        }
+    }
+}
+
+/// Events and receipts which can't be processed yet, because some
+/// previous events or signatures are missing. They are kept in memory
+/// until they can be processed or the timeout expires, up to `MAX_ITEMS`
+/// of them.
+///
+/// Escrow isn't persisted, so escrowed items are lost when entity is
+/// closed. Their senders need to send them again after restart, which is
+/// how KERI peers recover missing events anyway.
+pub struct Escrow {
+    timeout: Duration,
+    items: Vec<EscrowedItem>,
+}
+
+impl Escrow {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            items: vec![],
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Escrows event. Signatures of partially signed event are expected to
+    /// be verified, they are merged with signatures of the same partially
+    /// signed event, if it's already escrowed. Other events can't be
+    /// verified yet, so each differently signed copy is escrowed
+    /// separately.
+    pub fn add_event(&mut self, kind: FailureKind, event: SignedEventMessage) -> Result<(), Error> {
+        let serialized = event.event_message.serialize()?;
+        let signed = event.serialize()?;
+        for item in self.items.iter_mut() {
+            if let Escrowed::Event(escrowed) = &mut item.item {
+                if escrowed.serialize()? == signed {
+                    return Ok(());
+                }
+                if kind == FailureKind::PartiallySigned
+                    && item.kind == FailureKind::PartiallySigned
+                    && escrowed.event_message.serialize()? == serialized
+                {
+                    for signature in event.signatures {
+                        if !escrowed
+                            .signatures
+                            .iter()
+                            .any(|sig| sig.index == signature.index)
+                        {
+                            escrowed.signatures.push(signature);
+                        }
+                    }
+                    return Ok(());
+                }
+            }
+        }
+        self.push(kind, Escrowed::Event(event));
+        Ok(())
+    }
+
+    /// Escrows serialized receipt of unknown event.
+    pub fn add_receipt(&mut self, receipt: Vec<u8>) {
+        if self
+            .items
+            .iter()
+            .any(|item| matches!(&item.item, Escrowed::Receipt(r) if r == &receipt))
+        {
+            return;
+        }
+        self.push(FailureKind::UnknownEvent, Escrowed::Receipt(receipt));
+    }
+
+    fn push(&mut self, kind: FailureKind, item: Escrowed) {
+        let timeout = self.timeout;
+        self.items.retain(|item| item.since.elapsed() < timeout);
+        if self.items.len() >= MAX_ITEMS {
+            self.items.remove(0);
+        }
+        self.items.push(EscrowedItem {
+            kind,
+            item,
+            since: Instant::now(),
+        });
+    }
+
+    /// Returns number of escrowed items of given kind.
+    pub fn count(&self, kind: FailureKind) -> usize {
+        self.items.iter().filter(|item| item.kind == kind).count()
+    }
+
+    /// Drops expired items and passes the others to `process`, until none
+    /// of them can be processed. `process` returns true if item was
+    /// processed, then it's removed from escrow.
+    pub fn retry<F: FnMut(&[u8]) -> bool>(&mut self, mut process: F) {
+        let timeout = self.timeout;
+        self.items.retain(|item| item.since.elapsed() < timeout);
+        loop {
+            let count = self.items.len();
+            self.items.retain(|item| match item.serialize() {
+                Ok(msg) => !process(&msg),
+                Err(_) => false,
+            });
+            if self.items.len() == count {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_items() {
+        let mut escrow = Escrow::new(DEFAULT_TIMEOUT);
+        for i in 0..=MAX_ITEMS {
+            escrow.add_receipt(i.to_string().into_bytes());
+        }
+        assert_eq!(escrow.count(FailureKind::UnknownEvent), MAX_ITEMS);
+        // The oldest item is dropped.
+        let mut retried = vec![];
+        escrow.retry(|msg| {
+            retried.push(msg.to_vec());
+            false
+        });
+        assert_eq!(retried[0], b"1".to_vec());
+    }
+}
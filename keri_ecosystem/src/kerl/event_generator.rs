@@ -2,18 +2,51 @@ use crate::error::Error;
 use keri::{
     derivation::{basic::Basic, self_addressing::SelfAddressing},
     event::{
-        event_data::{EventData, ReceiptTransferable},
-        sections::seal::{DigestSeal, EventSeal, Seal},
+        event_data::{
+            delegated::{DelegatedInceptionEvent, DelegatedRotationEvent},
+            inception::InceptionEvent,
+            EventData, ReceiptNonTransferable, ReceiptTransferable,
+        },
+        sections::seal::{DigestSeal, EventSeal, LocationSeal, Seal},
+        sections::threshold::SignatureThreshold,
+        sections::{InceptionWitnessConfig, KeyConfig, RotationWitnessConfig},
         Event, EventMessage, SerializationFormats,
     },
     event_message::event_msg_builder::{EventMsgBuilder, EventType},
-    prefix::{BasicPrefix, IdentifierPrefix},
+    prefix::{BasicPrefix, IdentifierPrefix, Prefix},
     state::IdentifierState,
 };
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 use ursa::keys::PublicKey;
 
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum KeyType {
     Ed25519Sha512,
+    /// ECDSA secp256k1 key, public key is in compressed SEC1 form.
+    EcdsaSecp256k1Sha256,
+    /// Key agreement key. It can't be used for signing.
+    X25519,
+}
+
+impl KeyType {
+    /// Returns true if key of this type can sign events.
+    pub fn is_signing(&self) -> bool {
+        !matches!(self, KeyType::X25519)
+    }
+}
+
+impl FromStr for KeyType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ed25519" => Ok(KeyType::Ed25519Sha512),
+            "secp256k1" => Ok(KeyType::EcdsaSecp256k1Sha256),
+            "x25519" => Ok(KeyType::X25519),
+            _ => Err(Error::Generic(format!("Unknown key type: {}", s))),
+        }
+    }
 }
 
 pub struct Key {
@@ -25,61 +58,411 @@ impl Key {
     pub fn new(key: Vec<u8>, key_type: KeyType) -> Key {
         Self { key, key_type }
     }
+    pub fn key(&self) -> Vec<u8> {
+        self.key.clone()
+    }
+    pub fn key_type(&self) -> KeyType {
+        self.key_type
+    }
     pub fn derive_key_prefix(&self) -> BasicPrefix {
         let pk = PublicKey(self.key.clone());
         match self.key_type {
             KeyType::Ed25519Sha512 => Basic::Ed25519.derive(pk),
+            KeyType::EcdsaSecp256k1Sha256 => Basic::ECDSAsecp256k1.derive(pk),
+            KeyType::X25519 => Basic::X25519.derive(pk),
+        }
+    }
+    /// Derives key prefix which marks the key as non-transferable.
+    pub fn derive_non_transferable_prefix(&self) -> BasicPrefix {
+        let pk = PublicKey(self.key.clone());
+        match self.key_type {
+            KeyType::Ed25519Sha512 => Basic::Ed25519NT.derive(pk),
+            KeyType::EcdsaSecp256k1Sha256 => Basic::ECDSAsecp256k1NT.derive(pk),
+            KeyType::X25519 => Basic::X25519.derive(pk),
+        }
+    }
+}
+
+/// Describes how identifier prefix is derived at inception.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PrefixType {
+    /// Prefix derived from the only public key.
+    Basic,
+    /// Prefix derived from digest of inception event, so it commits to the
+    /// whole inception configuration.
+    SelfAddressing,
+    /// Basic prefix of non-transferable key. Identifier has no next keys,
+    /// so its KEL consists of inception event only.
+    NonTransferable,
+}
+
+impl FromStr for PrefixType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "basic" => Ok(PrefixType::Basic),
+            "self_addressing" => Ok(PrefixType::SelfAddressing),
+            "non_transferable" => Ok(PrefixType::NonTransferable),
+            _ => Err(Error::Generic(format!("Unknown prefix type: {}", s))),
+        }
+    }
+}
+
+/// Witnesses designated by the controller along with threshold of
+/// accountable duplicity (toad), which is the number of witness receipts
+/// required to consider an event fully committed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WitnessConfig {
+    pub witnesses: Vec<BasicPrefix>,
+    pub threshold: u64,
+}
+
+impl WitnessConfig {
+    pub fn new(witnesses: Vec<BasicPrefix>, threshold: u64) -> Result<Self, Error> {
+        if threshold as usize > witnesses.len() {
+            return Err(Error::Generic(format!(
+                "Witness threshold {} is bigger than number of witnesses {}",
+                threshold,
+                witnesses.len()
+            )));
+        }
+        if witnesses
+            .iter()
+            .any(|w| !is_non_transferable(&IdentifierPrefix::Basic(w.clone())))
+        {
+            return Err(Error::Generic(
+                "Witness has to be non-transferable identifier".into(),
+            ));
+        }
+        Ok(Self {
+            witnesses,
+            threshold,
+        })
+    }
+
+    /// Witness configuration of current identifier state.
+    pub fn from_state(state: &IdentifierState) -> Self {
+        Self {
+            witnesses: state.witnesses.clone(),
+            threshold: state.tally,
         }
     }
 }
 
 pub fn make_icp(
-    pk: &Key,
-    nxt_pk: &Key,
-    prefix: Option<IdentifierPrefix>,
+    pks: &[Key],
+    nxt_pks: &[Key],
+    threshold: &SignatureThreshold,
+    next_threshold: &SignatureThreshold,
+    witness_config: &WitnessConfig,
+    prefix_type: PrefixType,
+) -> Result<EventMessage, Error> {
+    match prefix_type {
+        PrefixType::NonTransferable => {
+            if !witness_config.witnesses.is_empty() {
+                return Err(Error::Generic(
+                    "Non-transferable identifier can't have witnesses".into(),
+                ));
+            }
+            make_non_transferable_icp(pks, nxt_pks, threshold)
+        }
+        _ => make_transferable_icp(
+            pks,
+            nxt_pks,
+            threshold,
+            next_threshold,
+            witness_config,
+            prefix_type,
+        ),
+    }
+}
+
+fn make_transferable_icp(
+    pks: &[Key],
+    nxt_pks: &[Key],
+    threshold: &SignatureThreshold,
+    next_threshold: &SignatureThreshold,
+    witness_config: &WitnessConfig,
+    prefix_type: PrefixType,
 ) -> Result<EventMessage, Error> {
-    let key_prefix = vec![pk.derive_key_prefix()];
-    let pref = prefix.unwrap_or(IdentifierPrefix::Basic(key_prefix[0].clone()));
-    let nxt_key_prefix = vec![nxt_pk.derive_key_prefix()];
+    let key_prefix: Vec<_> = pks.iter().map(Key::derive_key_prefix).collect();
+    let first_key = key_prefix
+        .get(0)
+        .ok_or(Error::Generic("At least one key is required".into()))?
+        .clone();
+    let nxt_key_prefix: Vec<_> = nxt_pks.iter().map(Key::derive_key_prefix).collect();
     let icp = EventMsgBuilder::new(EventType::Inception)?
-        .with_prefix(pref)
-        .with_keys(key_prefix)
+        .with_prefix(IdentifierPrefix::Basic(first_key))
+        .with_keys(key_prefix.clone())
         .with_next_keys(nxt_key_prefix)
+        .with_threshold(threshold)
+        .with_next_threshold(next_threshold)
         .build()?;
+
+    // Builder doesn't set witnesses, so put them into inception data.
+    let icp_data = match icp.event.event_data {
+        EventData::Icp(mut icp_data) => {
+            icp_data.witness_config = InceptionWitnessConfig {
+                tally: witness_config.threshold,
+                initial_witnesses: witness_config.witnesses.clone(),
+            };
+            icp_data
+        }
+        _ => return Err(Error::Generic("Not an inception event".into())),
+    };
+
+    match prefix_type {
+        PrefixType::Basic if key_prefix.len() > 1 => Err(Error::Generic(
+            "Basic prefix can be derived only from single key".into(),
+        )),
+        PrefixType::Basic => Ok(Event {
+            prefix: icp.event.prefix,
+            sn: 0,
+            event_data: EventData::Icp(icp_data),
+        }
+        .to_message(SerializationFormats::JSON)?),
+        _ => Ok(icp_data
+            .incept_self_addressing(SelfAddressing::Blake3_256, SerializationFormats::JSON)?),
+    }
+}
+
+/// Makes inception event of non-transferable identifier. It has one key,
+/// no next keys commitment and prefix is derived from that key.
+fn make_non_transferable_icp(
+    pks: &[Key],
+    nxt_pks: &[Key],
+    threshold: &SignatureThreshold,
+) -> Result<EventMessage, Error> {
+    if !nxt_pks.is_empty() {
+        return Err(Error::Generic(
+            "Non-transferable identifier can't have next keys".into(),
+        ));
+    }
+    let key_prefix = match pks {
+        [pk] => pk.derive_non_transferable_prefix(),
+        _ => {
+            return Err(Error::Generic(
+                "Non-transferable identifier requires exactly one key".into(),
+            ))
+        }
+    };
+    let key_config = KeyConfig::new(vec![key_prefix.clone()], None, Some(threshold.clone()));
+    let icp = Event {
+        prefix: IdentifierPrefix::Basic(key_prefix),
+        sn: 0,
+        event_data: EventData::Icp(InceptionEvent::new(key_config, None, None)),
+    }
+    .to_message(SerializationFormats::JSON)?;
     Ok(icp)
 }
 
-pub fn make_rot(pk: &Key, nxt_pk: &Key, state: IdentifierState) -> Result<EventMessage, Error> {
-    let key_prefix = vec![pk.derive_key_prefix()];
-    let nxt_key_prefix = vec![nxt_pk.derive_key_prefix()];
-    let ixn = EventMsgBuilder::new(EventType::Rotation)?
+/// Returns true if identifier is basic prefix of non-transferable key.
+pub fn is_non_transferable(prefix: &IdentifierPrefix) -> bool {
+    match prefix {
+        IdentifierPrefix::Basic(bp) => {
+            matches!(bp.derivation_code().as_str(), "B" | "1AAA")
+        }
+        _ => false,
+    }
+}
+
+/// Makes rotation event. Witnesses which are not in `witness_config` are
+/// pruned and the new ones are grafted.
+pub fn make_rot(
+    pks: &[Key],
+    nxt_pks: &[Key],
+    threshold: &SignatureThreshold,
+    next_threshold: &SignatureThreshold,
+    witness_config: &WitnessConfig,
+    state: IdentifierState,
+) -> Result<EventMessage, Error> {
+    let key_prefix = pks.iter().map(Key::derive_key_prefix).collect();
+    let nxt_key_prefix = nxt_pks.iter().map(Key::derive_key_prefix).collect();
+    let rot = EventMsgBuilder::new(EventType::Rotation)?
         .with_prefix(state.prefix.clone())
         .with_sn(state.sn + 1)
         .with_previous_event(SelfAddressing::Blake3_256.derive(&state.last))
         .with_keys(key_prefix)
         .with_next_keys(nxt_key_prefix)
+        .with_threshold(threshold)
+        .with_next_threshold(next_threshold)
         .build()?;
-    Ok(ixn)
+
+    let rot_data = match rot.event.event_data {
+        EventData::Rot(mut rot_data) => {
+            rot_data.witness_config = RotationWitnessConfig {
+                tally: witness_config.threshold,
+                prune: state
+                    .witnesses
+                    .iter()
+                    .filter(|w| !witness_config.witnesses.contains(w))
+                    .cloned()
+                    .collect(),
+                graft: witness_config
+                    .witnesses
+                    .iter()
+                    .filter(|w| !state.witnesses.contains(w))
+                    .cloned()
+                    .collect(),
+            };
+            rot_data
+        }
+        _ => return Err(Error::Generic("Not a rotation event".into())),
+    };
+    Ok(Event {
+        prefix: rot.event.prefix,
+        sn: rot.event.sn,
+        event_data: EventData::Rot(rot_data),
+    }
+    .to_message(SerializationFormats::JSON)?)
+}
+
+/// Makes seal of digest of arbitrary data.
+pub fn make_digest_seal(data: &[u8]) -> Seal {
+    Seal::Digest(DigestSeal {
+        dig: SelfAddressing::Blake3_256.derive(data),
+    })
 }
 
-pub fn make_ixn(payload: Option<&str>, state: IdentifierState) -> Result<EventMessage, Error> {
-    let seal_list = match payload {
-        Some(payload) => {
-            vec![Seal::Digest(DigestSeal {
-                dig: SelfAddressing::Blake3_256.derive(payload.as_bytes()),
-            })]
+/// Makes rotation event which decommissions identifier. Current keys are
+/// the previously committed next keys and there is no commitment to new
+/// next keys, so the KEL can't be extended anymore.
+pub fn make_abandoning_rot(
+    pks: &[Key],
+    threshold: &SignatureThreshold,
+    state: IdentifierState,
+) -> Result<EventMessage, Error> {
+    let witness_config = WitnessConfig::from_state(&state);
+    let rot = make_rot(
+        pks,
+        &[],
+        threshold,
+        &SignatureThreshold::Simple(0),
+        &witness_config,
+        state,
+    )?;
+    let rot_data = match rot.event.event_data {
+        EventData::Rot(mut rot_data) => {
+            let key_prefix = pks.iter().map(Key::derive_key_prefix).collect();
+            rot_data.key_config = KeyConfig::new(key_prefix, None, Some(threshold.clone()));
+            rot_data
         }
-        None => vec![],
+        _ => return Err(Error::Generic("Not a rotation event".into())),
     };
+    Ok(Event {
+        prefix: rot.event.prefix,
+        sn: rot.event.sn,
+        event_data: EventData::Rot(rot_data),
+    }
+    .to_message(SerializationFormats::JSON)?)
+}
+
+/// Returns true if transferable identifier was decommissioned, which means
+/// its current keys don't commit to any next keys.
+pub fn is_decommissioned(state: &IdentifierState) -> bool {
+    state.current.threshold_key_digest.is_none() && !is_non_transferable(&state.prefix)
+}
+
+/// Makes location seal of the next delegator's event, which is going to
+/// anchor the delegated event.
+pub fn make_delegating_location_seal(delegator_state: &IdentifierState) -> LocationSeal {
+    LocationSeal {
+        prefix: delegator_state.prefix.clone(),
+        sn: delegator_state.sn + 1,
+        ilk: "ixn".into(),
+        prior_digest: SelfAddressing::Blake3_256.derive(&delegator_state.last),
+    }
+}
+
+/// Makes delegated inception event. Its prefix is always self-addressing,
+/// so delegator's seal commits to the whole inception configuration.
+pub fn make_dip(
+    pks: &[Key],
+    nxt_pks: &[Key],
+    threshold: &SignatureThreshold,
+    next_threshold: &SignatureThreshold,
+    delegator_state: &IdentifierState,
+) -> Result<EventMessage, Error> {
+    let icp = make_transferable_icp(
+        pks,
+        nxt_pks,
+        threshold,
+        next_threshold,
+        &WitnessConfig::default(),
+        PrefixType::SelfAddressing,
+    )?;
+    let inception_data = match icp.event.event_data {
+        EventData::Icp(icp_data) => icp_data,
+        _ => return Err(Error::Generic("Not an inception event".into())),
+    };
+    Ok(DelegatedInceptionEvent {
+        inception_data,
+        seal: make_delegating_location_seal(delegator_state),
+    }
+    .incept_self_addressing(SelfAddressing::Blake3_256, SerializationFormats::JSON)?)
+}
+
+/// Makes delegated rotation event, which has to be anchored in the next
+/// delegator's event.
+pub fn make_drt(
+    pks: &[Key],
+    nxt_pks: &[Key],
+    threshold: &SignatureThreshold,
+    next_threshold: &SignatureThreshold,
+    state: IdentifierState,
+    delegator_state: &IdentifierState,
+) -> Result<EventMessage, Error> {
+    let witness_config = WitnessConfig::from_state(&state);
+    let rot = make_rot(
+        pks,
+        nxt_pks,
+        threshold,
+        next_threshold,
+        &witness_config,
+        state,
+    )?;
+    let rotation_data = match rot.event.event_data {
+        EventData::Rot(rot_data) => rot_data,
+        _ => return Err(Error::Generic("Not a rotation event".into())),
+    };
+    Ok(Event {
+        prefix: rot.event.prefix,
+        sn: rot.event.sn,
+        event_data: EventData::Drt(DelegatedRotationEvent {
+            rotation_data,
+            seal: make_delegating_location_seal(delegator_state),
+        }),
+    }
+    .to_message(SerializationFormats::JSON)?)
+}
+
+/// Makes interaction event which anchors given seals.
+pub fn make_ixn(seals: Vec<Seal>, state: IdentifierState) -> Result<EventMessage, Error> {
     let ev = EventMsgBuilder::new(EventType::Interaction)?
         .with_prefix(state.prefix.clone())
         .with_sn(state.sn + 1)
         .with_previous_event(SelfAddressing::Blake3_256.derive(&state.last))
-        .with_seal(seal_list)
+        .with_seal(seals)
         .build()?;
     Ok(ev)
 }
 
+/// Makes non-transferable receipt of event. Such receipts are made by
+/// witnesses, they are signed with witness key which is also its prefix.
+pub fn make_witness_rct(event: &EventMessage) -> Result<EventMessage, Error> {
+    let ser = event.serialize()?;
+    let rcp = Event {
+        prefix: event.event.prefix.clone(),
+        sn: event.event.sn,
+        event_data: EventData::Rct(ReceiptNonTransferable {
+            receipted_event_digest: SelfAddressing::Blake3_256.derive(&ser),
+        }),
+    }
+    .to_message(SerializationFormats::JSON)?;
+    Ok(rcp)
+}
+
 pub fn make_rct(
     event: EventMessage,
     validator_seal: EventSeal,
@@ -1,24 +1,47 @@
-use event_generator::{Key, KeyType};
 use keri::{
     database::EventDatabase,
-    derivation::self_signing::SelfSigning,
-    event::{event_data::EventData, sections::seal::EventSeal, EventMessage},
+    derivation::self_addressing::SelfAddressing,
+    error::Error as KeriError,
+    event::{
+        event_data::EventData,
+        sections::{
+            seal::{EventSeal, Seal},
+            threshold::SignatureThreshold,
+            KeyConfig,
+        },
+        EventMessage,
+    },
     event_message::parse::signed_message,
     event_message::parse::{signed_event_stream, Deserialized},
-    event_message::SignedEventMessage,
+    event_message::{SignedEventMessage, SignedNontransferableReceipt},
     prefix::AttachedSignaturePrefix,
-    prefix::IdentifierPrefix,
+    prefix::{IdentifierPrefix, Prefix},
     processor::EventProcessor,
-    signer::KeyManager,
-    state::IdentifierState,
+    state::{EventSemantics, IdentifierState},
 };
 
-use crate::error::Error;
+use std::{
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::{error::Error, wallet::MultiKeyManager};
+use duplicity::{FailureKind, ProcessingFailure};
+use escrow::Escrow;
+use event_generator::{is_decommissioned, is_non_transferable, Key, PrefixType, WitnessConfig};
+use verification::{verify_each, verify_with_keys};
+pub mod duplicity;
+pub mod escrow;
 pub mod event_generator;
+pub mod pending;
+pub mod validator_receipts;
+pub mod verification;
+pub mod witness_receipts;
 
 pub struct KERL<D: EventDatabase> {
     prefix: IdentifierPrefix,
     processor: EventProcessor<D>,
+    escrow: Mutex<Escrow>,
 }
 
 impl<D: EventDatabase> KERL<D> {
@@ -27,173 +50,692 @@ impl<D: EventDatabase> KERL<D> {
         Ok(KERL {
             prefix,
             processor: EventProcessor::new(db),
+            escrow: Mutex::new(Escrow::new(escrow::DEFAULT_TIMEOUT)),
         })
     }
 
+    /// Sets how long escrowed events and receipts wait for missing events
+    /// or signatures.
+    pub fn set_escrow_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        self.escrow()?.set_timeout(timeout);
+        Ok(())
+    }
+
+    pub fn get_escrow_timeout(&self) -> Result<Duration, Error> {
+        Ok(self.escrow()?.timeout())
+    }
+
+    /// Returns number of escrowed items of given kind.
+    pub fn get_escrowed_count(&self, kind: FailureKind) -> Result<usize, Error> {
+        Ok(self.escrow()?.count(kind))
+    }
+
+    fn escrow(&self) -> Result<MutexGuard<Escrow>, Error> {
+        self.escrow
+            .lock()
+            .map_err(|_| Error::StorageError("escrow is poisoned".into()))
+    }
+
+    /// Attaches signatures made outside of key manager to the event and
+    /// processes it.
     pub fn process(
         &mut self,
         message: EventMessage,
-        signature: Vec<u8>,
+        signatures: Vec<AttachedSignaturePrefix>,
     ) -> Result<SignedEventMessage, Error> {
-        
-        let sigged = message.sign(vec![AttachedSignaturePrefix::new(
-            SelfSigning::Ed25519Sha512,
-            signature,
-            0,
-        )]);
-        self.processor
-            .process(signed_message(&sigged.serialize()?).unwrap().1)?;
-        match message.event.event_data {
-            EventData::Icp(_) => {
+        let sigged = message.sign(signatures);
+        self.process_signed(&sigged)?;
+        Ok(sigged)
+    }
+
+    /// Processes signed event. Prefix of identifier is set by its
+    /// inception event.
+    pub fn process_signed(&mut self, sigged: &SignedEventMessage) -> Result<(), Error> {
+        self.processor.process(
+            signed_message(&sigged.serialize()?)
+                .map_err(|e| Error::ProtocolError(e.to_string()))?
+                .1,
+        )?;
+        match sigged.event_message.event.event_data {
+            EventData::Icp(_) | EventData::Dip(_) => {
                 if self.prefix == IdentifierPrefix::default() {
-                    self.prefix = message.clone().event.prefix
+                    self.prefix = sigged.event_message.event.prefix.clone()
                 }
             }
             _ => {}
         };
-        Ok(sigged)
+        Ok(())
     }
 
-    pub fn incept<K: KeyManager>(&mut self, key_manager: &K) -> Result<SignedEventMessage, Error> {
-        let pk = Key::new(key_manager.public_key().0.clone(), KeyType::Ed25519Sha512);
-        let next_pk = Key::new(
-            key_manager.next_public_key().0.clone(),
-            KeyType::Ed25519Sha512,
-        );
-        let icp = event_generator::make_icp(&pk, &next_pk, Some(self.prefix.clone()))
-            .unwrap();
+    /// Processes events of other identifier, for example its KEL.
+    /// Events which are already in the database are skipped and the ones
+    /// which can't be processed yet are escrowed. Any other event which
+    /// can't be processed makes it fail. Escrowed events are retried
+    /// afterwards.
+    pub fn process_kel(&self, kel: &[u8]) -> Result<(), Error> {
+        let events = signed_event_stream(kel)
+            .map_err(|e| Error::ProtocolError(e.to_string()))?
+            .1;
+        for des_event in events {
+            if let Deserialized::Event(ev) = &des_event {
+                let event = &ev.event.event;
+                if self.is_known(event)? {
+                    continue;
+                }
+                if !self.is_acceptable(&des_event)? {
+                    return Err(Error::Decommissioned(event.event.prefix.to_str()));
+                }
+            }
+            self.process_or_escrow(des_event)?;
+        }
+        self.process_escrow()?;
+        Ok(())
+    }
 
-        let sigged = icp.sign(vec![AttachedSignaturePrefix::new(
-            SelfSigning::Ed25519Sha512,
-            key_manager.sign(&icp.serialize()?)?,
-            0,
-        )]);
+    /// Makes unsigned inception event. It can be signed outside of key
+    /// manager and processed with `process`.
+    pub fn prepare_icp(
+        &self,
+        keys: &[Key],
+        next_keys: &[Key],
+        threshold: &SignatureThreshold,
+        next_threshold: &SignatureThreshold,
+        witness_config: &WitnessConfig,
+        prefix_type: PrefixType,
+    ) -> Result<EventMessage, Error> {
+        event_generator::make_icp(
+            keys,
+            next_keys,
+            threshold,
+            next_threshold,
+            witness_config,
+            prefix_type,
+        )
+    }
 
-        self.processor
-            .process(signed_message(&sigged.serialize()?).unwrap().1)?;
+    /// Makes unsigned rotation event. If `witness_config` is None, current
+    /// witnesses are kept.
+    pub fn prepare_rot(
+        &self,
+        keys: &[Key],
+        next_keys: &[Key],
+        threshold: &SignatureThreshold,
+        next_threshold: &SignatureThreshold,
+        witness_config: Option<&WitnessConfig>,
+    ) -> Result<EventMessage, Error> {
+        let state = self
+            .get_state()?
+            .ok_or_else(|| Error::KelNotFound(self.prefix.to_str()))?;
+        let witness_config = witness_config
+            .cloned()
+            .unwrap_or(WitnessConfig::from_state(&state));
+        event_generator::make_rot(
+            keys,
+            next_keys,
+            threshold,
+            next_threshold,
+            &witness_config,
+            state,
+        )
+    }
+
+    /// Makes unsigned interaction event which anchors given seals.
+    pub fn prepare_ixn(&self, seals: Vec<Seal>) -> Result<EventMessage, Error> {
+        let state = self
+            .get_state()?
+            .ok_or_else(|| Error::KelNotFound(self.prefix.to_str()))?;
+        event_generator::make_ixn(seals, state)
+    }
+
+    pub fn incept<K: MultiKeyManager>(
+        &mut self,
+        key_manager: &K,
+        witness_config: &WitnessConfig,
+        prefix_type: PrefixType,
+    ) -> Result<SignedEventMessage, Error> {
+        let icp = self.prepare_icp(
+            &key_manager.public_keys(),
+            &key_manager.next_public_keys(),
+            &key_manager.threshold(),
+            &key_manager.next_threshold(),
+            witness_config,
+            prefix_type,
+        )?;
+
+        let sigged = icp.sign(key_manager.sign_indexed(&icp.serialize()?)?);
+
+        self.processor.process(
+            signed_message(&sigged.serialize()?)
+                .map_err(|e| Error::ProtocolError(e.to_string()))?
+                .1,
+        )?;
 
         self.prefix = icp.event.prefix;
 
         Ok(sigged)
     }
 
-    pub fn rotate<K: KeyManager>(
+    /// Rotates keys. If `witness_config` is None, current witnesses are
+    /// kept. Key manager is rotated only when rotation event is accepted,
+    /// so it never gets out of sync with the KEL.
+    pub fn rotate<K: MultiKeyManager>(
         &mut self,
         key_manager: &mut K,
+        witness_config: Option<&WitnessConfig>,
     ) -> Result<SignedEventMessage, Error> {
+        key_manager.prepare_rotation()?;
+        let rot = self.prepare_rot(
+            &key_manager.next_public_keys(),
+            &key_manager.pending_public_keys(),
+            &key_manager.next_threshold(),
+            &key_manager.next_threshold(),
+            witness_config,
+        )?;
+
+        let rot = rot.sign(key_manager.sign_indexed_next(&rot.serialize()?)?);
+
+        self.processor.process(
+            signed_message(&rot.serialize()?)
+                .map_err(|e| Error::ProtocolError(e.to_string()))?
+                .1,
+        )?;
         key_manager.rotate()?;
-        let pk = Key::new(key_manager.public_key().0.clone(), KeyType::Ed25519Sha512);
-        let next_pk = Key::new(
-            key_manager.next_public_key().0.clone(),
-            KeyType::Ed25519Sha512,
-        );
-        let rot = event_generator::make_rot(&pk, &next_pk, self.get_state()?.unwrap()).unwrap();
-
-        let rot = rot.sign(vec![AttachedSignaturePrefix::new(
-            SelfSigning::Ed25519Sha512,
-            key_manager.sign(&rot.serialize()?)?,
-            0,
-        )]);
 
-        self.processor
-            .process(signed_message(&rot.serialize()?).unwrap().1)?;
+        Ok(rot)
+    }
+
+    /// Processes signed event stream, like exported KEL. Events which are
+    /// already in the database are skipped and the ones which can't be
+    /// processed yet are escrowed. Any other event or receipt which can't
+    /// be processed makes the import fail. Returns processed witness
+    /// receipts.
+    pub fn import(&self, stream: &[u8]) -> Result<Vec<SignedNontransferableReceipt>, Error> {
+        let events = signed_event_stream(stream)
+            .map_err(|e| Error::ProtocolError(e.to_string()))?
+            .1;
+        // Events conflicting with the database are looked for before
+        // anything is processed.
+        for des_event in &events {
+            if let Deserialized::Event(ev) = des_event {
+                let event = &ev.event.event;
+                if self.is_known(event)? {
+                    continue;
+                }
+                if self
+                    .get_event_at_sn(&event.event.prefix, event.event.sn)?
+                    .is_some()
+                {
+                    return Err(Error::Duplicitous(event.event.prefix.to_str()));
+                }
+                if !self.is_acceptable(des_event)? {
+                    return Err(Error::Decommissioned(event.event.prefix.to_str()));
+                }
+            }
+        }
+        let mut witness_receipts = vec![];
+        for des_event in events {
+            let rct = match &des_event {
+                Deserialized::Event(ev) => {
+                    let event = &ev.event.event;
+                    if self.is_known(event)? {
+                        continue;
+                    }
+                    if !self.is_acceptable(&des_event)? {
+                        return Err(Error::Decommissioned(event.event.prefix.to_str()));
+                    }
+                    None
+                }
+                Deserialized::Rct(rct) => Some(rct.clone()),
+                Deserialized::Vrc(_) => None,
+            };
+            if self.process_or_escrow(des_event)? {
+                witness_receipts.extend(rct);
+            }
+        }
+        self.process_escrow()?;
+        Ok(witness_receipts)
+    }
+
+    /// Makes final rotation to the next keys, without committing to new
+    /// ones. After that identifier can't make any new events.
+    pub fn decommission<K: MultiKeyManager>(
+        &mut self,
+        key_manager: &mut K,
+    ) -> Result<SignedEventMessage, Error> {
+        let state = self
+            .get_state()?
+            .ok_or_else(|| Error::KelNotFound(self.prefix.to_str()))?;
+        let rot = event_generator::make_abandoning_rot(
+            &key_manager.next_public_keys(),
+            &key_manager.next_threshold(),
+            state,
+        )?;
+
+        let rot = rot.sign(key_manager.sign_indexed_next(&rot.serialize()?)?);
+
+        self.processor.process(
+            signed_message(&rot.serialize()?)
+                .map_err(|e| Error::ProtocolError(e.to_string()))?
+                .1,
+        )?;
+        key_manager.abandon()?;
 
         Ok(rot)
     }
 
-    pub fn make_ixn<K: KeyManager>(
+    /// Makes signed delegated inception event. It's not processed, because
+    /// it has to be approved by delegator first.
+    pub fn make_dip<K: MultiKeyManager>(
+        &self,
+        key_manager: &K,
+        delegator_state: &IdentifierState,
+    ) -> Result<SignedEventMessage, Error> {
+        let dip = event_generator::make_dip(
+            &key_manager.public_keys(),
+            &key_manager.next_public_keys(),
+            &key_manager.threshold(),
+            &key_manager.next_threshold(),
+            delegator_state,
+        )?;
+        let signatures = key_manager.sign_indexed(&dip.serialize()?)?;
+        Ok(dip.sign(signatures))
+    }
+
+    /// Makes signed delegated rotation event to the next keys. It's not
+    /// processed, because it has to be approved by delegator first, and
+    /// key manager isn't rotated until it's accepted.
+    pub fn make_drt<K: MultiKeyManager>(
+        &self,
+        key_manager: &mut K,
+        delegator_state: &IdentifierState,
+    ) -> Result<SignedEventMessage, Error> {
+        let state = self
+            .get_state()?
+            .ok_or_else(|| Error::KelNotFound(self.prefix.to_str()))?;
+        key_manager.prepare_rotation()?;
+        let drt = event_generator::make_drt(
+            &key_manager.next_public_keys(),
+            &key_manager.pending_public_keys(),
+            &key_manager.next_threshold(),
+            &key_manager.next_threshold(),
+            state,
+            delegator_state,
+        )?;
+        let signatures = key_manager.sign_indexed_next(&drt.serialize()?)?;
+        Ok(drt.sign(signatures))
+    }
+
+    /// Checks if delegated rotation fits the KEL of its identifier and is
+    /// signed by keys it rotates to, so it can be approved by delegator.
+    pub fn check_drt(&self, drt: &SignedEventMessage) -> Result<(), Error> {
+        let event = &drt.event_message;
+        if !matches!(event.event.event_data, EventData::Drt(_)) {
+            return Err(Error::Generic("Event is not delegated rotation".into()));
+        }
+        let state = self
+            .get_state_for_prefix(&event.event.prefix)?
+            .ok_or_else(|| Error::KelNotFound(event.event.prefix.to_str()))?;
+        let new_state = event.apply_to(state)?;
+        if !verify_with_keys(&new_state.current, &event.serialize()?, &drt.signatures)? {
+            return Err(Error::InvalidSignature(format!(
+                "delegated rotation {} of {}",
+                event.event.sn,
+                event.event.prefix.to_str()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Approves delegated event by anchoring its seal in interaction event.
+    pub fn approve_delegated<K: MultiKeyManager>(
         &mut self,
-        payload: Option<&str>,
+        delegated: &EventMessage,
         key_manager: &K,
     ) -> Result<SignedEventMessage, Error> {
-        let state = self.get_state()?.unwrap();
+        let seal = Seal::Event(EventSeal {
+            prefix: delegated.event.prefix.clone(),
+            sn: delegated.event.sn,
+            event_digest: SelfAddressing::Blake3_256.derive(&delegated.serialize()?),
+        });
+        self.make_ixn(vec![seal], key_manager)
+    }
 
-        let ev = event_generator::make_ixn(payload, state).unwrap();
+    /// Makes interaction event which anchors given seals.
+    pub fn make_ixn<K: MultiKeyManager>(
+        &mut self,
+        seals: Vec<Seal>,
+        key_manager: &K,
+    ) -> Result<SignedEventMessage, Error> {
+        let ev = self.prepare_ixn(seals)?;
 
-        let ixn = ev.sign(vec![AttachedSignaturePrefix::new(
-            SelfSigning::Ed25519Sha512,
-            key_manager.sign(&ev.serialize()?)?,
-            0,
-        )]);
+        let ixn = ev.sign(key_manager.sign_indexed(&ev.serialize()?)?);
 
-        self.processor
-            .process(signed_message(&ixn.serialize()?).unwrap().1)?;
+        self.processor.process(
+            signed_message(&ixn.serialize()?)
+                .map_err(|e| Error::ProtocolError(e.to_string()))?
+                .1,
+        )?;
 
         Ok(ixn)
     }
 
-    pub fn respond<K: KeyManager>(&self, msg: &[u8], key_manager: &K) -> Result<Vec<u8>, Error> {
+    /// Processes events and makes receipts of accepted ones. Returns
+    /// response along with events which couldn't be accepted. Out of order
+    /// and partially signed events, as well as receipts of unknown events,
+    /// are escrowed and retried whenever some new event is accepted.
+    pub fn respond<K: MultiKeyManager>(
+        &self,
+        msg: &[u8],
+        key_manager: &K,
+    ) -> Result<(Vec<u8>, Vec<ProcessingFailure>), Error> {
         let events = signed_event_stream(msg)
-            .map_err(|e| Error::Generic(e.to_string()))?
+            .map_err(|e| Error::ProtocolError(e.to_string()))?
             .1;
-        let (processed_ok, _processed_failed): (Vec<_>, Vec<_>) = events
+        let (processed_ok, processed_failed): (Vec<_>, Vec<_>) = events
             .into_iter()
-            .map(|event| {
-                self.processor
-                    .process(event.clone())
-                    .and_then(|_| Ok(event))
+            .filter(|event| self.is_acceptable(event).unwrap_or(false))
+            .map(|event| match self.processor.process(event.clone()) {
+                Ok(_) => Ok(event),
+                Err(e) => Err((event, e)),
             })
             .partition(Result::is_ok);
-        let response: Vec<u8> = processed_ok
+        let mut failures = vec![];
+        for (des_event, error) in processed_failed.into_iter().filter_map(Result::err) {
+            if let Some(kind) = self.escrow_failure(&des_event, &error)? {
+                if let Deserialized::Event(ev) = des_event {
+                    let event = ev.event.event.clone().sign(ev.signatures.clone());
+                    failures.push(ProcessingFailure { kind, event });
+                }
+            }
+        }
+        let mut response: Vec<u8> = processed_ok
             .into_iter()
             .map(Result::unwrap)
-            .map(|des_event| -> Result<Vec<u8>, Error> {
-                match des_event {
-                    Deserialized::Event(ev) => {
-                        let mut buf = vec![];
-                        if let EventData::Icp(_) = ev.event.event.event.event_data {
-                            if !self.processor.has_receipt(
-                                &self.prefix,
-                                0,
-                                &ev.event.event.event.prefix,
-                            )? {
-                                buf.append(
-                                    &mut self
-                                        .processor
-                                        .get_kerl(&self.prefix)?
-                                        .ok_or(Error::Generic("KEL is empty".into()))?,
-                                )
-                            }
+            .filter_map(|des_event| self.make_response(des_event, key_manager).ok())
+            .flatten()
+            .collect();
+        // Escrowed events accepted thanks to this message are receipted
+        // too.
+        for processed in self.process_escrow()? {
+            let des_events = signed_event_stream(&processed)
+                .map_err(|e| Error::ProtocolError(e.to_string()))?
+                .1;
+            for des_event in des_events {
+                if let Ok(mut buf) = self.make_response(des_event, key_manager) {
+                    response.append(&mut buf);
+                }
+            }
+        }
+        Ok((response, failures))
+    }
+
+    /// Makes receipt of accepted event. Receipt of inception event is
+    /// preceded by own KEL, if the other side doesn't know it yet.
+    fn make_response<K: MultiKeyManager>(
+        &self,
+        des_event: Deserialized,
+        key_manager: &K,
+    ) -> Result<Vec<u8>, Error> {
+        // Witness doesn't need to share its KEL, receipt is verifiable with
+        // its prefix.
+        let is_witness = is_non_transferable(&self.prefix);
+        match des_event {
+            Deserialized::Event(ev) if is_witness => {
+                match self.make_witness_rct(ev.event.event.clone(), key_manager)? {
+                    Some(rct) => Ok(rct.serialize()?),
+                    None => Ok(vec![]),
+                }
+            }
+            Deserialized::Event(ev) => {
+                let mut buf = vec![];
+                if let EventData::Icp(_) = ev.event.event.event.event_data {
+                    if !self
+                        .processor
+                        .has_receipt(&self.prefix, 0, &ev.event.event.event.prefix)?
+                    {
+                        // Delegated KEL can't be accepted without
+                        // delegator's KEL, so it goes first.
+                        if let Some(delegator) = self.get_delegator(&self.prefix)? {
+                            buf.append(
+                                &mut self
+                                    .processor
+                                    .get_kerl(&delegator)?
+                                    .ok_or_else(|| Error::KelNotFound(delegator.to_str()))?,
+                            )
                         }
                         buf.append(
                             &mut self
-                                .make_rct(ev.event.event.clone(), key_manager)?
-                                .serialize()?,
-                        );
-                        Ok(buf)
+                                .processor
+                                .get_kerl(&self.prefix)?
+                                .ok_or_else(|| Error::KelNotFound(self.prefix.to_str()))?,
+                        )
                     }
-                    _ => Ok(vec![]),
                 }
-            })
-            .filter_map(|x| x.ok())
-            .flatten()
-            .collect();
-        Ok(response)
+                buf.append(
+                    &mut self
+                        .make_rct(ev.event.event.clone(), key_manager)?
+                        .serialize()?,
+                );
+                Ok(buf)
+            }
+            _ => Ok(vec![]),
+        }
+    }
+
+    /// Processes event or receipt. Returns false if it can't be processed
+    /// yet and it's escrowed, any other failure is an error.
+    fn process_or_escrow(&self, des_event: Deserialized) -> Result<bool, Error> {
+        let error = match self.processor.process(des_event.clone()) {
+            Ok(_) => return Ok(true),
+            Err(e) => e,
+        };
+        match self.escrow_failure(&des_event, &error)? {
+            Some(kind) if kind.is_escrowed() => Ok(false),
+            Some(_) => Err(Error::KeriError(error)),
+            None => Ok(true),
+        }
+    }
+
+    /// Escrows event or receipt which failed processing with `error`, if
+    /// it can be processed later. Returns the reason of failure, or None if
+    /// the same event is already accepted.
+    fn escrow_failure(
+        &self,
+        des_event: &Deserialized,
+        error: &KeriError,
+    ) -> Result<Option<FailureKind>, Error> {
+        match des_event {
+            Deserialized::Event(ev) => {
+                let event = ev.event.event.clone().sign(ev.signatures.clone());
+                let kind = match self.classify_failure(&event, error)? {
+                    // Only valid signatures are collected, so invalid one
+                    // can't take place of valid one of the same index.
+                    Some(FailureKind::PartiallySigned) if !self.has_valid_signatures(&event)? => {
+                        FailureKind::BadSignature
+                    }
+                    Some(kind) => kind,
+                    None => return Ok(None),
+                };
+                if kind.is_escrowed() {
+                    self.escrow()?.add_event(kind, event)?;
+                }
+                Ok(Some(kind))
+            }
+            Deserialized::Vrc(vrc) => self
+                .escrow_receipt(&vrc.event_message, vrc.serialize()?)
+                .map(Some),
+            Deserialized::Rct(rct) => self.escrow_receipt(&rct.body, rct.serialize()?).map(Some),
+        }
     }
 
-    fn make_rct<K: KeyManager>(
+    /// Escrows receipt if the receipted event isn't known yet.
+    fn escrow_receipt(
+        &self,
+        receipt: &EventMessage,
+        serialized: Vec<u8>,
+    ) -> Result<FailureKind, Error> {
+        if self
+            .get_event_at_sn(&receipt.event.prefix, receipt.event.sn)?
+            .is_none()
+        {
+            self.escrow()?.add_receipt(serialized);
+            return Ok(FailureKind::UnknownEvent);
+        }
+        Ok(FailureKind::Unknown)
+    }
+
+    /// Checks if all signatures of event are made with keys which should
+    /// sign it.
+    fn has_valid_signatures(&self, event: &SignedEventMessage) -> Result<bool, Error> {
+        Ok(match self.get_signing_keys(&event.event_message)? {
+            Some(keys) => verify_each(&keys, &event.event_message.serialize()?, &event.signatures)
+                .unwrap_or(false),
+            None => false,
+        })
+    }
+
+    /// Retries processing of escrowed events and receipts. Returns the
+    /// ones which got processed.
+    fn process_escrow(&self) -> Result<Vec<Vec<u8>>, Error> {
+        let mut processed = vec![];
+        self.escrow()?.retry(|msg| {
+            let accepted = match signed_event_stream(msg) {
+                Ok((_, des_events)) => des_events.into_iter().all(|des_event| {
+                    self.is_acceptable(&des_event).unwrap_or(false)
+                        && self.processor.process(des_event).is_ok()
+                }),
+                Err(_) => false,
+            };
+            if accepted {
+                processed.push(msg.to_vec());
+            }
+            // Other copy of the same event may be accepted already.
+            accepted || self.is_accepted_event(msg)
+        });
+        Ok(processed)
+    }
+
+    /// Finds out why event couldn't be processed from error returned by
+    /// event processor. Returns None if the same event is already
+    /// accepted.
+    fn classify_failure(
+        &self,
+        event: &SignedEventMessage,
+        error: &KeriError,
+    ) -> Result<Option<FailureKind>, Error> {
+        let prefix = &event.event_message.event.prefix;
+        Ok(Some(match error {
+            KeriError::EventOutOfOrderError => match self.get_state_for_prefix(prefix)? {
+                Some(_) => FailureKind::OutOfOrder,
+                None => FailureKind::UnknownPrefix,
+            },
+            KeriError::EventDuplicateError => {
+                let accepted = self
+                    .get_event_at_sn(prefix, event.event_message.event.sn)?
+                    .map(|ev| ev.serialize())
+                    .transpose()?;
+                if accepted == Some(event.event_message.serialize()?) {
+                    return Ok(None);
+                }
+                FailureKind::Duplicitous
+            }
+            KeriError::NotEnoughSigsError => FailureKind::PartiallySigned,
+            KeriError::SignatureVerificationError | KeriError::FaultySignatureVerification => {
+                FailureKind::BadSignature
+            }
+            _ => FailureKind::Unknown,
+        }))
+    }
+
+    /// Returns keys which should sign given event, if they can be found
+    /// out from accepted events. Keys of inception event have to be bound
+    /// to its basic or self-addressing prefix.
+    pub fn get_signing_keys(&self, message: &EventMessage) -> Result<Option<KeyConfig>, Error> {
+        let event = &message.event;
+        let previous_keys = match event.sn {
+            0 => None,
+            sn => self
+                .get_state_at_sn(&event.prefix, sn - 1)?
+                .map(|state| state.current),
+        };
+        Ok(match (&event.event_data, previous_keys) {
+            (EventData::Icp(icp), _) => match icp.key_config.public_keys.get(0) {
+                Some(key) if IdentifierPrefix::Basic(key.clone()) == event.prefix => {
+                    Some(icp.key_config.clone())
+                }
+                _ if is_self_addressing_bound(message)? => Some(icp.key_config.clone()),
+                _ => None,
+            },
+            (EventData::Dip(dip), _) if is_self_addressing_bound(message)? => {
+                Some(dip.inception_data.key_config.clone())
+            }
+            (EventData::Ixn(_), Some(previous)) => Some(previous),
+            (EventData::Rot(rot), Some(previous)) if previous.verify_next(&rot.key_config) => {
+                Some(rot.key_config.clone())
+            }
+            (EventData::Drt(drt), Some(previous))
+                if previous.verify_next(&drt.rotation_data.key_config) =>
+            {
+                Some(drt.rotation_data.key_config.clone())
+            }
+            _ => None,
+        })
+    }
+
+    /// Makes witness receipt of event. Returns None if this identifier
+    /// isn't designated as witness of event's identifier.
+    fn make_witness_rct<K: MultiKeyManager>(
+        &self,
+        event: EventMessage,
+        key_manager: &K,
+    ) -> Result<Option<SignedNontransferableReceipt>, Error> {
+        let witness = match &self.prefix {
+            IdentifierPrefix::Basic(bp) => bp.clone(),
+            _ => return Err(Error::Generic("Witness prefix has to be basic".into())),
+        };
+        let designated = self
+            .get_state_for_prefix(&event.event.prefix)?
+            .map(|state| state.witnesses.contains(&witness))
+            .unwrap_or(false);
+        if !designated {
+            return Ok(None);
+        }
+        let signature = key_manager
+            .sign_indexed(&event.serialize()?)?
+            .into_iter()
+            .next()
+            .ok_or(Error::Generic("There is no witness key".into()))?
+            .signature;
+        let rct = event_generator::make_witness_rct(&event)?;
+        Ok(Some(SignedNontransferableReceipt::new(
+            &rct,
+            vec![(witness, signature)],
+        )))
+    }
+
+    fn make_rct<K: MultiKeyManager>(
         &self,
         event: EventMessage,
         key_manager: &K,
     ) -> Result<SignedEventMessage, Error> {
         let ser = event.serialize()?;
-        let signature = key_manager.sign(&ser)?;
+        let signatures = key_manager.sign_indexed(&ser)?;
         let validator_event_seal = self
             .processor
             .get_last_establishment_event_seal(&self.prefix)?
-            .ok_or(Error::Generic("No establishment event seal".into()))?;
+            .ok_or_else(|| Error::KelNotFound(self.prefix.to_str()))?;
+        let state = self
+            .get_state()?
+            .ok_or_else(|| Error::KelNotFound(self.prefix.to_str()))?;
 
-        let rcp =
-            event_generator::make_rct(event, validator_event_seal, self.get_state()?.unwrap())
-                .unwrap();
+        let rcp = event_generator::make_rct(event, validator_event_seal, state)?;
 
-        let rcp = rcp.sign(vec![AttachedSignaturePrefix::new(
-            SelfSigning::Ed25519Sha512,
-            signature,
-            0,
-        )]);
-        self.processor
-            .process(signed_message(&rcp.serialize()?).unwrap().1)?;
+        let rcp = rcp.sign(signatures);
+        self.processor.process(
+            signed_message(&rcp.serialize()?)
+                .map_err(|e| Error::ProtocolError(e.to_string()))?
+                .1,
+        )?;
 
         Ok(rcp)
     }
@@ -210,6 +752,15 @@ impl<D: EventDatabase> KERL<D> {
             .map_err(|e| Error::KeriError(e))
     }
 
+    pub fn get_kerl_for_prefix(
+        &self,
+        prefix: &IdentifierPrefix,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        self.processor
+            .get_kerl(prefix)
+            .map_err(|e| Error::KeriError(e))
+    }
+
     pub fn get_state_for_prefix(
         &self,
         prefix: &IdentifierPrefix,
@@ -219,9 +770,205 @@ impl<D: EventDatabase> KERL<D> {
             .map_err(|e| Error::KeriError(e))
     }
 
+    /// Checks if event processor has receipt of given validator for event
+    /// of given identifier and sn.
+    pub fn has_receipt(
+        &self,
+        prefix: &IdentifierPrefix,
+        sn: u64,
+        validator: &IdentifierPrefix,
+    ) -> Result<bool, Error> {
+        self.processor
+            .has_receipt(prefix, sn, validator)
+            .map_err(|e| Error::KeriError(e))
+    }
+
+    /// Returns event of given identifier and sn, if it's in the KEL.
+    pub fn get_event_at_sn(
+        &self,
+        prefix: &IdentifierPrefix,
+        sn: u64,
+    ) -> Result<Option<EventMessage>, Error> {
+        Ok(self
+            .get_events(prefix)?
+            .into_iter()
+            .find(|ev| ev.event.sn == sn))
+    }
+
+    /// Returns signed event of given identifier and sn, if it's in the KEL.
+    pub fn get_signed_event_at_sn(
+        &self,
+        prefix: &IdentifierPrefix,
+        sn: u64,
+    ) -> Result<Option<SignedEventMessage>, Error> {
+        let kerl = match self.processor.get_kerl(prefix)? {
+            Some(kerl) => kerl,
+            None => return Ok(None),
+        };
+        Ok(signed_event_stream(&kerl)
+            .map_err(|e| Error::ProtocolError(e.to_string()))?
+            .1
+            .into_iter()
+            .find_map(|des_event| match des_event {
+                Deserialized::Event(ev) if ev.event.event.event.sn == sn => {
+                    Some(ev.event.event.sign(ev.signatures.clone()))
+                }
+                _ => None,
+            }))
+    }
+
+    /// Returns sn and keys of each establishment event of given identifier.
+    pub fn get_establishment_events(
+        &self,
+        prefix: &IdentifierPrefix,
+    ) -> Result<Vec<(u64, KeyConfig)>, Error> {
+        Ok(self
+            .get_events(prefix)?
+            .into_iter()
+            .filter_map(|ev| {
+                let keys = match ev.event.event_data {
+                    EventData::Icp(icp) => icp.key_config,
+                    EventData::Rot(rot) => rot.key_config,
+                    EventData::Dip(dip) => dip.inception_data.key_config,
+                    EventData::Drt(drt) => drt.rotation_data.key_config,
+                    _ => return None,
+                };
+                Some((ev.event.sn, keys))
+            })
+            .collect())
+    }
+
+    /// Returns events of KEL of given identifier, without signatures.
+    fn get_events(&self, prefix: &IdentifierPrefix) -> Result<Vec<EventMessage>, Error> {
+        let kerl = match self.processor.get_kerl(prefix)? {
+            Some(kerl) => kerl,
+            None => return Ok(vec![]),
+        };
+        Ok(signed_event_stream(&kerl)
+            .map_err(|e| Error::ProtocolError(e.to_string()))?
+            .1
+            .into_iter()
+            .filter_map(|des_event| match des_event {
+                Deserialized::Event(ev) => Some(ev.event.event.clone()),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Returns true if message is event which is already in the database.
+    fn is_accepted_event(&self, msg: &[u8]) -> bool {
+        match signed_message(msg) {
+            Ok((_, Deserialized::Event(ev))) => self.is_known(&ev.event.event).unwrap_or(false),
+            _ => false,
+        }
+    }
+
+    /// Returns true if the same event is already in the database.
+    fn is_known(&self, event: &EventMessage) -> Result<bool, Error> {
+        Ok(self
+            .get_event_at_sn(&event.event.prefix, event.event.sn)?
+            .map(|known| -> Result<bool, Error> { Ok(known.serialize()? == event.serialize()?) })
+            .transpose()?
+            .unwrap_or(false))
+    }
+
+    /// Returns false for new events of decommissioned identifiers. Their
+    /// KEL can't be extended anymore.
+    fn is_acceptable(&self, des_event: &Deserialized) -> Result<bool, Error> {
+        if let Deserialized::Event(ev) = des_event {
+            let event = &ev.event.event.event;
+            if let Some(state) = self.get_state_for_prefix(&event.prefix)? {
+                return Ok(!(is_decommissioned(&state) && event.sn > state.sn));
+            }
+        }
+        Ok(true)
+    }
+
+    /// Returns delegator of identifier, if it's delegated.
+    pub fn get_delegator(
+        &self,
+        prefix: &IdentifierPrefix,
+    ) -> Result<Option<IdentifierPrefix>, Error> {
+        Ok(self
+            .get_event_at_sn(prefix, 0)?
+            .and_then(|ev| match ev.event.event_data {
+                EventData::Dip(dip) => Some(dip.seal.prefix),
+                _ => None,
+            }))
+    }
+
+    /// Checks if all establishment events of delegated identifier are
+    /// anchored in delegator's KEL. Delegation of delegator is checked
+    /// too, up to the root of delegation chain.
+    pub fn check_delegation(&self, prefix: &IdentifierPrefix) -> Result<(), Error> {
+        let delegator = match self.get_delegator(prefix)? {
+            Some(delegator) => delegator,
+            None => return Ok(()),
+        };
+        let anchored: Vec<EventSeal> = self
+            .get_events(&delegator)?
+            .into_iter()
+            .flat_map(|ev| match ev.event.event_data {
+                EventData::Ixn(ixn) => ixn.data,
+                EventData::Rot(rot) => rot.data,
+                _ => vec![],
+            })
+            .filter_map(|seal| match seal {
+                Seal::Event(event_seal) => Some(event_seal),
+                _ => None,
+            })
+            .collect();
+
+        for ev in self.get_events(prefix)? {
+            if let EventData::Dip(_) | EventData::Drt(_) = ev.event.event_data {
+                let digest = SelfAddressing::Blake3_256.derive(&ev.serialize()?);
+                let approved = anchored.iter().any(|seal| {
+                    &seal.prefix == prefix && seal.sn == ev.event.sn && seal.event_digest == digest
+                });
+                if !approved {
+                    return Err(Error::InvalidSignature(format!(
+                        "Event {} of {} is not approved by delegator",
+                        ev.event.sn,
+                        prefix.to_str()
+                    )));
+                }
+            }
+        }
+
+        self.check_delegation(&delegator)
+    }
+
+    pub fn get_state_at_sn(
+        &self,
+        prefix: &IdentifierPrefix,
+        sn: u64,
+    ) -> Result<Option<IdentifierState>, Error> {
+        self.processor
+            .compute_state_at_sn(prefix, sn)
+            .map_err(|e| Error::KeriError(e))
+    }
+
     pub fn get_state_for_seal(&self, seal: &EventSeal) -> Result<Option<IdentifierState>, Error> {
         self.processor
             .compute_state_at_sn(&seal.prefix, seal.sn)
             .map_err(|e| Error::KeriError(e))
     }
 }
+/// Checks if self-addressing prefix of inception event is digest of the
+/// event with prefix replaced by placeholder of the same length, as it's
+/// computed when identifier is incepted.
+fn is_self_addressing_bound(message: &EventMessage) -> Result<bool, Error> {
+    let prefix = match &message.event.prefix {
+        IdentifierPrefix::SelfAddressing(prefix) => prefix,
+        _ => return Ok(false),
+    };
+    let serialized = String::from_utf8(message.serialize()?)?;
+    let prefix_field = format!(r#""i":"{}""#, prefix.to_str());
+    if !serialized.contains(&prefix_field) {
+        return Ok(false);
+    }
+    let dummy_field = format!(r#""i":"{}""#, "#".repeat(prefix.to_str().len()));
+    let dummy = serialized.replacen(&prefix_field, &dummy_field, 1);
+    Ok(prefix.verify_binding(dummy.as_bytes()))
+}
+
@@ -0,0 +1,115 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use keri::{
+    event::EventMessage,
+    prefix::{AttachedSignaturePrefix, Prefix},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, storage::write_atomically};
+
+const PENDING_FILE: &str = "pending_event";
+
+/// Event waiting for signatures, as kept in file.
+#[derive(Serialize, Deserialize)]
+struct PendingRecord {
+    event: String,
+    signatures: Vec<String>,
+    document: Option<String>,
+}
+
+/// Event prepared for signing outside, along with signatures collected so
+/// far.
+pub struct PendingEvent {
+    pub event: EventMessage,
+    pub signatures: Vec<AttachedSignaturePrefix>,
+    /// Serialized document anchored by the event. It's stored when the
+    /// event is committed.
+    pub document: Option<String>,
+}
+
+/// Event waiting for signatures. It's kept in file in event database
+/// directory, so signing can be finished after restart. There is at most
+/// one pending event at a time.
+pub struct PendingStore {
+    path: PathBuf,
+}
+
+impl PendingStore {
+    pub fn new(db_path: &str) -> Self {
+        Self {
+            path: Path::new(db_path).join(PENDING_FILE),
+        }
+    }
+
+    pub fn get(&self) -> Result<Option<PendingEvent>, Error> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let storage_error =
+            |e: serde_json::Error| Error::StorageError(format!("{}: {}", self.path.display(), e));
+        let record: PendingRecord =
+            serde_json::from_slice(&fs::read(&self.path)?).map_err(storage_error)?;
+        Ok(Some(PendingEvent {
+            event: serde_json::from_str(&record.event).map_err(storage_error)?,
+            signatures: record
+                .signatures
+                .iter()
+                .map(|sig| sig.parse())
+                .collect::<Result<_, _>>()?,
+            document: record.document,
+        }))
+    }
+
+    /// Stores new event waiting for signatures. It fails if other event
+    /// is already waiting.
+    pub fn set(&self, event: EventMessage, document: Option<String>) -> Result<(), Error> {
+        if self.path.exists() {
+            return Err(Error::Generic(
+                "Other event is waiting for signatures".into(),
+            ));
+        }
+        self.save(&PendingEvent {
+            event,
+            signatures: vec![],
+            document,
+        })
+    }
+
+    /// Adds detached signatures of pending event. Signature replaces the
+    /// previous one made with key of the same index.
+    pub fn add_signatures(&self, signatures: &[AttachedSignaturePrefix]) -> Result<(), Error> {
+        let mut pending = self
+            .get()?
+            .ok_or(Error::Generic("There is no pending event".into()))?;
+        for signature in signatures {
+            pending
+                .signatures
+                .retain(|sig| sig.index != signature.index);
+            pending.signatures.push(signature.clone());
+        }
+        self.save(&pending)
+    }
+
+    /// Drops pending event along with its signatures.
+    pub fn clear(&self) -> Result<(), Error> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+
+    fn save(&self, pending: &PendingEvent) -> Result<(), Error> {
+        let record = PendingRecord {
+            event: String::from_utf8(pending.event.serialize()?)?,
+            signatures: pending.signatures.iter().map(|sig| sig.to_str()).collect(),
+            document: pending.document.clone(),
+        };
+        let content =
+            serde_json::to_vec(&record).map_err(|e| Error::StorageError(e.to_string()))?;
+        write_atomically(&self.path, &content)
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+use keri::{
+    event::sections::seal::EventSeal,
+    prefix::{IdentifierPrefix, Prefix},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, storage::RecordLog};
+
+const RECEIPTS_FILE: &str = "validator_receipts";
+
+/// Seal of validator's establishment event, which has keys used for
+/// signing receipt of event of given identifier and sn.
+#[derive(Serialize, Deserialize)]
+struct ValidatorReceipt {
+    prefix: String,
+    sn: u64,
+    seal: EventSeal,
+}
+
+/// Index of transferable receipts of other identifiers. Receipts itself
+/// are kept by event processor, which can't list validators of an event,
+/// so their seals are kept in file in event database directory.
+pub struct ValidatorReceipts {
+    log: RecordLog<ValidatorReceipt>,
+}
+
+impl ValidatorReceipts {
+    pub fn new(db_path: &str) -> Self {
+        Self {
+            log: RecordLog::new(Path::new(db_path).join(RECEIPTS_FILE))
+                .with_legacy(legacy_receipts),
+        }
+    }
+
+    /// Stores seal of validator. Receipt is expected to be already
+    /// verified and processed.
+    pub fn add(&self, prefix: &IdentifierPrefix, sn: u64, seal: &EventSeal) -> Result<(), Error> {
+        self.log.append(&ValidatorReceipt {
+            prefix: prefix.to_str(),
+            sn,
+            seal: seal.clone(),
+        })
+    }
+
+    /// Returns seals of validators which receipted event of given sn.
+    pub fn get(&self, prefix: &IdentifierPrefix, sn: u64) -> Result<Vec<EventSeal>, Error> {
+        let prefix = prefix.to_str();
+        // The latest receipt of validator replaces the previous ones.
+        let seals: BTreeMap<String, EventSeal> = self
+            .log
+            .load()?
+            .into_iter()
+            .filter(|receipt| receipt.prefix == prefix && receipt.sn == sn)
+            .map(|receipt| (receipt.seal.prefix.to_str(), receipt.seal))
+            .collect();
+        Ok(seals.into_iter().map(|(_, seal)| seal).collect())
+    }
+}
+
+/// Receipt as stored in the first version of receipts file.
+#[derive(Deserialize)]
+struct LegacyReceipt {
+    seal: EventSeal,
+}
+
+/// Reads receipts file of the first version, which was json map of whole
+/// receipts by identifier prefix, event sn and validator prefix. Only seals
+/// are kept, receipts were processed by event processor as well.
+fn legacy_receipts(content: &[u8]) -> Result<Vec<ValidatorReceipt>, Error> {
+    let receipts: HashMap<String, BTreeMap<u64, BTreeMap<String, LegacyReceipt>>> =
+        serde_json::from_slice(content).map_err(|e| Error::StorageError(e.to_string()))?;
+    Ok(receipts
+        .into_iter()
+        .flat_map(|(prefix, events)| {
+            events.into_iter().flat_map(move |(sn, validators)| {
+                let prefix = prefix.clone();
+                validators
+                    .into_iter()
+                    .map(move |(_, receipt)| ValidatorReceipt {
+                        prefix: prefix.clone(),
+                        sn,
+                        seal: receipt.seal,
+                    })
+            })
+        })
+        .collect())
+}
@@ -0,0 +1,124 @@
+use std::{collections::BTreeSet, convert::TryInto};
+
+use keri::{
+    event::sections::KeyConfig,
+    prefix::{AttachedSignaturePrefix, BasicPrefix, Prefix},
+};
+
+use crate::error::Error;
+
+/// Checks if signatures are valid and satisfy the threshold of given keys.
+/// Each key counts once, however many signatures of it there are.
+pub fn verify_with_keys(
+    keys: &KeyConfig,
+    msg: &[u8],
+    signatures: &[AttachedSignaturePrefix],
+) -> Result<bool, Error> {
+    if !verify_each(keys, msg, signatures)? {
+        return Ok(false);
+    }
+    let indexes: BTreeSet<usize> = signatures.iter().map(|sig| sig.index as usize).collect();
+    let indexes: Vec<usize> = indexes.into_iter().collect();
+    keys.threshold
+        .enough_signatures(&indexes)
+        .map_err(Error::KeriError)
+}
+
+/// Checks if each of signatures is valid, regardless of the threshold.
+pub fn verify_each(
+    keys: &KeyConfig,
+    msg: &[u8],
+    signatures: &[AttachedSignaturePrefix],
+) -> Result<bool, Error> {
+    for signature in signatures {
+        let key = keys
+            .public_keys
+            .get(signature.index as usize)
+            .ok_or(Error::InvalidSignature(format!(
+                "there is no key of index {}",
+                signature.index
+            )))?;
+        if !verify_signature(key, msg, &signature.signature.derivative())? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Verifies signature made with key of given prefix.
+pub fn verify_signature(key: &BasicPrefix, msg: &[u8], signature: &[u8]) -> Result<bool, Error> {
+    match key.derivation_code().as_str() {
+        // Ed25519, transferable and non-transferable.
+        "D" | "B" => {
+            use ed25519_dalek::{PublicKey, Signature, Verifier};
+            let pk = PublicKey::from_bytes(&key.derivative())
+                .map_err(|e| Error::InvalidSignature(e.to_string()))?;
+            let array_signature: [u8; 64] = match signature.try_into() {
+                Ok(array) => array,
+                Err(_) => return Ok(false),
+            };
+            let signature = Signature::new(array_signature);
+            Ok(pk.verify(msg, &signature).is_ok())
+        }
+        // ECDSA secp256k1, transferable and non-transferable.
+        "1AAB" | "1AAA" => {
+            use k256::ecdsa::{
+                signature::{Signature as _, Verifier},
+                Signature, VerifyingKey,
+            };
+            let pk = VerifyingKey::from_sec1_bytes(&key.derivative())
+                .map_err(|e| Error::InvalidSignature(e.to_string()))?;
+            let signature = match Signature::from_bytes(signature) {
+                Ok(signature) => signature,
+                Err(_) => return Ok(false),
+            };
+            Ok(pk.verify(msg, &signature).is_ok())
+        }
+        "C" => Err(Error::InvalidSignature(
+            "X25519 key can't be used for signature verification".into(),
+        )),
+        code => Err(Error::InvalidSignature(format!(
+            "unsupported key type: {}",
+            code
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{controller::entity::Entity, kerl::event_generator::KeyType};
+    use keri::{event::sections::threshold::SignatureThreshold, prefix::IdentifierPrefix};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_repeated_signature() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let ent = Entity::new_with_keys(
+            dir.path().to_str().unwrap(),
+            KeyType::Ed25519Sha512,
+            2,
+            SignatureThreshold::Simple(2),
+        )?;
+        let prefix: IdentifierPrefix = ent.get_prefix()?.parse()?;
+        let state = ent.get_state_for_prefix(&prefix)?.unwrap();
+        let msg = "hello there!";
+        let signatures = ent.sign_indexed(msg)?;
+        assert!(verify_with_keys(
+            &state.current,
+            msg.as_bytes(),
+            &signatures
+        )?);
+
+        // The same signature twice is still one signature of 2-of-2.
+        let repeated = vec![signatures[0].clone(), signatures[0].clone()];
+        assert!(verify_each(&state.current, msg.as_bytes(), &repeated)?);
+        assert!(!verify_with_keys(
+            &state.current,
+            msg.as_bytes(),
+            &repeated
+        )?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+use keri::prefix::{BasicPrefix, IdentifierPrefix, Prefix, SelfSigningPrefix};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, storage::RecordLog};
+
+const RECEIPTS_FILE: &str = "witness_receipts";
+
+/// Signature of witness on event of given identifier and sn.
+#[derive(Serialize, Deserialize)]
+struct WitnessReceipt {
+    prefix: String,
+    sn: u64,
+    witness: String,
+    signature: String,
+}
+
+/// Non-transferable receipts collected from witnesses. They are kept in
+/// file in identifier's event database directory.
+pub struct WitnessReceipts {
+    log: RecordLog<WitnessReceipt>,
+}
+
+impl WitnessReceipts {
+    pub fn new(db_path: &str) -> Self {
+        Self {
+            log: RecordLog::new(Path::new(db_path).join(RECEIPTS_FILE))
+                .with_legacy(legacy_receipts),
+        }
+    }
+
+    /// Stores receipt of witness. Receipt is expected to be already
+    /// verified.
+    pub fn add(
+        &self,
+        prefix: &IdentifierPrefix,
+        sn: u64,
+        witness: &BasicPrefix,
+        signature: &SelfSigningPrefix,
+    ) -> Result<(), Error> {
+        self.log.append(&WitnessReceipt {
+            prefix: prefix.to_str(),
+            sn,
+            witness: witness.to_str(),
+            signature: signature.to_str(),
+        })
+    }
+
+    /// Returns witnesses which receipted event of given sn along with
+    /// their signatures.
+    pub fn get(
+        &self,
+        prefix: &IdentifierPrefix,
+        sn: u64,
+    ) -> Result<Vec<(BasicPrefix, SelfSigningPrefix)>, Error> {
+        let prefix = prefix.to_str();
+        // The latest signature of witness replaces the previous ones.
+        let couplets: BTreeMap<String, String> = self
+            .log
+            .load()?
+            .into_iter()
+            .filter(|receipt| receipt.prefix == prefix && receipt.sn == sn)
+            .map(|receipt| (receipt.witness, receipt.signature))
+            .collect();
+        couplets
+            .iter()
+            .map(|(witness, signature)| Ok((witness.parse()?, signature.parse()?)))
+            .collect()
+    }
+}
+
+/// Reads receipts file of the first version, which was json map of
+/// signatures by identifier prefix, event sn and witness prefix.
+fn legacy_receipts(content: &[u8]) -> Result<Vec<WitnessReceipt>, Error> {
+    let receipts: HashMap<String, BTreeMap<u64, BTreeMap<String, String>>> =
+        serde_json::from_slice(content).map_err(|e| Error::StorageError(e.to_string()))?;
+    Ok(receipts
+        .into_iter()
+        .flat_map(|(prefix, events)| {
+            events.into_iter().flat_map(move |(sn, couplets)| {
+                let prefix = prefix.clone();
+                couplets
+                    .into_iter()
+                    .map(move |(witness, signature)| WitnessReceipt {
+                        prefix: prefix.clone(),
+                        sn,
+                        witness,
+                        signature,
+                    })
+            })
+        })
+        .collect())
+}
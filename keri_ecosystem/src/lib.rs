@@ -3,5 +3,7 @@ pub mod controller;
 pub mod datum;
 pub mod error;
 pub mod kerl;
+mod storage;
 pub mod tel;
 pub mod thing;
+pub mod wallet;
@@ -0,0 +1,232 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::error::Error;
+
+/// First line of every record log, so logs can be told apart from files
+/// of older stores, which kept the whole state in one json document.
+const HEADER: &[u8] = b"keri_ecosystem record log 1\n";
+
+/// Converts content of file written by older version of the store into
+/// records.
+pub type LegacyFormat<T> = fn(&[u8]) -> Result<Vec<T>, Error>;
+
+/// Append only log of json encoded records, one in each line. Records are
+/// synced to disk before `append` returns. Unfinished last line, left by
+/// interrupted write, is ignored and overwritten by the next record.
+#[derive(Debug)]
+pub struct RecordLog<T> {
+    path: PathBuf,
+    legacy: Option<LegacyFormat<T>>,
+    _record: PhantomData<T>,
+}
+
+impl<T: Serialize + DeserializeOwned> RecordLog<T> {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            legacy: None,
+            _record: PhantomData,
+        }
+    }
+
+    /// Sets conversion of files in older format. They are rewritten as log
+    /// when the log is read or appended to for the first time.
+    pub fn with_legacy(self, legacy: LegacyFormat<T>) -> Self {
+        Self {
+            legacy: Some(legacy),
+            ..self
+        }
+    }
+
+    /// Returns all records in order of appending.
+    pub fn load(&self) -> Result<Vec<T>, Error> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+        let content = fs::read(&self.path)?;
+        if content.is_empty() {
+            return Ok(vec![]);
+        }
+        if !content.starts_with(HEADER) {
+            return self.migrate(&content);
+        }
+        let records = &content[HEADER.len()..];
+        // Bytes after the last newline are what's left of interrupted write.
+        let complete = match records.iter().rposition(|b| *b == b'\n') {
+            Some(end) => &records[..end],
+            None => return Ok(vec![]),
+        };
+        complete
+            .split(|b| *b == b'\n')
+            .map(|line| {
+                serde_json::from_slice(line)
+                    .map_err(|e| Error::StorageError(format!("{}: {}", self.path.display(), e)))
+            })
+            .collect()
+    }
+
+    /// Appends record to the log and syncs it to disk.
+    pub fn append(&self, record: &T) -> Result<(), Error> {
+        let mut line =
+            serde_json::to_vec(record).map_err(|e| Error::StorageError(e.to_string()))?;
+        line.push(b'\n');
+
+        if !self.has_header()? {
+            // Start new log, or convert file of older format.
+            let records = self.load()?;
+            self.replace(&records)?;
+        }
+        let mut file = OpenOptions::new().read(true).write(true).open(&self.path)?;
+        let len = file.seek(SeekFrom::End(0))?;
+        let mut last = [0u8];
+        file.seek(SeekFrom::End(-1))?;
+        file.read_exact(&mut last)?;
+        if last[0] != b'\n' {
+            // Drop unfinished line of interrupted write.
+            let mut content = vec![];
+            file.seek(SeekFrom::Start(0))?;
+            file.read_to_end(&mut content)?;
+            let end = content
+                .iter()
+                .rposition(|b| *b == b'\n')
+                .map(|end| end + 1)
+                .unwrap_or(0)
+                .max(HEADER.len());
+            file.set_len(end as u64)?;
+            file.seek(SeekFrom::Start(end as u64))?;
+        } else {
+            file.seek(SeekFrom::Start(len))?;
+        }
+        file.write_all(&line)?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    fn has_header(&self) -> Result<bool, Error> {
+        if !self.path.exists() {
+            return Ok(false);
+        }
+        let mut start = vec![0u8; HEADER.len()];
+        let mut file = File::open(&self.path)?;
+        Ok(file.read_exact(&mut start).is_ok() && start == HEADER)
+    }
+
+    fn migrate(&self, content: &[u8]) -> Result<Vec<T>, Error> {
+        let legacy = self.legacy.ok_or_else(|| {
+            Error::StorageError(format!("{}: unknown file format", self.path.display()))
+        })?;
+        let records = legacy(content).map_err(|e| {
+            Error::StorageError(format!(
+                "{}: can't read file of older format: {}",
+                self.path.display(),
+                e
+            ))
+        })?;
+        self.replace(&records)?;
+        Ok(records)
+    }
+
+    /// Writes new log with given records in place of the current file.
+    fn replace(&self, records: &[T]) -> Result<(), Error> {
+        let mut content = HEADER.to_vec();
+        for record in records {
+            content.extend(
+                serde_json::to_vec(record).map_err(|e| Error::StorageError(e.to_string()))?,
+            );
+            content.push(b'\n');
+        }
+        write_atomically(&self.path, &content)
+    }
+}
+
+/// Replaces file with `content`, so it holds either old or new content
+/// even if writing is interrupted. Both the file and the directory entry
+/// are synced to disk.
+pub fn write_atomically(path: &Path, content: &[u8]) -> Result<(), Error> {
+    let tmp_path = path.with_extension("tmp");
+    let mut tmp = File::create(&tmp_path)?;
+    tmp.write_all(content)?;
+    tmp.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    if let Some(dir) = path.parent() {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_record_log() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let path = dir.path().join("log");
+        let log: RecordLog<(String, u64)> = RecordLog::new(path.clone());
+        assert!(log.load()?.is_empty());
+
+        log.append(&("first".to_string(), 1))?;
+        log.append(&("second".to_string(), 2))?;
+        assert_eq!(
+            log.load()?,
+            vec![("first".to_string(), 1), ("second".to_string(), 2)]
+        );
+
+        // Interrupted write leaves unfinished line, which is skipped.
+        let mut file = OpenOptions::new().append(true).open(&path)?;
+        file.write_all(br#"["thi"#)?;
+        assert_eq!(log.load()?.len(), 2);
+        log.append(&("third".to_string(), 3))?;
+        assert_eq!(
+            log.load()?,
+            vec![
+                ("first".to_string(), 1),
+                ("second".to_string(), 2),
+                ("third".to_string(), 3)
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_legacy_file() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let path = dir.path().join("log");
+        fs::write(&path, br#"{"first":1,"second":2}"#)?;
+
+        // File of unknown format isn't overwritten.
+        let log: RecordLog<(String, u64)> = RecordLog::new(path.clone());
+        assert!(matches!(log.load(), Err(Error::StorageError(_))));
+        assert!(matches!(
+            log.append(&("third".to_string(), 3)),
+            Err(Error::StorageError(_))
+        ));
+
+        let log = log.with_legacy(|content| {
+            let map: std::collections::BTreeMap<String, u64> =
+                serde_json::from_slice(content).map_err(|e| Error::StorageError(e.to_string()))?;
+            Ok(map.into_iter().collect())
+        });
+        log.append(&("third".to_string(), 3))?;
+        assert!(fs::read(&path)?.starts_with(HEADER));
+        assert_eq!(
+            log.load()?,
+            vec![
+                ("first".to_string(), 1),
+                ("second".to_string(), 2),
+                ("third".to_string(), 3)
+            ]
+        );
+
+        Ok(())
+    }
+}
@@ -1,15 +1,18 @@
 pub mod tel_event;
 pub mod tel_manager;
+pub mod tel_store;
 
 use std::fmt;
 
-use self::tel_event::{TelEvent, TelState};
+use self::tel_event::{EventType, SignedTelEvent, TelState};
 use crate::error::Error;
+use keri::prefix::Prefix;
 use serde::{Deserialize, Serialize};
 
+/// Vc TEL, made of `iss` and `rev` events.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TEL {
-    events: Vec<TelEvent>,
+    events: Vec<SignedTelEvent>,
     state: TelState,
 }
 
@@ -28,8 +31,51 @@ impl TEL {
         }
     }
 
-    pub fn update(&self, event: TelEvent) -> Result<TEL, Error> {
-        let state = event.apply(&self.state.clone())?;
+    /// Returns TEL with event appended. Event has to follow the last one:
+    /// refer to the same vc and registry, have next sn and digest of the
+    /// last event as prior digest.
+    pub fn update(&self, event: SignedTelEvent) -> Result<TEL, Error> {
+        let state = match (event.event.event_type, &self.state) {
+            (EventType::Iss, TelState::NotIsuued) => TelState::Issued(event.seal.clone()),
+            (EventType::Rev, TelState::Issued(_)) => TelState::Revoked,
+            (EventType::Iss, _) => {
+                return Err(Error::TelStateConflict("VC is already issued".into()))
+            }
+            (EventType::Rev, _) => return Err(Error::TelStateConflict("VC is not issued".into())),
+            (EventType::Vcp, _) => {
+                return Err(Error::ProtocolError(
+                    "registry inception can't be part of vc TEL".into(),
+                ))
+            }
+        };
+        event.event.check_format()?;
+        match self.events.last() {
+            Some(last) => {
+                if event.event.prefix != last.event.prefix
+                    || event.event.registry_id != last.event.registry_id
+                {
+                    return Err(Error::ProtocolError(
+                        "TEL event refers to other vc or registry".into(),
+                    ));
+                }
+                if event.event.sn != last.event.sn + 1 {
+                    return Err(Error::TelStateConflict(format!(
+                        "expected TEL event of sn {}",
+                        last.event.sn + 1
+                    )));
+                }
+                if event.event.prior_digest != Some(last.event.digest()?.to_str()) {
+                    return Err(Error::TelStateConflict(
+                        "prior digest doesn't match the last TEL event".into(),
+                    ));
+                }
+            }
+            None => {
+                if event.event.sn != 0 {
+                    return Err(Error::TelStateConflict("expected TEL event of sn 0".into()));
+                }
+            }
+        }
         let mut events = self.events.clone();
         events.push(event);
         Ok(TEL { state, events })
@@ -38,6 +84,57 @@ impl TEL {
     pub fn get_state(&self) -> TelState {
         self.state.clone()
     }
+
+    pub fn get_events(&self) -> &[SignedTelEvent] {
+        &self.events
+    }
+}
+
+/// Management TEL of registry, started by registry inception. Registries
+/// without backers don't have other management events.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Registry {
+    inception: SignedTelEvent,
+}
+
+impl Registry {
+    /// Makes registry of `vcp` event anchored in issuer's KEL.
+    pub fn new(inception: SignedTelEvent) -> Result<Self, Error> {
+        let event = &inception.event;
+        if event.event_type != EventType::Vcp || event.sn != 0 {
+            return Err(Error::ProtocolError(
+                "registry has to start with inception".into(),
+            ));
+        }
+        event.check_format()?;
+        if event.issuer != Some(inception.seal.prefix.to_str()) {
+            return Err(Error::ProtocolError(
+                "registry inception isn't anchored by its issuer".into(),
+            ));
+        }
+        Ok(Registry { inception })
+    }
+
+    pub fn get_id(&self) -> &str {
+        &self.inception.event.prefix
+    }
+
+    pub fn get_issuer(&self) -> String {
+        self.inception.seal.prefix.to_str()
+    }
+
+    pub fn get_inception(&self) -> &SignedTelEvent {
+        &self.inception
+    }
+}
+
+/// Reply to TEL query about vc: registry inception and events of vc TEL,
+/// along with issuer's KEL which anchors them. Both are empty if vc is
+/// unknown.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TelReply {
+    pub kel: String,
+    pub events: Vec<SignedTelEvent>,
 }
 
 #[cfg(test)]
@@ -1,79 +1,297 @@
 use std::fmt;
 
 use crate::error::Error;
-use base64::URL_SAFE;
-use keri::{event::sections::seal::EventSeal, prefix::Prefix};
+use base64::URL_SAFE_NO_PAD;
+use keri::{
+    derivation::self_addressing::SelfAddressing,
+    event::sections::seal::EventSeal,
+    prefix::{AttachedSignaturePrefix, Prefix, SelfAddressingPrefix},
+};
+use rand::{rngs::OsRng, RngCore};
 use serde::{Deserialize, Serialize};
 
+/// Stands for registry identifier while digest of registry inception is
+/// computed. It's as long as the identifier, so event size doesn't change.
+const DUMMY_PREFIX: &str = "############################################";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TelState {
     NotIsuued,
+    /// Vc is issued, with seal of issuer's KEL event which anchors the
+    /// issuance.
     Issued(EventSeal),
     Revoked,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum Operation {
-    Issue,
-    Revoke,
+/// Type of TEL event, as in `t` field.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventType {
+    /// Registry inception, which starts management TEL.
+    Vcp,
+    /// Issuance of vc in registry without backers.
+    Iss,
+    /// Revocation of vc in registry without backers.
+    Rev,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl fmt::Display for EventType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let event_type = match self {
+            EventType::Vcp => "vcp",
+            EventType::Iss => "iss",
+            EventType::Rev => "rev",
+        };
+        write!(f, "{}", event_type)
+    }
+}
+
+/// TEL event. Fields are in order defined by the spec, so serialized
+/// event, and so its digest, are the same as in other implementations.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TelEvent {
-    event_seal: EventSeal,
-    operation: Operation,
+    #[serde(rename = "v")]
+    version: String,
+    /// Registry identifier for `vcp`, vc identifier for `iss` and `rev`.
+    #[serde(rename = "i")]
+    pub prefix: String,
+    #[serde(rename = "ii", skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    #[serde(rename = "s", with = "hex_sn")]
+    pub sn: u64,
+    #[serde(rename = "t")]
+    pub event_type: EventType,
+    /// Registry configuration traits. `NB` means there are no backers.
+    #[serde(rename = "c", skip_serializing_if = "Option::is_none")]
+    config: Option<Vec<String>>,
+    #[serde(rename = "bt", skip_serializing_if = "Option::is_none")]
+    backer_threshold: Option<String>,
+    #[serde(rename = "b", skip_serializing_if = "Option::is_none")]
+    backers: Option<Vec<String>>,
+    #[serde(rename = "n", skip_serializing_if = "Option::is_none")]
+    nonce: Option<String>,
+    #[serde(rename = "ri", skip_serializing_if = "Option::is_none")]
+    pub registry_id: Option<String>,
+    #[serde(rename = "p", skip_serializing_if = "Option::is_none")]
+    pub prior_digest: Option<String>,
+}
+
+impl TelEvent {
+    /// Makes registry inception of issuer. Registry identifier is digest of
+    /// the event, which is unique thanks to random nonce.
+    pub fn registry_inception(issuer: &str) -> Result<Self, Error> {
+        let mut nonce = [0u8; 16];
+        OsRng.fill_bytes(&mut nonce);
+        let mut event = TelEvent {
+            version: String::new(),
+            prefix: DUMMY_PREFIX.into(),
+            issuer: Some(issuer.into()),
+            sn: 0,
+            event_type: EventType::Vcp,
+            config: Some(vec!["NB".into()]),
+            backer_threshold: Some("0".into()),
+            backers: Some(vec![]),
+            nonce: Some(["0A", &base64::encode_config(nonce, URL_SAFE_NO_PAD)].join("")),
+            registry_id: None,
+            prior_digest: None,
+        };
+        event.version = event.version_string()?;
+        event.prefix = event.digest()?.to_str();
+        Ok(event)
+    }
+
+    /// Makes issuance of vc of given identifier in registry.
+    pub fn issuance(vc_id: &str, registry_id: &str) -> Result<Self, Error> {
+        Self::vc_event(vc_id, 0, EventType::Iss, registry_id, None)
+    }
+
+    /// Makes revocation of vc, which follows event of `sn - 1` with
+    /// `prior_digest` digest.
+    pub fn revocation(
+        vc_id: &str,
+        sn: u64,
+        registry_id: &str,
+        prior_digest: &SelfAddressingPrefix,
+    ) -> Result<Self, Error> {
+        Self::vc_event(
+            vc_id,
+            sn,
+            EventType::Rev,
+            registry_id,
+            Some(prior_digest.to_str()),
+        )
+    }
+
+    fn vc_event(
+        vc_id: &str,
+        sn: u64,
+        event_type: EventType,
+        registry_id: &str,
+        prior_digest: Option<String>,
+    ) -> Result<Self, Error> {
+        let mut event = TelEvent {
+            version: String::new(),
+            prefix: vc_id.into(),
+            issuer: None,
+            sn,
+            event_type,
+            config: None,
+            backer_threshold: None,
+            backers: None,
+            nonce: None,
+            registry_id: Some(registry_id.into()),
+            prior_digest,
+        };
+        event.version = event.version_string()?;
+        Ok(event)
+    }
+
+    pub fn serialize(&self) -> Result<Vec<u8>, Error> {
+        serde_json::to_vec(self).map_err(|e| Error::ProtocolError(e.to_string()))
+    }
+
+    pub fn digest(&self) -> Result<SelfAddressingPrefix, Error> {
+        Ok(SelfAddressing::Blake3_256.derive(&self.serialize()?))
+    }
+
+    /// Returns digest of vc which `iss` or `rev` event refers to.
+    pub fn vc_digest(&self) -> Result<Vec<u8>, Error> {
+        Ok(self
+            .prefix
+            .parse::<SelfAddressingPrefix>()?
+            .derivative()
+            .to_vec())
+    }
 
-    // #[serde(skip_serializing)]
-    signature: Vec<u8>,
+    /// Checks if version string holds the event size and, for registry
+    /// inception, if registry identifier is digest of the event.
+    pub fn check_format(&self) -> Result<(), Error> {
+        if self.version != self.version_string()? {
+            return Err(Error::ProtocolError(
+                "wrong TEL event version string".into(),
+            ));
+        }
+        if self.event_type == EventType::Vcp {
+            let dummy = TelEvent {
+                prefix: DUMMY_PREFIX.into(),
+                ..self.clone()
+            };
+            if dummy.digest()?.to_str() != self.prefix {
+                return Err(Error::ProtocolError(
+                    "registry identifier doesn't match registry inception".into(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn version_string(&self) -> Result<String, Error> {
+        let sized = TelEvent {
+            version: format!("KERI10JSON{:06x}_", 0),
+            ..self.clone()
+        };
+        Ok(format!("KERI10JSON{:06x}_", sized.serialize()?.len()))
+    }
 }
 
-impl fmt::Display for TelEvent {
+/// TEL event along with seal of issuer's KEL event which anchors it, as in
+/// attached source seal, and issuer's signatures of the event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedTelEvent {
+    pub event: TelEvent,
+    pub seal: EventSeal,
+    /// Attached signatures made with keys current at the sealed sn.
+    signatures: Vec<String>,
+}
+
+impl fmt::Display for SignedTelEvent {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let _issuer = ["issuer: ", &self.event_seal.prefix.to_str()].join("");
-        let operation = [
-            "operation: ",
-            &serde_json::to_string(&self.operation).unwrap(),
-        ]
-        .join("");
-        let _signature = [
-            "signature: ",
-            &base64::encode_config(&self.signature, URL_SAFE),
-        ]
-        .join("");
-        let sn = ["sn: ", &self.event_seal.sn.to_string()].join("");
-        let _digest = [
-            "issuence event digest: ",
-            &self.event_seal.event_digest.to_str(),
-        ]
-        .join("");
-
-        write!(f, "\t{}", [sn, operation].join(", "))
+        let sn = ["sn: ", &self.event.sn.to_string()].join("");
+        let event_type = ["type: ", &self.event.event_type.to_string()].join("");
+        let anchor = ["anchored at: ", &self.seal.sn.to_string()].join("");
+
+        write!(f, "\t{}", [sn, event_type, anchor].join(", "))
     }
 }
 
-impl TelEvent {
-    pub fn new(event_seal: EventSeal, operation: Operation) -> Self {
-        TelEvent {
-            event_seal,
-            operation,
-            signature: vec![],
+impl SignedTelEvent {
+    pub fn new(event: TelEvent, seal: EventSeal, signatures: &[AttachedSignaturePrefix]) -> Self {
+        SignedTelEvent {
+            event,
+            seal,
+            signatures: signatures.iter().map(|sig| sig.to_str()).collect(),
         }
     }
 
-    pub fn apply(&self, prev_state: &TelState) -> Result<TelState, Error> {
-        match self.operation {
-            Operation::Issue => match prev_state {
-                TelState::NotIsuued => Ok(TelState::Issued(self.event_seal.clone())),
-                _ => Err(Error::Generic("Wrong state".into())),
-            },
-            Operation::Revoke => match prev_state {
-                TelState::Issued(_) => Ok(TelState::Revoked),
-                _ => Err(Error::Generic("Wrong state".into())),
-            },
-        }
+    pub fn get_signatures(&self) -> Result<Vec<AttachedSignaturePrefix>, Error> {
+        self.signatures.iter().map(|sig| Ok(sig.parse()?)).collect()
     }
+}
+
+/// Serializes sn as hex string, as in KEL events.
+mod hex_sn {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(sn: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:x}", sn))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        let sn = String::deserialize(deserializer)?;
+        u64::from_str_radix(&sn, 16).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tel_events() -> Result<(), Error> {
+        let issuer = "DhaJFfaW1aoKXZospMvka-VdQlmj0BQd4HlL3JvCOUg8";
+        let vcp = TelEvent::registry_inception(issuer)?;
+        vcp.check_format()?;
+        let vcp_str = String::from_utf8(vcp.serialize()?)?;
+        assert!(vcp_str.starts_with(&format!(
+            r#"{{"v":"KERI10JSON{:06x}_","i":"{}","ii":"{}","s":"0","t":"vcp","c":["NB"],"bt":"0","b":[],"n":"0A"#,
+            vcp_str.len(),
+            vcp.prefix,
+            issuer
+        )));
+        // Registry identifier is bound to the inception.
+        let other = TelEvent::registry_inception(issuer)?;
+        assert_ne!(other.prefix, vcp.prefix);
+        let forged = TelEvent {
+            prefix: other.prefix,
+            ..vcp.clone()
+        };
+        assert!(forged.check_format().is_err());
+
+        let vc_id = SelfAddressing::Blake3_256.derive(b"Some vc").to_str();
+        let iss = TelEvent::issuance(&vc_id, &vcp.prefix)?;
+        iss.check_format()?;
+        assert_eq!(
+            String::from_utf8(iss.serialize()?)?,
+            format!(
+                r#"{{"v":"{}","i":"{}","s":"0","t":"iss","ri":"{}"}}"#,
+                iss.version, vc_id, vcp.prefix
+            )
+        );
+        assert_eq!(
+            iss.vc_digest()?,
+            blake3::hash(b"Some vc").as_bytes().to_vec()
+        );
+
+        let rev = TelEvent::revocation(&vc_id, 1, &vcp.prefix, &iss.digest()?)?;
+        let rev_str = String::from_utf8(rev.serialize()?)?;
+        assert!(rev_str.contains(&format!(
+            r#""s":"1","t":"rev","ri":"{}","p":"{}""#,
+            vcp.prefix,
+            iss.digest()?.to_str()
+        )));
+        let parsed: TelEvent = serde_json::from_str(&rev_str).unwrap();
+        assert_eq!(parsed, rev);
 
-    pub fn attach_signature(&mut self, signature: &[u8]) {
-        self.signature = signature.to_vec();
+        Ok(())
     }
 }
@@ -1,34 +1,144 @@
 use std::collections::HashMap;
 
-use crate::error::Error;
+use crate::{controller::entity::Entity, error::Error};
+use keri::event::sections::seal::EventSeal;
 
 use super::{
-    tel_event::{TelEvent, TelState},
-    TEL,
+    tel_event::{EventType, SignedTelEvent, TelEvent, TelState},
+    tel_store::TelStore,
+    Registry, TEL,
 };
 
+/// Change of TELs made by accepted event.
+enum Update {
+    Registry(Registry),
+    Tel(Vec<u8>, TEL),
+}
+
+/// Registries and TELs of vcs issued in them. Accepted events are stored
+/// in `db_path` and replayed when manager is created again, so vc states
+/// survive restart. Replayed events aren't verified against issuers' KELs
+/// again: events file is trusted as much as the KEL database next to it.
 #[derive(Debug)]
 pub struct TelManager {
+    registries: HashMap<String, Registry>,
     tels: HashMap<Vec<u8>, TEL>,
+    store: TelStore,
 }
 
 impl TelManager {
-    pub fn new() -> Self {
-        TelManager {
+    pub fn new(db_path: &str) -> Result<Self, Error> {
+        let store = TelStore::new(db_path);
+        let events = store.get_all()?;
+        let mut manager = TelManager {
+            registries: HashMap::new(),
             tels: HashMap::new(),
+            store,
+        };
+        // Stored events were verified before storing. Only their order
+        // is checked again.
+        for event in events {
+            let update = manager
+                .apply(event)
+                .map_err(|e| Error::StorageError(format!("Can't replay TEL event: {}", e)))?;
+            manager.insert(update);
         }
+        Ok(manager)
+    }
+
+    /// Logs own event of issuer before it's anchored in issuer's KEL, so it
+    /// can be accepted after restart if storing it is interrupted.
+    pub fn prepare(&self, issuer: &str, event: &TelEvent) -> Result<(), Error> {
+        self.store.prepare(issuer, event)
+    }
+
+    /// Marks prepared event, which isn't anchored, as never accepted.
+    pub fn abandon(&self, event: &TelEvent) -> Result<(), Error> {
+        self.store.abandon(event)
+    }
+
+    /// Returns prepared events, along with their issuers, which weren't
+    /// accepted nor abandoned.
+    pub fn get_prepared(&self) -> Result<Vec<(String, TelEvent)>, Error> {
+        self.store.get_prepared()
     }
 
-    pub fn process_tel_event(&mut self, vc_dig: &[u8], event: TelEvent) -> Result<(), Error> {
-        let tel = match self.tels.get_mut(vc_dig) {
-            Some(tel) => tel.update(event)?,
-            None => TEL::new().update(event)?,
+    /// Checks if own event of issuer can be accepted in current state, so
+    /// event which would be rejected isn't anchored in issuer's KEL.
+    pub fn check(&self, issuer: &str, event: &TelEvent) -> Result<(), Error> {
+        // Anchor and signatures aren't known yet. Only issuer of the seal
+        // matters for the state.
+        let seal = EventSeal {
+            prefix: issuer.parse()?,
+            sn: 0,
+            event_digest: event.digest()?,
         };
-        self.tels.insert(vc_dig.to_vec(), tel);
+        self.apply(SignedTelEvent::new(event.clone(), seal, &[]))
+            .map(|_| ())
+    }
+
+    /// Validates event against registry and TEL it belongs to, stores it
+    /// and updates state. Event has to be anchored and signed by issuer,
+    /// according to issuer's KEL in `issuer_kel`.
+    pub fn process_tel_event(
+        &mut self,
+        event: SignedTelEvent,
+        issuer_kel: &Entity,
+    ) -> Result<(), Error> {
+        issuer_kel.verify_tel_event(&event)?;
+        let update = self.apply(event.clone())?;
+        // Store event before updating state, so state never includes event
+        // which wasn't stored.
+        self.store.add(&event)?;
+        self.insert(update);
 
         Ok(())
     }
 
+    fn apply(&self, event: SignedTelEvent) -> Result<Update, Error> {
+        match event.event.event_type {
+            EventType::Vcp => {
+                if self.registries.contains_key(&event.event.prefix) {
+                    return Err(Error::TelStateConflict(format!(
+                        "registry {} is already incepted",
+                        event.event.prefix
+                    )));
+                }
+                Ok(Update::Registry(Registry::new(event)?))
+            }
+            EventType::Iss | EventType::Rev => {
+                let registry_id =
+                    event.event.registry_id.as_ref().ok_or_else(|| {
+                        Error::ProtocolError("missing registry identifier".into())
+                    })?;
+                let registry = self.get_registry(registry_id)?;
+                if registry.get_issuer() != event.seal.prefix.to_str() {
+                    return Err(Error::ProtocolError(
+                        "TEL event isn't anchored by registry issuer".into(),
+                    ));
+                }
+                let vc_dig = event.event.vc_digest()?;
+                let tel = match self.tels.get(&vc_dig) {
+                    Some(tel) => tel.update(event)?,
+                    None => TEL::new().update(event)?,
+                };
+                Ok(Update::Tel(vc_dig, tel))
+            }
+        }
+    }
+
+    fn insert(&mut self, update: Update) {
+        match update {
+            Update::Registry(registry) => {
+                self.registries
+                    .insert(registry.get_id().to_owned(), registry);
+            }
+            Update::Tel(vc_dig, tel) => {
+                self.tels.insert(vc_dig, tel);
+            }
+        }
+    }
+
     pub fn get_state(&self, vc_dig: &[u8]) -> Result<TelState, Error> {
         let tel = self.tels.get(vc_dig);
         match tel {
@@ -42,56 +152,283 @@ impl TelManager {
             .get(vc_dig)
             .ok_or(Error::Generic("No TEl for VC".into()))
     }
+
+    /// Returns registry inception followed by events of vc TEL, as needed
+    /// to rebuild the TEL elsewhere. Empty if vc is unknown.
+    pub fn get_tel_events(&self, vc_dig: &[u8]) -> Result<Vec<SignedTelEvent>, Error> {
+        let events = match self.tels.get(vc_dig) {
+            Some(tel) => tel.get_events(),
+            None => return Ok(vec![]),
+        };
+        let registry_id = events
+            .first()
+            .and_then(|event| event.event.registry_id.as_ref())
+            .ok_or_else(|| Error::ProtocolError("missing registry identifier".into()))?;
+        let mut tel_events = vec![self.get_registry(registry_id)?.get_inception().clone()];
+        tel_events.extend(events.iter().cloned());
+        Ok(tel_events)
+    }
+
+    /// Returns true if event was already accepted, so there is no need to
+    /// process it again.
+    pub fn is_known(&self, event: &SignedTelEvent) -> Result<bool, Error> {
+        Ok(match event.event.event_type {
+            EventType::Vcp => self.registries.contains_key(&event.event.prefix),
+            EventType::Iss | EventType::Rev => self
+                .tels
+                .get(&event.event.vc_digest()?)
+                .map(|tel| tel.get_events().len() as u64 > event.event.sn)
+                .unwrap_or(false),
+        })
+    }
+
+    pub fn get_registry(&self, registry_id: &str) -> Result<&Registry, Error> {
+        self.registries
+            .get(registry_id)
+            .ok_or_else(|| Error::TelStateConflict(format!("unknown registry {}", registry_id)))
+    }
+
+    /// Returns registry incepted by given issuer, if there is any.
+    pub fn get_registry_of(&self, issuer: &str) -> Option<&Registry> {
+        self.registries
+            .values()
+            .find(|registry| registry.get_issuer() == issuer)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::TelManager;
+    use crate::controller::entity::Entity;
     use crate::error::Error;
-    use crate::tel::tel_event::{Operation, TelEvent, TelState};
-    use keri::event::sections::seal::EventSeal;
+    use crate::kerl::event_generator::PrefixType;
+    use crate::tel::tel_event::{SignedTelEvent, TelEvent, TelState};
+    use keri::{
+        derivation::self_addressing::SelfAddressing,
+        event::sections::seal::{EventSeal, Seal},
+        prefix::Prefix,
+    };
+    use tempfile::tempdir;
+
+    /// Anchors TEL event in issuer's KEL and signs it with issuer's keys.
+    fn anchored(issuer: &mut Entity, event: TelEvent) -> Result<SignedTelEvent, Error> {
+        let tel_seal = EventSeal {
+            prefix: event.prefix.parse()?,
+            sn: event.sn,
+            event_digest: event.digest()?,
+        };
+        let seal = issuer.anchor(vec![Seal::Event(tel_seal)])?;
+        let signatures = issuer.sign_indexed(&String::from_utf8(event.serialize()?)?)?;
+        Ok(SignedTelEvent::new(event, seal, &signatures))
+    }
 
     #[test]
     fn test_process() -> Result<(), Error> {
-        let vc = "Some vc";
-        let vc_digest = blake3::hash(vc.as_bytes()).as_bytes().to_vec();
+        let vc_id = SelfAddressing::Blake3_256.derive(b"Some vc").to_str();
+
+        let db_dir = tempdir()?;
+        let mut tel_manager = TelManager::new(db_dir.path().to_str().unwrap())?;
+        let issuer_dir = tempdir()?;
+        let mut issuer = Entity::new(issuer_dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        let issuer_id = issuer.get_prefix()?;
+
+        let vcp = TelEvent::registry_inception(&issuer_id)?;
+        let registry_id = vcp.prefix.clone();
+        // Issuance in registry which isn't incepted yet is rejected.
+        let iss = TelEvent::issuance(&vc_id, &registry_id)?;
+        let early_iss = anchored(&mut issuer, iss.clone())?;
+        assert!(tel_manager.process_tel_event(early_iss, &issuer).is_err());
 
-        let mut tel_manager = TelManager::new();
+        let signed_vcp = anchored(&mut issuer, vcp.clone())?;
+        tel_manager.process_tel_event(signed_vcp, &issuer)?;
+        assert_eq!(
+            tel_manager.get_registry_of(&issuer_id).unwrap().get_id(),
+            registry_id
+        );
+        let second_vcp = anchored(&mut issuer, vcp.clone())?;
+        assert!(matches!(
+            tel_manager.process_tel_event(second_vcp, &issuer),
+            Err(Error::TelStateConflict(_))
+        ));
 
-        let seal_str = r#"{"i":"DhaJFfaW1aoKXZospMvka-VdQlmj0BQd4HlL3JvCOUg8","s":"1","d":"EJbYpX41mdwfdTOY3w65kjwx_E1bzqhaNFZLDRSEBaVk"}"#;
-        let issuing_event_seal = serde_json::from_str(seal_str).unwrap();
+        // Issuance anchored by other identifier is rejected.
+        let other_dir = tempdir()?;
+        let mut other = Entity::new(other_dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        let others_iss = anchored(&mut other, iss.clone())?;
+        assert!(tel_manager.process_tel_event(others_iss, &other).is_err());
 
-        let iss_event = TelEvent::new(issuing_event_seal, Operation::Issue);
+        Ok(())
+    }
+
+    #[test]
+    fn test_forged_events() -> Result<(), Error> {
+        let vc_id = SelfAddressing::Blake3_256.derive(b"Some vc").to_str();
+        let db_dir = tempdir()?;
+        let mut tel_manager = TelManager::new(db_dir.path().to_str().unwrap())?;
+        let issuer_dir = tempdir()?;
+        let mut issuer = Entity::new(issuer_dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        let vcp = TelEvent::registry_inception(&issuer.get_prefix()?)?;
+        let registry_id = vcp.prefix.clone();
+        tel_manager.process_tel_event(anchored(&mut issuer, vcp)?, &issuer)?;
+        let iss = TelEvent::issuance(&vc_id, &registry_id)?;
+        let msg = String::from_utf8(iss.serialize()?)?;
+
+        // Seal points at interaction event which anchors something else.
+        let unanchored = SignedTelEvent::new(
+            iss.clone(),
+            issuer.append("other data")?,
+            &issuer.sign_indexed(&msg)?,
+        );
+        assert!(matches!(
+            tel_manager.process_tel_event(unanchored, &issuer),
+            Err(Error::InvalidSignature(_))
+        ));
+
+        // Event is anchored, but signed by someone else.
+        let other_dir = tempdir()?;
+        let other = Entity::new(other_dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        let signed_iss = anchored(&mut issuer, iss.clone())?;
+        let forged = SignedTelEvent::new(iss.clone(), signed_iss.seal, &other.sign_indexed(&msg)?);
+        assert!(matches!(
+            tel_manager.process_tel_event(forged, &issuer),
+            Err(Error::InvalidSignature(_))
+        ));
+
+        // Seal points at event which isn't in issuer's KEL.
+        let mut missing = anchored(&mut issuer, iss.clone())?;
+        missing.seal.sn += 1;
+        assert!(tel_manager.process_tel_event(missing, &issuer).is_err());
+
+        assert!(matches!(
+            tel_manager.get_state(&iss.vc_digest()?)?,
+            TelState::NotIsuued
+        ));
+        tel_manager.process_tel_event(anchored(&mut issuer, iss.clone())?, &issuer)?;
+        assert!(matches!(
+            tel_manager.get_state(&iss.vc_digest()?)?,
+            TelState::Issued(_)
+        ));
 
-        tel_manager.process_tel_event(&vc_digest, iss_event)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_vc_tel() -> Result<(), Error> {
+        let vc_id = SelfAddressing::Blake3_256.derive(b"Some vc").to_str();
+        let vc_digest = blake3::hash(b"Some vc").as_bytes().to_vec();
+        let db_dir = tempdir()?;
+        let mut tel_manager = TelManager::new(db_dir.path().to_str().unwrap())?;
+        let issuer_dir = tempdir()?;
+        let mut issuer = Entity::new(issuer_dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        let vcp = TelEvent::registry_inception(&issuer.get_prefix()?)?;
+        let registry_id = vcp.prefix.clone();
+        tel_manager.process_tel_event(anchored(&mut issuer, vcp.clone())?, &issuer)?;
 
+        let iss = TelEvent::issuance(&vc_id, &registry_id)?;
+        tel_manager.process_tel_event(anchored(&mut issuer, iss.clone())?, &issuer)?;
         let vc_state = tel_manager.get_state(&vc_digest)?;
         assert!(matches!(vc_state, TelState::Issued(_)));
 
-        let rev_seal_str = r#"{"i":"DhaJFfaW1aoKXZospMvka-VdQlmj0BQd4HlL3JvCOUg8","s":"3","d":"EChnM_a1gMogM8utFBk0Fdlj-P8Sx963VR7mCPYTbVXs"}"#;
-        let revoking_event_seal: EventSeal = serde_json::from_str(rev_seal_str).unwrap();
-
-        let rev_event = TelEvent::new(revoking_event_seal, Operation::Revoke);
+        // Revocation has to follow the issuance.
+        let wrong_sn = TelEvent::revocation(&vc_id, 2, &registry_id, &iss.digest()?)?;
+        assert!(tel_manager
+            .process_tel_event(anchored(&mut issuer, wrong_sn)?, &issuer)
+            .is_err());
+        let wrong_prior = TelEvent::revocation(&vc_id, 1, &registry_id, &vcp.digest()?)?;
+        assert!(tel_manager
+            .process_tel_event(anchored(&mut issuer, wrong_prior)?, &issuer)
+            .is_err());
 
-        tel_manager.process_tel_event(&vc_digest, rev_event)?;
+        let rev = TelEvent::revocation(&vc_id, 1, &registry_id, &iss.digest()?)?;
+        tel_manager.process_tel_event(anchored(&mut issuer, rev)?, &issuer)?;
 
         let vc_state = tel_manager.get_state(&vc_digest)?;
         assert!(matches!(vc_state, TelState::Revoked));
+        assert_eq!(tel_manager.get_tel(&vc_digest)?.get_events().len(), 2);
+
+        // Registry inception comes first, so TEL can be rebuilt from events.
+        let tel_events = tel_manager.get_tel_events(&vc_digest)?;
+        assert_eq!(tel_events.len(), 3);
+        assert_eq!(tel_events[0].event, vcp);
+        let other_dir = tempdir()?;
+        let mut other_manager = TelManager::new(other_dir.path().to_str().unwrap())?;
+        for event in tel_events {
+            assert!(!other_manager.is_known(&event)?);
+            other_manager.process_tel_event(event.clone(), &issuer)?;
+            assert!(other_manager.is_known(&event)?);
+        }
+        assert!(matches!(
+            other_manager.get_state(&vc_digest)?,
+            TelState::Revoked
+        ));
 
         // Try to revoke not issued vc.
         let not_issued_vc = "not issued vc";
+        let not_issued_vc_id = SelfAddressing::Blake3_256
+            .derive(not_issued_vc.as_bytes())
+            .to_str();
         let not_issued_vc_digest = blake3::hash(not_issued_vc.as_bytes()).as_bytes().to_vec();
 
-        let rev_seal = r#"{"i":"DIhUJMEYCsLSWhZ1TtvXI2Z9WZmWOBrtozLrLJwmNexI","s":"3","d":"EWhbaXRxADbR0yOmLqRRW2XzEKR0tyE8EbjqlGOaJg-o"}"#;
-        let revoking_event_seal: EventSeal = serde_json::from_str(rev_seal).unwrap();
-
-        let rev_event = TelEvent::new(revoking_event_seal, Operation::Revoke);
-        assert!(tel_manager
-            .process_tel_event(&not_issued_vc_digest, rev_event)
-            .is_err());
+        let rev_event = TelEvent::revocation(&not_issued_vc_id, 1, &registry_id, &iss.digest()?)?;
+        assert!(matches!(
+            tel_manager.process_tel_event(anchored(&mut issuer, rev_event)?, &issuer),
+            Err(Error::TelStateConflict(_))
+        ));
 
         let vc_state = tel_manager.get_state(&not_issued_vc_digest)?;
         assert!(matches!(vc_state, TelState::NotIsuued));
+        assert!(tel_manager
+            .get_tel_events(&not_issued_vc_digest)?
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_replay() -> Result<(), Error> {
+        let db_dir = tempdir()?;
+        let db_path = db_dir.path().to_str().unwrap();
+        let issuer_dir = tempdir()?;
+        let mut issuer = Entity::new(issuer_dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        let issuer_id = issuer.get_prefix()?;
+        let issued_id = SelfAddressing::Blake3_256.derive(b"issued vc").to_str();
+        let revoked_id = SelfAddressing::Blake3_256.derive(b"revoked vc").to_str();
+        let issued_digest = blake3::hash(b"issued vc").as_bytes().to_vec();
+        let revoked_digest = blake3::hash(b"revoked vc").as_bytes().to_vec();
+
+        let registry_id = {
+            let mut tel_manager = TelManager::new(db_path)?;
+            let vcp = TelEvent::registry_inception(&issuer_id)?;
+            let registry_id = vcp.prefix.clone();
+            let issued_iss = TelEvent::issuance(&issued_id, &registry_id)?;
+            let revoked_iss = TelEvent::issuance(&revoked_id, &registry_id)?;
+            let rev = TelEvent::revocation(&revoked_id, 1, &registry_id, &revoked_iss.digest()?)?;
+            for event in vec![vcp, issued_iss.clone(), revoked_iss, rev] {
+                tel_manager.process_tel_event(anchored(&mut issuer, event)?, &issuer)?;
+            }
+            // Rejected event isn't stored.
+            assert!(tel_manager
+                .process_tel_event(anchored(&mut issuer, issued_iss)?, &issuer)
+                .is_err());
+            registry_id
+        };
+
+        // States are rebuilt by manager created again, as after restart.
+        let tel_manager = TelManager::new(db_path)?;
+        assert_eq!(
+            tel_manager.get_registry(&registry_id)?.get_issuer(),
+            issuer_id
+        );
+        assert!(matches!(
+            tel_manager.get_state(&issued_digest)?,
+            TelState::Issued(_)
+        ));
+        assert!(matches!(
+            tel_manager.get_state(&revoked_digest)?,
+            TelState::Revoked
+        ));
+        assert_eq!(tel_manager.get_tel(&revoked_digest)?.get_events().len(), 2);
+        assert_eq!(tel_manager.get_tel(&issued_digest)?.get_events().len(), 1);
 
         Ok(())
     }
@@ -0,0 +1,159 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::tel_event::{SignedTelEvent, TelEvent};
+use crate::{error::Error, storage::RecordLog};
+
+const TEL_EVENTS_FILE: &str = "tel_events";
+
+/// Change of TEL store, as kept in log.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "record", rename_all = "lowercase")]
+enum TelRecord {
+    /// Own event which is about to be anchored in KEL of the issuer.
+    Prepared { issuer: String, event: TelEvent },
+    /// Event which wasn't anchored, so it's never accepted.
+    Abandoned { event: TelEvent },
+    /// Event accepted to TEL.
+    Accepted(SignedTelEvent),
+}
+
+/// Log of TEL events of all registries and vcs, in order of processing.
+/// It's kept in file in event database directory. Own events are logged
+/// before they're anchored in KEL, so event anchored just before restart
+/// can be found and accepted again, see `get_prepared`.
+#[derive(Debug)]
+pub struct TelStore {
+    log: RecordLog<TelRecord>,
+}
+
+impl TelStore {
+    pub fn new(db_path: &str) -> Self {
+        Self {
+            log: RecordLog::new(Path::new(db_path).join(TEL_EVENTS_FILE))
+                .with_legacy(legacy_events),
+        }
+    }
+
+    /// Logs own event before it's anchored in issuer's KEL.
+    pub fn prepare(&self, issuer: &str, event: &TelEvent) -> Result<(), Error> {
+        self.log.append(&TelRecord::Prepared {
+            issuer: issuer.into(),
+            event: event.clone(),
+        })
+    }
+
+    /// Marks prepared event, which isn't anchored, as never accepted.
+    pub fn abandon(&self, event: &TelEvent) -> Result<(), Error> {
+        self.log.append(&TelRecord::Abandoned {
+            event: event.clone(),
+        })
+    }
+
+    /// Appends event to the log. Event is expected to be already validated
+    /// against TEL state.
+    pub fn add(&self, event: &SignedTelEvent) -> Result<(), Error> {
+        self.log.append(&TelRecord::Accepted(event.clone()))
+    }
+
+    /// Returns all accepted events in order of adding.
+    pub fn get_all(&self) -> Result<Vec<SignedTelEvent>, Error> {
+        Ok(self
+            .log
+            .load()?
+            .into_iter()
+            .filter_map(|record| match record {
+                TelRecord::Accepted(event) => Some(event),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Returns prepared events, along with their issuers, which weren't
+    /// accepted nor abandoned yet.
+    pub fn get_prepared(&self) -> Result<Vec<(String, TelEvent)>, Error> {
+        let mut prepared: Vec<(String, TelEvent)> = vec![];
+        for record in self.log.load()? {
+            match record {
+                TelRecord::Prepared { issuer, event } => prepared.push((issuer, event)),
+                TelRecord::Abandoned { event } => prepared.retain(|(_, e)| *e != event),
+                TelRecord::Accepted(accepted) => prepared.retain(|(_, e)| *e != accepted.event),
+            }
+        }
+        Ok(prepared)
+    }
+}
+
+/// Reads events file written before the log, which was json list of
+/// accepted events. Events of older versions, with signature stored
+/// without type of the key or without registry, can't be converted.
+fn legacy_events(content: &[u8]) -> Result<Vec<TelRecord>, Error> {
+    let storage_error = |e: serde_json::Error| Error::StorageError(e.to_string());
+    let events: Vec<serde_json::Value> = serde_json::from_slice(content).map_err(storage_error)?;
+    events
+        .into_iter()
+        .map(|event| {
+            let unsupported = if event.get("vc_digest").is_some() {
+                Some("without registry")
+            } else if event.get("signature").is_some() {
+                Some("with signature without key type")
+            } else {
+                None
+            };
+            if let Some(unsupported) = unsupported {
+                return Err(Error::StorageError(format!(
+                    "TEL events {} aren't supported, vcs have to be issued again",
+                    unsupported
+                )));
+            }
+            Ok(TelRecord::Accepted(
+                serde_json::from_value(event).map_err(storage_error)?,
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keri::event::sections::seal::EventSeal;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_legacy_events() -> Result<(), Error> {
+        let issuer = "DhaJFfaW1aoKXZospMvka-VdQlmj0BQd4HlL3JvCOUg8";
+        let vcp = TelEvent::registry_inception(issuer)?;
+        let seal = EventSeal {
+            prefix: issuer.parse()?,
+            sn: 1,
+            event_digest: vcp.digest()?,
+        };
+        let event = SignedTelEvent::new(vcp.clone(), seal.clone(), &[]);
+
+        let dir = tempdir()?;
+        let path = dir.path().join(TEL_EVENTS_FILE);
+        std::fs::write(&path, serde_json::to_vec(&vec![event]).unwrap())?;
+        let store = TelStore::new(dir.path().to_str().unwrap());
+        let events = store.get_all()?;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, vcp);
+
+        // Raw signature doesn't say which key made it.
+        let old_event = serde_json::json!([{
+            "event": vcp,
+            "seal": seal,
+            "signature": [1, 2, 3],
+        }]);
+        std::fs::write(&path, serde_json::to_vec(&old_event).unwrap())?;
+        assert!(matches!(store.get_all(), Err(Error::StorageError(_))));
+        // File isn't overwritten, so it can be converted by other means.
+        assert!(store.add(&events[0]).is_err());
+        assert_eq!(
+            std::fs::read(&path)?,
+            serde_json::to_vec(&old_event).unwrap()
+        );
+
+        Ok(())
+    }
+}
@@ -1,180 +1,264 @@
-// use crate::{controller::Controller, error::Error};
-
-// use crate::kerl::{event_generator, KERL};
-// use keri::{
-//     database::{EventDatabase},
-//     event::{EventMessage},
-//     event_message::SignedEventMessage,
-//     prefix::IdentifierPrefix,
-//     state::IdentifierState,
-// };
-
-// pub struct Thing<D: EventDatabase> {
-//     kerl: KERL<D>,
-//     vc_storage: Vec<SignedAttestationDatum>,
-// }
-
-// impl<D: EventDatabase> Thing<D> {
-//     pub fn new(db: D, prefix: IdentifierPrefix) -> Self {
-//         Thing {
-//             kerl: KERL::new(db, prefix).unwrap(),
-//             vc_storage: vec![],
-//         }
-//     }
-
-//     pub fn add_document(
-//         &mut self,
-//         document: SignedAttestationDatum,
-//         signer: &Controller,
-//     ) -> Result<(), Error> {
-//         // Append interaction event to pack KEL.
-//         let vc_str = document.get_attestation_datum()?;
-//         let ixn = event_generator::make_ixn(Some(&vc_str), self.kerl.get_state()?.unwrap())?;
-//         let signature = signer.sign(std::str::from_utf8(&ixn.serialize()?).unwrap())?;
-//         self.kerl.process(ixn, signature)?;
-
-//         // Insert document to storage
-//         self.vc_storage.push(document);
-
-//         Ok(())
-//     }
-
-//     pub fn process(
-//         &mut self,
-//         event: EventMessage,
-//         signature: Vec<u8>,
-//     ) -> Result<SignedEventMessage, Error> {
-//         self.kerl.process(event, signature)
-//     }
-
-//     pub fn get_datum_list(&self) -> Result<String, Error> {
-//         let mut out = String::new();
-//         for datum in self.vc_storage.iter() {
-//             let hash = base64::encode_config(
-//                 blake3::hash(datum.get_attestation_datum()?.as_bytes())
-//                     .as_bytes()
-//                     .to_vec(),
-//                 base64::URL_SAFE,
-//             );
-//             out = format!(
-//                 "{}\nvc hash: {},\n vc {}\n",
-//                 out,
-//                 hash,
-//                 serde_json::to_string_pretty(datum).unwrap()
-//             );
-//         }
-//         Ok(out.to_string())
-//     }
-
-//     pub fn get_kerl(&self) -> Result<Option<Vec<u8>>, Error> {
-//         self.kerl.get_kerl()
-//     }
-
-//     pub fn get_state(&self) -> Result<Option<IdentifierState>, Error> {
-//         self.kerl.get_state()
-//     }
-// }
-
-// #[test]
-// pub fn test_pack() -> Result<(), Error> {
-//     use std::fs;
-//     use tempfile::tempdir;
-//     use tempfile::Builder;
-
-//     use crate::communication::tcp_communication::TCPCommunication;
-//     use crate::controller::Controller;
-//     use crate::datum::AttestationDatum;
-//     use keri::database::lmdb::LmdbEventDatabase;
-//     use event_generator::{Key, KeyType};
-
-//     let temp_dir = tempdir().unwrap();
-//     let adr_store_path = [temp_dir.path().to_str().unwrap(), "adr"].join("");
-
-//     // Setup empty pack kerl
-//     let pack_root = Builder::new().prefix("test-db").tempdir().unwrap();
-//     fs::create_dir_all(pack_root.path()).unwrap();
-//     let pack_db = LmdbEventDatabase::new(pack_root.path()).unwrap();
-//     let mut pack = Thing::new(pack_db, IdentifierPrefix::default());
-
-//     // Setup sender controller
-//     let sender_db_dir = tempdir().unwrap();
-//     let sender_db_path = sender_db_dir.path().to_str().unwrap();
-//     let mut sender = Controller::new(sender_db_path, "localhost:1212", &adr_store_path);
-//     println!(
-//         "Sender: {}, current key: {}\n",
-//         sender.get_prefix()?,
-//         base64::encode_config(&sender.get_current_pk(), base64::URL_SAFE)
-//     );
-
-//     // Setup courier controller
-//     let courier_db_dir = tempdir().unwrap();
-//     let courier_db_path = courier_db_dir.path().to_str().unwrap();
-//     let mut courier = Controller::new(courier_db_path, "localhost:1200", &adr_store_path);
-//     println!(
-//         "Courier: {}, current key: {}\n",
-//         courier.get_prefix()?,
-//         base64::encode_config(&courier.get_current_pk(), base64::URL_SAFE)
-//     );
-
-//     // Setup receiver controller
-//     let receiver_db_dir = tempdir().unwrap();
-//     let receiver_db_path = receiver_db_dir.path().to_str().unwrap();
-//     let receiver = Controller::new(receiver_db_path, "localhost:1000", &adr_store_path);
-
-//     let receiver_pref = receiver.get_prefix()?;
-
-//     // ========================================================
-//     // Sender fills the sending form
-//     // Incept pack kel with sender key as current key and courier public key as next.
-//     let pk = Key::new(sender.get_current_pk(), KeyType::Ed25519Sha512);
-//     let next_pk = Key::new(courier.get_current_pk(), KeyType::Ed25519Sha512);
-
-//     let icp_msg = event_generator::make_icp(&pk, &next_pk, None)?;
-//     let signature = sender.sign(&String::from_utf8(icp_msg.serialize()?).unwrap())?;
-//     // Confirm sending the pack by signing icp message
-//     pack.process(icp_msg, signature)?;
-
-//     // Construct confirmation of sending the pack.
-//     // Insert reciver id in the pack kerl
-//     let sender_prefix = sender.get_prefix()?;
-//     // Compute vc related stuff
-//     let msg = format!("I send pack to {}", receiver_pref);
-//     let sender_vc = AttestationDatum::new(&msg, &sender_prefix);
-//     let signed_ad = sender.issue_vc(&sender_vc)?;
-
-//     // Add document to pack. It adds document to pack vcx store and adds interaction event to pack kerl.
-//     pack.add_document(signed_ad, &sender)?;
-
-//     // =========================================================
-//     // Courier got the pack. Rotate key to storage public key as next.
-//     // Setup storage controller
-//     let receiver_db_dir = tempdir().unwrap();
-//     let receiver_db_path = receiver_db_dir.path().to_str().unwrap();
-//     let storage = Controller::new(receiver_db_path, "localhost:1200", &adr_store_path);
-
-//     let pk = Key::new(courier.get_current_pk(), KeyType::Ed25519Sha512);
-//     let next_pk = Key::new(storage.get_current_pk(), KeyType::Ed25519Sha512);
-
-//     let rotation_event = event_generator::make_rot(&pk, &next_pk, pack.get_state()?.unwrap())?;
-//     let signature = courier.sign(std::str::from_utf8(&rotation_event.serialize()?).unwrap())?;
-//     pack.process(rotation_event, signature)?;
-
-//     // Compute vc related stuff
-//     let msg = "I got the pack";
-//     let ad = AttestationDatum::new(msg, &courier.get_prefix()?);
-
-//     let signed_ad = courier.issue_vc(&ad).unwrap();
-
-//     // Append interaction event to pack KEL.
-//     pack.add_document(signed_ad, &courier)?;
-
-//     let formatted_kerl = TCPCommunication::format_event_stream(&pack.get_kerl()?.unwrap(), false);
-
-//     println!("=========== PACK'S KEL =============\n {}", formatted_kerl);
-//     println!(
-//         "==== DOCUMENTS ASSOCIATED WITH THE PACK ======\n {}",
-//         pack.get_datum_list()?
-//     );
-
-//     Ok(())
-// }
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    datum::SignedAttestationDatum,
+    error::Error,
+    kerl::{
+        event_generator::{make_digest_seal, Key, PrefixType, WitnessConfig},
+        pending::PendingStore,
+        KERL,
+    },
+    storage::{write_atomically, RecordLog},
+};
+use keri::{
+    database::EventDatabase,
+    event::{
+        sections::{seal::Seal, threshold::SignatureThreshold},
+        EventMessage,
+    },
+    event_message::SignedEventMessage,
+    prefix::{AttachedSignaturePrefix, IdentifierPrefix, Prefix},
+    state::IdentifierState,
+};
+
+const PREFIX_FILE: &str = "thing_prefix";
+const DOCUMENTS_FILE: &str = "documents";
+
+/// Identifier which keys are kept outside, for example on offline machine
+/// or by other controllers, like a pack passed between sender, courier and
+/// receiver. Its events are prepared here, signed elsewhere and committed
+/// when enough signatures are collected. Pending event, prefix and
+/// documents are kept in files in `path` directory, so the thing can be
+/// loaded after restart.
+pub struct Thing<D: EventDatabase> {
+    kerl: KERL<D>,
+    prefix_path: PathBuf,
+    pending: PendingStore,
+    vc_storage: RecordLog<String>,
+}
+
+impl<D: EventDatabase> Thing<D> {
+    pub fn new(db: D, path: &str) -> Result<Self, Error> {
+        let prefix_path = Path::new(path).join(PREFIX_FILE);
+        let prefix = if prefix_path.exists() {
+            String::from_utf8(fs::read(&prefix_path)?)?.parse()?
+        } else {
+            IdentifierPrefix::default()
+        };
+        Ok(Thing {
+            kerl: KERL::new(db, prefix)?,
+            prefix_path,
+            pending: PendingStore::new(path),
+            vc_storage: RecordLog::new(Path::new(path).join(DOCUMENTS_FILE)),
+        })
+    }
+
+    /// Prepares inception event with self-addressing prefix. Returns
+    /// serialized event, which has to be signed with `keys`.
+    pub fn prepare_inception(
+        &mut self,
+        keys: &[Key],
+        next_keys: &[Key],
+        threshold: &SignatureThreshold,
+        next_threshold: &SignatureThreshold,
+    ) -> Result<Vec<u8>, Error> {
+        if self.kerl.get_state()?.is_some() {
+            return Err(Error::Generic("Identifier is already incepted".into()));
+        }
+        let icp = self.kerl.prepare_icp(
+            keys,
+            next_keys,
+            threshold,
+            next_threshold,
+            &WitnessConfig::default(),
+            PrefixType::SelfAddressing,
+        )?;
+        self.set_pending(icp, None)
+    }
+
+    /// Prepares rotation event. Returns serialized event, which has to be
+    /// signed with `keys`. They have to match the next keys committed in
+    /// the previous establishment event.
+    pub fn prepare_rotation(
+        &mut self,
+        keys: &[Key],
+        next_keys: &[Key],
+        threshold: &SignatureThreshold,
+        next_threshold: &SignatureThreshold,
+    ) -> Result<Vec<u8>, Error> {
+        let rot = self
+            .kerl
+            .prepare_rot(keys, next_keys, threshold, next_threshold, None)?;
+        self.set_pending(rot, None)
+    }
+
+    /// Prepares interaction event which anchors given seals. Returns
+    /// serialized event, which has to be signed with current keys.
+    pub fn prepare_interaction(&mut self, seals: Vec<Seal>) -> Result<Vec<u8>, Error> {
+        let ixn = self.kerl.prepare_ixn(seals)?;
+        self.set_pending(ixn, None)
+    }
+
+    /// Prepares interaction event which anchors digest of the document.
+    /// Document is added to the storage when the event is committed.
+    pub fn prepare_document(&mut self, document: SignedAttestationDatum) -> Result<Vec<u8>, Error> {
+        let seal = make_digest_seal(document.get_attestation_datum()?.as_bytes());
+        let ixn = self.kerl.prepare_ixn(vec![seal])?;
+        self.set_pending(ixn, Some(document.to_string()?))
+    }
+
+    fn set_pending(
+        &mut self,
+        event: EventMessage,
+        document: Option<String>,
+    ) -> Result<Vec<u8>, Error> {
+        let serialized = event.serialize()?;
+        self.pending.set(event, document)?;
+        Ok(serialized)
+    }
+
+    /// Returns serialized event which waits for signatures, if there is
+    /// one.
+    pub fn get_pending(&self) -> Result<Option<Vec<u8>>, Error> {
+        self.pending
+            .get()?
+            .map(|pending| pending.event.serialize().map_err(Error::from))
+            .transpose()
+    }
+
+    /// Adds detached signatures of pending event. Signature replaces the
+    /// previous one made with key of the same index.
+    pub fn add_signatures(&mut self, signatures: &[AttachedSignaturePrefix]) -> Result<(), Error> {
+        self.pending.add_signatures(signatures)
+    }
+
+    /// Processes pending event with collected signatures. If they are
+    /// wrong or don't satisfy the threshold, event keeps waiting for more
+    /// signatures.
+    pub fn commit(&mut self) -> Result<SignedEventMessage, Error> {
+        let pending = self
+            .pending
+            .get()?
+            .ok_or(Error::Generic("There is no pending event".into()))?;
+        let signed = self.kerl.process(pending.event, pending.signatures)?;
+        if !self.prefix_path.exists() {
+            let prefix = signed.event_message.event.prefix.to_str();
+            write_atomically(&self.prefix_path, prefix.as_bytes())?;
+        }
+        if let Some(document) = pending.document {
+            self.vc_storage.append(&document)?;
+        }
+        self.pending.clear()?;
+        Ok(signed)
+    }
+
+    /// Drops pending event along with its signatures.
+    pub fn discard(&mut self) -> Result<(), Error> {
+        self.pending.clear()
+    }
+
+    pub fn get_datum_list(&self) -> Result<String, Error> {
+        let mut out = String::new();
+        for datum in self.vc_storage.load()? {
+            let datum = SignedAttestationDatum::deserialize(&datum)?;
+            let hash = base64::encode_config(
+                blake3::hash(datum.get_attestation_datum()?.as_bytes())
+                    .as_bytes()
+                    .to_vec(),
+                base64::URL_SAFE,
+            );
+            out = format!("{}\nvc hash: {},\n vc {}\n", out, hash, datum.to_string()?);
+        }
+        Ok(out)
+    }
+
+    pub fn get_kerl(&self) -> Result<Option<Vec<u8>>, Error> {
+        self.kerl.get_kerl()
+    }
+
+    pub fn get_state(&self) -> Result<Option<IdentifierState>, Error> {
+        self.kerl.get_state()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        controller::entity::Entity,
+        datum::{create_attestation, sign_attestation},
+        wallet::MultiKeyManager,
+    };
+    use keri::database::lmdb::LmdbEventDatabase;
+    use std::str::from_utf8;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_pack() -> Result<(), Error> {
+        let pack_dir = tempdir()?;
+        let pack_path = pack_dir.path().to_str().unwrap();
+        let pack_db = LmdbEventDatabase::new(pack_dir.path()).unwrap();
+        let mut pack = Thing::new(pack_db, pack_path)?;
+
+        let sender_dir = tempdir()?;
+        let sender = Entity::new(sender_dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        let courier_dir = tempdir()?;
+        let courier = Entity::new(courier_dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        let storage_dir = tempdir()?;
+        let storage = Entity::new(storage_dir.path().to_str().unwrap(), PrefixType::Basic)?;
+        let threshold = SignatureThreshold::Simple(1);
+
+        // Sender incepts the pack with courier's key as the next key.
+        let icp = pack.prepare_inception(
+            &sender.wallet.public_keys(),
+            &courier.wallet.public_keys(),
+            &threshold,
+            &threshold,
+        )?;
+        assert!(pack.commit().is_err());
+        pack.add_signatures(&sender.sign_indexed(from_utf8(&icp).unwrap())?)?;
+        pack.commit()?;
+        assert_eq!(pack.get_state()?.unwrap().sn, 0);
+
+        // Sender confirms sending the pack with a document.
+        let sender_prefix = sender.get_prefix()?;
+        let ad = create_attestation(&sender_prefix, "att_id", "I send the pack", "schema")?;
+        let ad_str = serde_json::to_string(&ad).unwrap();
+        let document = SignedAttestationDatum {
+            sa: sign_attestation(ad, sender.sign(&ad_str)?)?,
+        };
+        let ixn = pack.prepare_document(document)?;
+        // Only one event can wait for signatures.
+        assert!(pack.prepare_interaction(vec![]).is_err());
+        pack.add_signatures(&sender.sign_indexed(from_utf8(&ixn).unwrap())?)?;
+        pack.commit()?;
+
+        // Courier got the pack and rotates to storage's key. Sender's
+        // signature is not valid anymore.
+        let rot = pack.prepare_rotation(
+            &courier.wallet.public_keys(),
+            &storage.wallet.public_keys(),
+            &threshold,
+            &threshold,
+        )?;
+        pack.add_signatures(&sender.sign_indexed(from_utf8(&rot).unwrap())?)?;
+        assert!(pack.commit().is_err());
+        assert_eq!(pack.get_pending()?, Some(rot.clone()));
+
+        // Pending event and its signatures are kept over restart.
+        drop(pack);
+        let pack_db = LmdbEventDatabase::new(pack_dir.path()).unwrap();
+        let mut pack = Thing::new(pack_db, pack_path)?;
+        assert_eq!(pack.get_pending()?, Some(rot.clone()));
+        pack.add_signatures(&courier.sign_indexed(from_utf8(&rot).unwrap())?)?;
+        pack.commit()?;
+        assert_eq!(pack.get_state()?.unwrap().sn, 2);
+        assert!(pack.get_pending()?.is_none());
+        assert_eq!(pack.vc_storage.load()?.len(), 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use ed25519_dalek::{Keypair, PublicKey as DalekPublicKey, SecretKey};
+use k256::ecdsa::SigningKey;
+use keri::derivation::self_signing::SelfSigning;
+use rand::{rngs::OsRng, RngCore};
+
+use crate::{
+    error::Error,
+    kerl::event_generator::{Key, KeyType},
+};
+
+/// Signing key pair of one of supported types.
+pub enum KeyPair {
+    Ed25519(Keypair),
+    Secp256k1(SigningKey),
+}
+
+impl KeyPair {
+    pub fn generate(key_type: KeyType) -> Result<Self, Error> {
+        match key_type {
+            KeyType::Ed25519Sha512 => Ok(KeyPair::Ed25519(Keypair::generate(&mut OsRng))),
+            KeyType::EcdsaSecp256k1Sha256 => {
+                // Random bytes are not a valid secp256k1 scalar with
+                // negligible probability, try again in that case.
+                let mut bytes = [0u8; 32];
+                loop {
+                    OsRng.fill_bytes(&mut bytes);
+                    if let Ok(key) = SigningKey::from_bytes(&bytes) {
+                        return Ok(KeyPair::Secp256k1(key));
+                    }
+                }
+            }
+            KeyType::X25519 => Err(Error::Generic(
+                "X25519 key can't be used for signing".into(),
+            )),
+        }
+    }
+
+    pub fn from_private_key(key_type: KeyType, private_key: &[u8]) -> Result<Self, Error> {
+        match key_type {
+            KeyType::Ed25519Sha512 => {
+                let secret = SecretKey::from_bytes(private_key)
+                    .map_err(|e| Error::Generic(e.to_string()))?;
+                let public: DalekPublicKey = (&secret).into();
+                Ok(KeyPair::Ed25519(Keypair { secret, public }))
+            }
+            KeyType::EcdsaSecp256k1Sha256 => Ok(KeyPair::Secp256k1(
+                SigningKey::from_bytes(private_key).map_err(|e| Error::Generic(e.to_string()))?,
+            )),
+            KeyType::X25519 => Err(Error::Generic(
+                "X25519 key can't be used for signing".into(),
+            )),
+        }
+    }
+
+    pub fn key_type(&self) -> KeyType {
+        match self {
+            KeyPair::Ed25519(_) => KeyType::Ed25519Sha512,
+            KeyPair::Secp256k1(_) => KeyType::EcdsaSecp256k1Sha256,
+        }
+    }
+
+    pub fn signature_type(&self) -> SelfSigning {
+        match self {
+            KeyPair::Ed25519(_) => SelfSigning::Ed25519Sha512,
+            KeyPair::Secp256k1(_) => SelfSigning::ECDSAsecp256k1Sha256,
+        }
+    }
+
+    pub fn public_key(&self) -> Key {
+        let key = match self {
+            KeyPair::Ed25519(kp) => kp.public.to_bytes().to_vec(),
+            KeyPair::Secp256k1(sk) => sk.verifying_key().to_bytes().to_vec(),
+        };
+        Key::new(key, self.key_type())
+    }
+
+    pub fn private_key(&self) -> Vec<u8> {
+        match self {
+            KeyPair::Ed25519(kp) => kp.secret.to_bytes().to_vec(),
+            KeyPair::Secp256k1(sk) => sk.to_bytes().to_vec(),
+        }
+    }
+
+    pub fn sign(&self, msg: &[u8]) -> Vec<u8> {
+        match self {
+            KeyPair::Ed25519(kp) => {
+                use ed25519_dalek::Signer;
+                kp.sign(msg).to_bytes().to_vec()
+            }
+            KeyPair::Secp256k1(sk) => {
+                use k256::ecdsa::{signature::Signer, Signature};
+                let signature: Signature = sk.sign(msg);
+                signature.as_ref().to_vec()
+            }
+        }
+    }
+
+    /// Returns X25519 key agreement key corresponding to ed25519 public key.
+    pub fn x25519_public_key(&self) -> Result<Key, Error> {
+        match self {
+            KeyPair::Ed25519(kp) => {
+                let montgomery = CompressedEdwardsY::from_slice(kp.public.as_bytes())
+                    .decompress()
+                    .ok_or(Error::Generic("Invalid ed25519 public key".into()))?
+                    .to_montgomery();
+                Ok(Key::new(montgomery.to_bytes().to_vec(), KeyType::X25519))
+            }
+            _ => Err(Error::Generic(
+                "Only ed25519 key can be converted to X25519".into(),
+            )),
+        }
+    }
+}
@@ -0,0 +1,421 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::URL_SAFE;
+use chacha20poly1305::{
+    aead::{Aead, NewAead},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use keri::event::sections::threshold::SignatureThreshold;
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use super::{key_pair::KeyPair, KeySource, MultiKeyManager, Wallet};
+use crate::{error::Error, kerl::event_generator::KeyType, storage::write_atomically};
+
+const KEYSTORE_FILE: &str = "keystore";
+/// Version of keystore file format written by `save`.
+const KEYSTORE_VERSION: u32 = 2;
+/// Context of blake3 key derivation used by keystores of version 1.
+const LEGACY_KDF_CONTEXT: &str = "keri_ecosystem 2021-06 keystore encryption key";
+
+/// Argon2id costs of keys derived for new keystores. Costs are stored
+/// along with the salt, so they can be raised without breaking older files.
+#[cfg(not(test))]
+const KDF_PARAMS: KdfParams = KdfParams {
+    m_cost: 19 * 1024,
+    t_cost: 2,
+    p_cost: 1,
+};
+#[cfg(test)]
+const KDF_PARAMS: KdfParams = KdfParams {
+    m_cost: 64,
+    t_cost: 1,
+    p_cost: 1,
+};
+
+/// Argon2id parameters: memory in KiB, number of passes and parallelism.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct KdfParams {
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+}
+
+/// Private keys of an identifier. Serialized and encrypted before being
+/// written to disk.
+#[derive(Serialize, Deserialize)]
+struct StoredKeys {
+    current: Vec<(KeyType, String)>,
+    next: Vec<(KeyType, String)>,
+    /// Keys which become next keys after rotation. They are stored before
+    /// rotation event is made, so keys are never lost.
+    #[serde(default)]
+    pending: Vec<(KeyType, String)>,
+    threshold: SignatureThreshold,
+    next_threshold: SignatureThreshold,
+    /// Source of keys for next rotations. Keystores written before it was
+    /// stored use random keys.
+    #[serde(default)]
+    source: StoredSource,
+}
+
+/// Stored `KeySource`, with seeds base64 encoded.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum StoredSource {
+    Random,
+    Seeds { seeds: Vec<String> },
+    Mnemonic { seed: String, index: u32 },
+}
+
+impl Default for StoredSource {
+    fn default() -> Self {
+        StoredSource::Random
+    }
+}
+
+impl StoredSource {
+    fn new(source: &KeySource) -> Self {
+        match source {
+            KeySource::Random => StoredSource::Random,
+            KeySource::Seeds(keys) => StoredSource::Seeds {
+                seeds: keys
+                    .iter()
+                    .map(|kp| base64::encode_config(kp.private_key(), URL_SAFE))
+                    .collect(),
+            },
+            KeySource::Mnemonic { seed, index } => StoredSource::Mnemonic {
+                seed: base64::encode_config(seed, URL_SAFE),
+                index: *index,
+            },
+        }
+    }
+
+    fn to_source(&self) -> Result<KeySource, Error> {
+        Ok(match self {
+            StoredSource::Random => KeySource::Random,
+            StoredSource::Seeds { seeds } => KeySource::Seeds(
+                seeds
+                    .iter()
+                    .map(|seed| {
+                        KeyPair::from_private_key(
+                            KeyType::Ed25519Sha512,
+                            &base64::decode_config(seed, URL_SAFE)?,
+                        )
+                    })
+                    .collect::<Result<_, Error>>()?,
+            ),
+            StoredSource::Mnemonic { seed, index } => KeySource::Mnemonic {
+                seed: base64::decode_config(seed, URL_SAFE)?,
+                index: *index,
+            },
+        })
+    }
+}
+
+/// Content of keystore file.
+#[derive(Serialize, Deserialize)]
+struct KeystoreFile {
+    /// Files written before versioning have no version, they are of
+    /// version 1.
+    #[serde(default = "legacy_version")]
+    version: u32,
+    prefix: String,
+    salt: String,
+    /// Parameters of argon2id key derivation. Missing in version 1, which
+    /// derived key with blake3.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kdf: Option<KdfParams>,
+    nonce: String,
+    keys: String,
+}
+
+fn legacy_version() -> u32 {
+    1
+}
+
+/// Encrypted file keeping private keys of an identifier. It lives in the
+/// identifier's event database directory.
+pub struct Keystore {
+    path: PathBuf,
+    passphrase: String,
+}
+
+impl Keystore {
+    pub fn new(db_path: &str, passphrase: &str) -> Self {
+        Self {
+            path: Path::new(db_path).join(KEYSTORE_FILE),
+            passphrase: passphrase.to_string(),
+        }
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    /// Encrypts wallet keys and writes them to keystore file along with
+    /// identifier prefix.
+    pub fn save(&self, prefix: &str, wallet: &Wallet) -> Result<(), Error> {
+        let (current, next) = wallet.private_keys();
+        let encode = |keys: Vec<(KeyType, Vec<u8>)>| {
+            keys.into_iter()
+                .map(|(key_type, key)| (key_type, base64::encode_config(key, URL_SAFE)))
+                .collect::<Vec<_>>()
+        };
+        let keys = serde_json::to_vec(&StoredKeys {
+            current: encode(current),
+            next: encode(next),
+            pending: encode(wallet.pending_private_keys()),
+            threshold: wallet.threshold(),
+            next_threshold: wallet.next_threshold(),
+            source: StoredSource::new(wallet.source()),
+        })
+        .map_err(|e| Error::StorageError(e.to_string()))?;
+
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let mut nonce = [0u8; 24];
+        OsRng.fill_bytes(&mut nonce);
+
+        let encrypted = self
+            .cipher(&salt, &KDF_PARAMS)?
+            .encrypt(XNonce::from_slice(&nonce), keys.as_ref())
+            .map_err(|_e| Error::StorageError("can't encrypt keystore".into()))?;
+
+        let file = KeystoreFile {
+            version: KEYSTORE_VERSION,
+            prefix: prefix.to_string(),
+            salt: base64::encode_config(salt, URL_SAFE),
+            kdf: Some(KDF_PARAMS),
+            nonce: base64::encode_config(nonce, URL_SAFE),
+            keys: base64::encode_config(encrypted, URL_SAFE),
+        };
+        let file_content =
+            serde_json::to_vec(&file).map_err(|e| Error::StorageError(e.to_string()))?;
+        // Keys are never left half written.
+        write_atomically(&self.path, &file_content)
+    }
+
+    /// Reads and decrypts keystore file. Returns stored identifier prefix
+    /// and wallet with its keys. Keystore of older version is rewritten in
+    /// the current format.
+    pub fn load(&self) -> Result<(String, Wallet), Error> {
+        let file_content = fs::read(&self.path)?;
+        let file: KeystoreFile = serde_json::from_slice(&file_content)
+            .map_err(|e| Error::StorageError(e.to_string()))?;
+
+        let salt = base64::decode_config(&file.salt, URL_SAFE)?;
+        let nonce = base64::decode_config(&file.nonce, URL_SAFE)?;
+        let encrypted = base64::decode_config(&file.keys, URL_SAFE)?;
+
+        let cipher = match (file.version, &file.kdf) {
+            (1, _) => self.legacy_cipher(&salt),
+            (KEYSTORE_VERSION, Some(params)) => self.cipher(&salt, params)?,
+            (KEYSTORE_VERSION, None) => {
+                return Err(Error::StorageError(
+                    "keystore misses key derivation parameters".into(),
+                ))
+            }
+            (version, _) => {
+                return Err(Error::StorageError(format!(
+                    "unsupported keystore version {}",
+                    version
+                )))
+            }
+        };
+        let keys = cipher
+            .decrypt(XNonce::from_slice(&nonce), encrypted.as_ref())
+            .map_err(|_e| {
+                Error::StorageError("can't decrypt keystore, wrong passphrase?".into())
+            })?;
+        let keys: StoredKeys =
+            serde_json::from_slice(&keys).map_err(|e| Error::StorageError(e.to_string()))?;
+
+        let decode = |keys: &[(KeyType, String)]| {
+            keys.iter()
+                .map(|(key_type, key)| Ok((*key_type, base64::decode_config(key, URL_SAFE)?)))
+                .collect::<Result<Vec<_>, Error>>()
+        };
+        // Keys of decommissioned identifier are wiped.
+        let wallet = if keys.current.is_empty() {
+            Wallet::wiped()
+        } else {
+            let mut wallet = Wallet::from_private_keys(
+                &decode(&keys.current)?,
+                &decode(&keys.next)?,
+                keys.threshold,
+                keys.next_threshold,
+            )?;
+            wallet.set_pending_keys(&decode(&keys.pending)?)?;
+            wallet.set_source(keys.source.to_source()?);
+            wallet
+        };
+
+        if file.version != KEYSTORE_VERSION {
+            self.save(&file.prefix, &wallet)?;
+        }
+
+        Ok((file.prefix, wallet))
+    }
+
+    /// Makes cipher with key derived from passphrase by argon2id.
+    fn cipher(&self, salt: &[u8], params: &KdfParams) -> Result<XChaCha20Poly1305, Error> {
+        let params = Params::new(params.m_cost, params.t_cost, params.p_cost, Some(32))
+            .map_err(|e| Error::StorageError(e.to_string()))?;
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(self.passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| Error::StorageError(e.to_string()))?;
+        Ok(XChaCha20Poly1305::new(Key::from_slice(&key)))
+    }
+
+    /// Makes cipher of version 1 keystore, with key derived by blake3.
+    fn legacy_cipher(&self, salt: &[u8]) -> XChaCha20Poly1305 {
+        let key_material = [salt, self.passphrase.as_bytes()].concat();
+        let key = blake3::derive_key(LEGACY_KDF_CONTEXT, &key_material);
+        XChaCha20Poly1305::new(Key::from_slice(&key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keri::signer::KeyManager;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_save_and_load() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let path = dir.path().to_str().unwrap();
+
+        let wallet = Wallet::with_key_types(
+            &[KeyType::Ed25519Sha512, KeyType::EcdsaSecp256k1Sha256],
+            SignatureThreshold::Simple(1),
+        )?;
+        let keystore = Keystore::new(path, "secret");
+        assert!(!keystore.exists());
+        keystore.save("DSomePrefix", &wallet)?;
+        assert!(keystore.exists());
+
+        let (prefix, loaded) = keystore.load()?;
+        assert_eq!(prefix, "DSomePrefix");
+        assert_eq!(wallet.public_key().0, loaded.public_key().0);
+        assert_eq!(wallet.next_public_key().0, loaded.next_public_key().0);
+        assert_eq!(loaded.public_keys().len(), 2);
+        assert!(!loaded.is_rotation_prepared());
+
+        // Keys prepared for rotation are stored too.
+        let mut wallet = loaded;
+        wallet.prepare_rotation()?;
+        keystore.save("DSomePrefix", &wallet)?;
+        let (_, loaded) = keystore.load()?;
+        assert_eq!(
+            loaded.pending_public_keys()[0].derive_key_prefix(),
+            wallet.pending_public_keys()[0].derive_key_prefix()
+        );
+
+        // Wrong passphrase can't decrypt keys.
+        assert!(Keystore::new(path, "wrong").load().is_err());
+
+        let file: KeystoreFile = serde_json::from_slice(&fs::read(&keystore.path)?).unwrap();
+        assert_eq!(file.version, KEYSTORE_VERSION);
+        assert_eq!(file.kdf, Some(KDF_PARAMS));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_source() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let keystore = Keystore::new(dir.path().to_str().unwrap(), "secret");
+        let phrase = Wallet::generate_mnemonic();
+
+        // Keys derived after reload are the same as derived from phrase.
+        let mut wallet = Wallet::from_mnemonic(&phrase)?;
+        wallet.rotate()?;
+        keystore.save("DSomePrefix", &wallet)?;
+        let (_, mut loaded) = keystore.load()?;
+        let mut expected = Wallet::from_mnemonic(&phrase)?;
+        expected.rotate()?;
+        loaded.rotate()?;
+        expected.rotate()?;
+        assert_eq!(loaded.public_key().0, expected.public_key().0);
+        assert_eq!(loaded.next_public_key().0, expected.next_public_key().0);
+
+        // Seeds left after inception are kept as well.
+        let seeds: Vec<String> = (1..=4u8)
+            .map(|i| base64::encode_config([i; 32], URL_SAFE))
+            .collect();
+        let seeds: Vec<&str> = seeds.iter().map(String::as_str).collect();
+        keystore.save("DSomePrefix", &Wallet::from_seeds(&seeds)?)?;
+        let (_, mut loaded) = keystore.load()?;
+        let mut expected = Wallet::from_seeds(&seeds)?;
+        loaded.rotate()?;
+        expected.rotate()?;
+        assert_eq!(loaded.next_public_key().0, expected.next_public_key().0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_legacy_keystore() -> Result<(), Error> {
+        let dir = tempdir()?;
+        let keystore = Keystore::new(dir.path().to_str().unwrap(), "secret");
+        let wallet = Wallet::new()?;
+
+        // Write keystore the way version 1 did, without version and
+        // argon2id parameters.
+        let (current, next) = wallet.private_keys();
+        let encode = |keys: Vec<(KeyType, Vec<u8>)>| {
+            keys.into_iter()
+                .map(|(key_type, key)| (key_type, base64::encode_config(key, URL_SAFE)))
+                .collect::<Vec<_>>()
+        };
+        let keys = serde_json::to_vec(&StoredKeys {
+            current: encode(current),
+            next: encode(next),
+            pending: vec![],
+            threshold: wallet.threshold(),
+            next_threshold: wallet.next_threshold(),
+            source: StoredSource::Random,
+        })
+        .unwrap();
+        let (salt, nonce) = ([1u8; 16], [2u8; 24]);
+        let encrypted = keystore
+            .legacy_cipher(&salt)
+            .encrypt(XNonce::from_slice(&nonce), keys.as_ref())
+            .unwrap();
+        let legacy = serde_json::json!({
+            "prefix": "DSomePrefix",
+            "salt": base64::encode_config(salt, URL_SAFE),
+            "nonce": base64::encode_config(nonce, URL_SAFE),
+            "keys": base64::encode_config(encrypted, URL_SAFE),
+        });
+        fs::write(&keystore.path, legacy.to_string())?;
+
+        // It's readable and upgraded to the current version.
+        let (prefix, loaded) = keystore.load()?;
+        assert_eq!(prefix, "DSomePrefix");
+        assert_eq!(loaded.public_key().0, wallet.public_key().0);
+        let file: KeystoreFile = serde_json::from_slice(&fs::read(&keystore.path)?).unwrap();
+        assert_eq!(file.version, KEYSTORE_VERSION);
+        assert_eq!(keystore.load()?.1.public_key().0, wallet.public_key().0);
+
+        // Unknown versions are rejected.
+        let future = serde_json::json!({
+            "version": KEYSTORE_VERSION + 1,
+            "prefix": "DSomePrefix",
+            "salt": base64::encode_config(salt, URL_SAFE),
+            "nonce": base64::encode_config(nonce, URL_SAFE),
+            "keys": base64::encode_config(b"keys", URL_SAFE),
+        });
+        fs::write(&keystore.path, future.to_string())?;
+        assert!(matches!(keystore.load(), Err(Error::StorageError(_))));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,573 @@
+pub mod key_pair;
+pub mod keystore;
+
+use std::collections::VecDeque;
+
+use base64::URL_SAFE;
+use bip39::{Language, Mnemonic, MnemonicType, Seed};
+use keri::{
+    error::Error as KeriError,
+    event::sections::threshold::SignatureThreshold,
+    prefix::AttachedSignaturePrefix,
+    signer::KeyManager,
+};
+use ursa::keys::PublicKey;
+
+use self::key_pair::KeyPair;
+use crate::{
+    error::Error,
+    kerl::event_generator::{Key, KeyType},
+};
+
+const MNEMONIC_CONTEXT: &str = "keri_ecosystem 2021-06 mnemonic key derivation";
+
+/// Key manager able to handle identifiers with multiple keys and
+/// signing thresholds.
+pub trait MultiKeyManager: KeyManager {
+    fn public_keys(&self) -> Vec<Key>;
+    fn next_public_keys(&self) -> Vec<Key>;
+    fn threshold(&self) -> SignatureThreshold;
+    fn next_threshold(&self) -> SignatureThreshold;
+    /// Signs message with all current keys. Signatures are indexed by the
+    /// position of the key in the key list.
+    fn sign_indexed(&self, msg: &[u8]) -> Result<Vec<AttachedSignaturePrefix>, Error>;
+    /// Rotates to next keys without generating new next keys, so no
+    /// further rotation is possible.
+    fn abandon(&mut self) -> Result<(), Error>;
+    /// Makes keys which become next keys after rotation, if they aren't
+    /// made yet. Rotation event can be made and signed before the key
+    /// manager is rotated.
+    fn prepare_rotation(&mut self) -> Result<(), Error>;
+    /// Returns keys which become next keys after rotation.
+    fn pending_public_keys(&self) -> Vec<Key>;
+    /// Signs message with all next keys, as rotation event is signed.
+    fn sign_indexed_next(&self, msg: &[u8]) -> Result<Vec<AttachedSignaturePrefix>, Error>;
+}
+
+/// Describes where keys for next rotations come from.
+pub(crate) enum KeySource {
+    Random,
+    /// Seeds left after inception. They are used in order, random keys are
+    /// generated when all of them are used.
+    Seeds(VecDeque<KeyPair>),
+    /// Keys derived from mnemonic seed. `index` is the index of the next
+    /// key to derive.
+    Mnemonic { seed: Vec<u8>, index: u32 },
+}
+
+/// Holds current and next key pairs of an identifier along with their
+/// signing thresholds.
+///
+/// Unlike `CryptoBox` it gives access to the private keys, so they can be
+/// stored in a keystore and loaded again after restart.
+pub struct Wallet {
+    current: Vec<KeyPair>,
+    next: Vec<KeyPair>,
+    /// Keys which become next keys after rotation, made by
+    /// `prepare_rotation`.
+    pending: Vec<KeyPair>,
+    threshold: SignatureThreshold,
+    next_threshold: SignatureThreshold,
+    source: KeySource,
+}
+
+impl Wallet {
+    pub fn new() -> Result<Self, Error> {
+        Self::with_keys(1, SignatureThreshold::Simple(1))
+    }
+
+    /// Creates wallet with `key_count` random ed25519 current and next
+    /// keys. The same threshold is used for current and next keys.
+    pub fn with_keys(key_count: usize, threshold: SignatureThreshold) -> Result<Self, Error> {
+        Self::with_key_types(&vec![KeyType::Ed25519Sha512; key_count], threshold)
+    }
+
+    /// Creates wallet with random current and next keys of given types.
+    /// The same threshold is used for current and next keys.
+    pub fn with_key_types(
+        key_types: &[KeyType],
+        threshold: SignatureThreshold,
+    ) -> Result<Self, Error> {
+        check_threshold(&threshold, key_types.len())?;
+        let generate = || {
+            key_types
+                .iter()
+                .map(|key_type| KeyPair::generate(*key_type))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(Self {
+            current: generate()?,
+            next: generate()?,
+            pending: vec![],
+            threshold: threshold.clone(),
+            next_threshold: threshold,
+            source: KeySource::Random,
+        })
+    }
+
+    /// Creates wallet of non-transferable identifier. It has one key and
+    /// no next keys, so it can't be rotated.
+    pub fn non_transferable(key_type: KeyType) -> Result<Self, Error> {
+        Ok(Self {
+            current: vec![KeyPair::generate(key_type)?],
+            next: vec![],
+            pending: vec![],
+            threshold: SignatureThreshold::Simple(1),
+            next_threshold: SignatureThreshold::Simple(0),
+            source: KeySource::Random,
+        })
+    }
+
+    /// Creates wallet without any keys. It's what is left of the wallet of
+    /// decommissioned identifier.
+    pub fn wiped() -> Self {
+        Self {
+            current: vec![],
+            next: vec![],
+            pending: vec![],
+            threshold: SignatureThreshold::Simple(0),
+            next_threshold: SignatureThreshold::Simple(0),
+            source: KeySource::Random,
+        }
+    }
+
+    /// Removes all keys and the material they are derived from. Key pairs
+    /// zero their secrets when dropped.
+    pub fn wipe(&mut self) {
+        if let KeySource::Mnemonic { seed, .. } = &mut self.source {
+            seed.iter_mut().for_each(|byte| *byte = 0);
+        }
+        *self = Self::wiped();
+    }
+
+    /// Returns true if there are any current keys.
+    pub fn has_keys(&self) -> bool {
+        !self.current.is_empty()
+    }
+
+    /// Returns true if there are next keys to rotate to.
+    pub fn is_rotatable(&self) -> bool {
+        !self.next.is_empty()
+    }
+
+    /// Returns true if keys which become next keys after rotation are
+    /// already made.
+    pub fn is_rotation_prepared(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Restore wallet from current and next private keys and thresholds.
+    pub fn from_private_keys(
+        current: &[(KeyType, Vec<u8>)],
+        next: &[(KeyType, Vec<u8>)],
+        threshold: SignatureThreshold,
+        next_threshold: SignatureThreshold,
+    ) -> Result<Self, Error> {
+        check_threshold(&threshold, current.len())?;
+        // Non-transferable identifiers have no next keys.
+        if !next.is_empty() {
+            check_threshold(&next_threshold, next.len())?;
+        }
+        let to_key_pairs = |keys: &[(KeyType, Vec<u8>)]| {
+            keys.iter()
+                .map(|(key_type, key)| KeyPair::from_private_key(*key_type, key))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(Self {
+            current: to_key_pairs(current)?,
+            next: to_key_pairs(next)?,
+            pending: vec![],
+            threshold,
+            next_threshold,
+            source: KeySource::Random,
+        })
+    }
+
+    /// Creates wallet from list of base64 (url safe) encoded ed25519 seeds.
+    /// First seed is used for current key, second for next key and the
+    /// rest for subsequent rotations.
+    pub fn from_seeds(seeds: &[&str]) -> Result<Self, Error> {
+        let mut keys = seeds
+            .iter()
+            .map(|seed| {
+                KeyPair::from_private_key(
+                    KeyType::Ed25519Sha512,
+                    &base64::decode_config(seed, URL_SAFE)?,
+                )
+            })
+            .collect::<Result<VecDeque<_>, Error>>()?;
+        if keys.len() < 2 {
+            return Err(Error::Generic(
+                "At least two seeds are required, for current and next key".into(),
+            ));
+        }
+        let current = keys.pop_front().unwrap();
+        let next = keys.pop_front().unwrap();
+
+        Ok(Self {
+            current: vec![current],
+            next: vec![next],
+            pending: vec![],
+            threshold: SignatureThreshold::Simple(1),
+            next_threshold: SignatureThreshold::Simple(1),
+            source: KeySource::Seeds(keys),
+        })
+    }
+
+    /// Creates wallet from BIP39 english mnemonic phrase. Key of index `n`
+    /// is derived from mnemonic seed and `n`, so the same phrase always
+    /// gives the same sequence of keys.
+    pub fn from_mnemonic(phrase: &str) -> Result<Self, Error> {
+        let mnemonic = Mnemonic::from_phrase(phrase, Language::English)
+            .map_err(|e| Error::Generic(e.to_string()))?;
+        let seed = Seed::new(&mnemonic, "").as_bytes().to_vec();
+
+        Ok(Self {
+            current: vec![derive_keypair(&seed, 0)?],
+            next: vec![derive_keypair(&seed, 1)?],
+            pending: vec![],
+            threshold: SignatureThreshold::Simple(1),
+            next_threshold: SignatureThreshold::Simple(1),
+            source: KeySource::Mnemonic { seed, index: 2 },
+        })
+    }
+
+    /// Generates new 24 words mnemonic phrase.
+    pub fn generate_mnemonic() -> String {
+        Mnemonic::new(MnemonicType::Words24, Language::English).into_phrase()
+    }
+
+    /// Returns X25519 key agreement key corresponding to the first current
+    /// key. The first key has to be ed25519 key.
+    pub fn encryption_key(&self) -> Result<Key, Error> {
+        self.first_key()?.x25519_public_key()
+    }
+
+    fn first_key(&self) -> Result<&KeyPair, Error> {
+        self.current
+            .get(0)
+            .ok_or(Error::Generic("There are no current keys".into()))
+    }
+
+    /// Returns current and next private keys along with their types.
+    pub(crate) fn private_keys(&self) -> (Vec<(KeyType, Vec<u8>)>, Vec<(KeyType, Vec<u8>)>) {
+        let to_bytes = |keys: &[KeyPair]| {
+            keys.iter()
+                .map(|kp| (kp.key_type(), kp.private_key()))
+                .collect::<Vec<_>>()
+        };
+        (to_bytes(&self.current), to_bytes(&self.next))
+    }
+
+    /// Returns private keys which become next keys after rotation.
+    pub(crate) fn pending_private_keys(&self) -> Vec<(KeyType, Vec<u8>)> {
+        self.pending
+            .iter()
+            .map(|kp| (kp.key_type(), kp.private_key()))
+            .collect()
+    }
+
+    /// Returns source of keys for next rotations.
+    pub(crate) fn source(&self) -> &KeySource {
+        &self.source
+    }
+
+    /// Restores source of keys for next rotations.
+    pub(crate) fn set_source(&mut self, source: KeySource) {
+        self.source = source;
+    }
+
+    /// Restores keys which become next keys after rotation.
+    pub(crate) fn set_pending_keys(&mut self, keys: &[(KeyType, Vec<u8>)]) -> Result<(), Error> {
+        self.pending = keys
+            .iter()
+            .map(|(key_type, key)| KeyPair::from_private_key(*key_type, key))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(())
+    }
+
+    fn next_keypair(&mut self, key_type: KeyType) -> Result<KeyPair, Error> {
+        match &mut self.source {
+            KeySource::Random => KeyPair::generate(key_type),
+            KeySource::Seeds(keys) => match keys.pop_front() {
+                Some(kp) => Ok(kp),
+                None => KeyPair::generate(key_type),
+            },
+            KeySource::Mnemonic { seed, index } => {
+                let keypair = derive_keypair(seed, *index)?;
+                *index += 1;
+                Ok(keypair)
+            }
+        }
+    }
+}
+
+// Single key methods use the first key from the list.
+impl KeyManager for Wallet {
+    fn sign(&self, msg: &Vec<u8>) -> Result<Vec<u8>, KeriError> {
+        self.first_key()
+            .map(|kp| kp.sign(msg))
+            .map_err(|e| KeriError::SemanticError(e.to_string()))
+    }
+
+    fn public_key(&self) -> PublicKey {
+        PublicKey(
+            self.current
+                .get(0)
+                .map(|kp| kp.public_key().key())
+                .unwrap_or_default(),
+        )
+    }
+
+    fn next_public_key(&self) -> PublicKey {
+        PublicKey(
+            self.next
+                .get(0)
+                .map(|kp| kp.public_key().key())
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Rotates to next keys. Keys made by `prepare_rotation` become next
+    /// keys.
+    fn rotate(&mut self) -> Result<(), KeriError> {
+        self.prepare_rotation()
+            .map_err(|e| KeriError::SemanticError(e.to_string()))?;
+        let new_next = std::mem::take(&mut self.pending);
+        self.current = std::mem::replace(&mut self.next, new_next);
+        self.threshold = self.next_threshold.clone();
+        Ok(())
+    }
+}
+
+impl MultiKeyManager for Wallet {
+    fn public_keys(&self) -> Vec<Key> {
+        self.current.iter().map(KeyPair::public_key).collect()
+    }
+
+    fn next_public_keys(&self) -> Vec<Key> {
+        self.next.iter().map(KeyPair::public_key).collect()
+    }
+
+    fn threshold(&self) -> SignatureThreshold {
+        self.threshold.clone()
+    }
+
+    fn next_threshold(&self) -> SignatureThreshold {
+        self.next_threshold.clone()
+    }
+
+    fn sign_indexed(&self, msg: &[u8]) -> Result<Vec<AttachedSignaturePrefix>, Error> {
+        self.first_key()?;
+        Ok(self
+            .current
+            .iter()
+            .enumerate()
+            .map(|(i, kp)| {
+                AttachedSignaturePrefix::new(kp.signature_type(), kp.sign(msg), i as u16)
+            })
+            .collect())
+    }
+
+    fn abandon(&mut self) -> Result<(), Error> {
+        if !self.is_rotatable() {
+            return Err(Error::Generic("There are no next keys to rotate to".into()));
+        }
+        self.current = std::mem::take(&mut self.next);
+        self.pending = vec![];
+        self.threshold =
+            std::mem::replace(&mut self.next_threshold, SignatureThreshold::Simple(0));
+        Ok(())
+    }
+
+    fn prepare_rotation(&mut self) -> Result<(), Error> {
+        if !self.is_rotatable() {
+            return Err(Error::Generic("There are no next keys to rotate to".into()));
+        }
+        if self.pending.is_empty() {
+            let key_types: Vec<_> = self.next.iter().map(KeyPair::key_type).collect();
+            self.pending = key_types
+                .into_iter()
+                .map(|key_type| self.next_keypair(key_type))
+                .collect::<Result<Vec<_>, _>>()?;
+        }
+        Ok(())
+    }
+
+    fn pending_public_keys(&self) -> Vec<Key> {
+        self.pending.iter().map(KeyPair::public_key).collect()
+    }
+
+    fn sign_indexed_next(&self, msg: &[u8]) -> Result<Vec<AttachedSignaturePrefix>, Error> {
+        if !self.is_rotatable() {
+            return Err(Error::Generic("There are no next keys to sign with".into()));
+        }
+        Ok(self
+            .next
+            .iter()
+            .enumerate()
+            .map(|(i, kp)| {
+                AttachedSignaturePrefix::new(kp.signature_type(), kp.sign(msg), i as u16)
+            })
+            .collect())
+    }
+}
+
+/// Checks if threshold can be satisfied by `key_count` keys.
+fn check_threshold(threshold: &SignatureThreshold, key_count: usize) -> Result<(), Error> {
+    let indexes: Vec<usize> = (0..key_count).collect();
+    match threshold.enough_signatures(&indexes) {
+        Ok(true) if key_count > 0 => Ok(()),
+        _ => Err(Error::Generic(format!(
+            "Threshold can't be satisfied by {} keys",
+            key_count
+        ))),
+    }
+}
+
+fn derive_keypair(seed: &[u8], index: u32) -> Result<KeyPair, Error> {
+    let key_material = [seed, &index.to_be_bytes()].concat();
+    KeyPair::from_private_key(
+        KeyType::Ed25519Sha512,
+        &blake3::derive_key(MNEMONIC_CONTEXT, &key_material),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kerl::event_generator::is_non_transferable;
+    use keri::prefix::IdentifierPrefix;
+
+    #[test]
+    fn test_mnemonic() -> Result<(), Error> {
+        let phrase = Wallet::generate_mnemonic();
+        let mut first = Wallet::from_mnemonic(&phrase)?;
+        let mut second = Wallet::from_mnemonic(&phrase)?;
+        assert_eq!(first.public_key().0, second.public_key().0);
+        assert_eq!(first.next_public_key().0, second.next_public_key().0);
+
+        // Rotated keys are deterministic as well.
+        first.rotate()?;
+        second.rotate()?;
+        assert_eq!(first.next_public_key().0, second.next_public_key().0);
+        assert_ne!(first.public_key().0, first.next_public_key().0);
+
+        assert!(Wallet::from_mnemonic("not a valid phrase").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_multiple_keys() -> Result<(), Error> {
+        let mut wallet = Wallet::with_keys(3, SignatureThreshold::Simple(2))?;
+        assert_eq!(wallet.public_keys().len(), 3);
+        assert_eq!(wallet.next_public_keys().len(), 3);
+
+        let signatures = wallet.sign_indexed("message".as_bytes())?;
+        assert_eq!(
+            signatures.iter().map(|s| s.index).collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+
+        let next_keys: Vec<_> = wallet
+            .next_public_keys()
+            .iter()
+            .map(|k| k.derive_key_prefix())
+            .collect();
+        wallet.rotate()?;
+        let current_keys: Vec<_> = wallet
+            .public_keys()
+            .iter()
+            .map(|k| k.derive_key_prefix())
+            .collect();
+        assert_eq!(next_keys, current_keys);
+
+        // Threshold bigger than number of keys.
+        assert!(Wallet::with_keys(1, SignatureThreshold::Simple(2)).is_err());
+
+        // Prepared keys become next keys after rotation.
+        wallet.prepare_rotation()?;
+        let pending = wallet.pending_public_keys();
+        let pending: Vec<_> = pending.iter().map(|k| k.derive_key_prefix()).collect();
+        let next_signatures = wallet.sign_indexed_next(b"message")?;
+        wallet.prepare_rotation()?;
+        assert_eq!(
+            wallet
+                .pending_public_keys()
+                .iter()
+                .map(|k| k.derive_key_prefix())
+                .collect::<Vec<_>>(),
+            pending
+        );
+        wallet.rotate()?;
+        assert_eq!(wallet.sign_indexed(b"message")?, next_signatures);
+        let next_keys: Vec<_> = wallet
+            .next_public_keys()
+            .iter()
+            .map(|k| k.derive_key_prefix())
+            .collect();
+        assert_eq!(next_keys, pending);
+        assert!(!wallet.is_rotation_prepared());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_abandon_and_wipe() -> Result<(), Error> {
+        let mut wallet = Wallet::from_mnemonic(&Wallet::generate_mnemonic())?;
+        let next_key = wallet.next_public_key().0;
+        wallet.abandon()?;
+        assert_eq!(wallet.public_key().0, next_key);
+        assert!(wallet.next_public_keys().is_empty());
+        assert!(wallet.rotate().is_err());
+
+        wallet.wipe();
+        assert!(!wallet.has_keys());
+        assert!(wallet.sign(&b"message".to_vec()).is_err());
+        assert!(wallet.sign_indexed(b"message").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_types() -> Result<(), Error> {
+        use keri::{derivation::self_signing::SelfSigning, prefix::Prefix};
+
+        let mut wallet = Wallet::with_key_types(
+            &[KeyType::EcdsaSecp256k1Sha256, KeyType::Ed25519Sha512],
+            SignatureThreshold::Simple(2),
+        )?;
+        let prefixes: Vec<_> = wallet
+            .public_keys()
+            .iter()
+            .map(|k| k.derive_key_prefix().derivation_code())
+            .collect();
+        assert_eq!(prefixes, vec!["1AAB", "D"]);
+
+        let signatures = wallet.sign_indexed("message".as_bytes())?;
+        assert_eq!(
+            signatures[0].signature.derivation,
+            SelfSigning::ECDSAsecp256k1Sha256
+        );
+        assert_eq!(signatures[1].signature.derivation, SelfSigning::Ed25519Sha512);
+
+        // Key types are kept after rotation.
+        wallet.rotate()?;
+        assert_eq!(wallet.public_keys()[0].key_type(), KeyType::EcdsaSecp256k1Sha256);
+
+        // X25519 key can't sign.
+        assert!(
+            Wallet::with_key_types(&[KeyType::X25519], SignatureThreshold::Simple(1)).is_err()
+        );
+        // But it can be derived from ed25519 key.
+        let encryption_key = Wallet::new()?.encryption_key()?.derive_key_prefix();
+        assert_eq!(encryption_key.derivation_code(), "C");
+        // X25519 prefix isn't a non-transferable identifier.
+        assert!(!is_non_transferable(&IdentifierPrefix::Basic(
+            encryption_key
+        )));
+
+        Ok(())
+    }
+}